@@ -0,0 +1,55 @@
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+
+extern crate simple_server;
+
+use simple_server::{Body, Server, StatusCode};
+use std::path::PathBuf;
+
+// Only plain file names are accepted, so that a request can't reach outside
+// the exports directory.
+fn export_path(name: &str) -> Option<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if valid {
+        Some(PathBuf::from("exports").join(name))
+    } else {
+        None
+    }
+}
+
+fn main() {
+    let host = "127.0.0.1";
+    let port = "7878";
+
+    let server = Server::new(|request, mut response| {
+        info!("Request received. {} {}", request.method(), request.uri());
+
+        // `/download?file=report.csv` sends `exports/report.csv`, straight
+        // from disk.
+        let name = request
+            .uri()
+            .query()
+            .unwrap_or("")
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("file="));
+
+        match name.and_then(export_path) {
+            Some(path) if request.uri().path() == "/download" => {
+                response.header("Content-Type", "application/octet-stream");
+                Ok(response.body(Body::File(path))?)
+            }
+            _ => {
+                response.status(StatusCode::NOT_FOUND);
+                Ok(response.body(Body::Static(b"<h1>404</h1><p>Not found!<p>"))?)
+            }
+        }
+    });
+
+    server.listen(host, port);
+}
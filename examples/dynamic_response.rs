@@ -13,12 +13,12 @@ fn main() {
     let server = Server::new(|request, mut response| {
         info!("Request received. {} {}", request.method(), request.uri());
 
-        match request.method() {
-            &Method::GET => {
+        match *request.method() {
+            Method::GET => {
                 let body = format!("The path you requested was '{}'", request.uri().path());
                 Ok(response.body(body.into_bytes())?)
             }
-            &Method::POST => {
+            Method::POST => {
                 let data = String::from_utf8_lossy(request.body()).into_owned();
                 let body = format!("The data you posted was '{}'", data);
                 Ok(response.body(body.into_bytes())?)
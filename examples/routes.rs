@@ -10,17 +10,12 @@ fn main() {
     let host = "127.0.0.1";
     let port = "7878";
 
-    let server = Server::new(|request, mut response| {
+    let server = Server::new_with_responder(|request| {
         info!("Request received. {} {}", request.method(), request.uri());
 
         match (request.method(), request.uri().path()) {
-            (&Method::GET, "/hello") => {
-                Ok(response.body("<h1>Hi!</h1><p>Hello Rust!</p>".as_bytes().to_vec())?)
-            }
-            (_, _) => {
-                response.status(StatusCode::NOT_FOUND);
-                Ok(response.body("<h1>404</h1><p>Not found!<p>".as_bytes().to_vec())?)
-            }
+            (&Method::GET, "/hello") => (StatusCode::OK, "Hello Rust!"),
+            (_, _) => (StatusCode::NOT_FOUND, "Not found!"),
         }
     });
 
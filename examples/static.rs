@@ -0,0 +1,7 @@
+extern crate simple_server;
+
+use simple_server::Server;
+
+fn main() {
+    Server::static_files("examples/public").listen("127.0.0.1", "7878");
+}
@@ -0,0 +1,46 @@
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+
+extern crate simple_server;
+
+use simple_server::{with_thread_state, Server};
+use std::thread;
+
+// Stands in for something each thread needs its own of, like a database
+// connection.
+struct Counter {
+    served: u64,
+}
+
+impl Counter {
+    fn open() -> Counter {
+        info!("Opening a counter on {:?}", thread::current().name());
+        Counter { served: 0 }
+    }
+}
+
+fn main() {
+    let host = "127.0.0.1";
+    let port = "7878";
+
+    let mut server = Server::new_with_responder(|request| {
+        info!("Request received. {} {}", request.method(), request.uri());
+
+        let served = with_thread_state(|counter: &mut Counter| {
+            counter.served += 1;
+            counter.served
+        })
+        .unwrap_or(0);
+
+        format!(
+            "{} has answered {} request(s)",
+            thread::current().name().unwrap_or("this thread"),
+            served
+        )
+    });
+
+    server.set_thread_state(Counter::open);
+
+    server.listen(host, port);
+}
@@ -0,0 +1,227 @@
+use std::fs::File;
+use std::io;
+use std::net::TcpListener;
+use std::time::Duration;
+
+// How long a non-blocking listener waits before checking for a connection
+// again.
+const POLL_MS: u64 = 10;
+
+// How long to wait before trying again once there's nothing left to accept a
+// connection with. Retrying straight away would spin, as nothing frees up
+// until some other connection closes.
+const BACKOFF_MS: u64 = 50;
+
+#[cfg(unix)]
+const NULL_DEVICE: &str = "/dev/null";
+#[cfg(windows)]
+const NULL_DEVICE: &str = "nul";
+
+/// Why accepting a connection failed, which decides what the listener does
+/// next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// A non-blocking listener had no connection waiting.
+    WouldBlock,
+    /// That one connection went away before it could be accepted, or the
+    /// call was interrupted. The next one is unaffected.
+    Transient,
+    /// The process or the system ran out of file descriptors, or of memory
+    /// for another socket.
+    Exhausted,
+    /// Anything else, such as a listener that's been shut down.
+    Other,
+}
+
+impl Failure {
+    /// Works out what kind of failure an error from `accept` is.
+    pub fn classify(error: &io::Error) -> Failure {
+        match error.kind() {
+            io::ErrorKind::WouldBlock => return Failure::WouldBlock,
+            io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted => return Failure::Transient,
+            io::ErrorKind::OutOfMemory => return Failure::Exhausted,
+            _ => {}
+        }
+
+        match error.raw_os_error() {
+            Some(code) if is_exhausted(code) => Failure::Exhausted,
+            Some(code) if is_transient(code) => Failure::Transient,
+            _ => Failure::Other,
+        }
+    }
+
+    /// How long to wait before accepting again.
+    pub fn delay(self) -> Duration {
+        match self {
+            Failure::WouldBlock => Duration::from_millis(POLL_MS),
+            Failure::Transient => Duration::from_millis(0),
+            Failure::Exhausted | Failure::Other => Duration::from_millis(BACKOFF_MS),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_exhausted(code: i32) -> bool {
+    code == errno::EMFILE || code == errno::ENFILE || errno::EXHAUSTED.contains(&code)
+}
+
+// Linux reports network errors that are already pending on the new socket
+// from `accept`, which are about that connection alone.
+#[cfg(unix)]
+fn is_transient(code: i32) -> bool {
+    errno::TRANSIENT.contains(&code)
+}
+
+// Error numbers differ between systems, and the standard library only gives
+// some of them an `ErrorKind`, so the rest are spelled out here.
+#[cfg(unix)]
+mod errno {
+    // These two have been the same on every Unix since the first one.
+    pub const ENFILE: i32 = 23;
+    pub const EMFILE: i32 = 24;
+
+    // The rest are as Linux numbers them, on all but a few architectures:
+    // ENOBUFS, and then EPROTO, ENETDOWN, ENETUNREACH and EHOSTUNREACH.
+    #[cfg(all(
+        target_os = "linux",
+        not(any(
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "sparc",
+            target_arch = "sparc64"
+        ))
+    ))]
+    mod numbers {
+        pub const EXHAUSTED: &[i32] = &[105];
+        pub const TRANSIENT: &[i32] = &[71, 100, 101, 113];
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        not(any(
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "sparc",
+            target_arch = "sparc64"
+        ))
+    )))]
+    mod numbers {
+        pub const EXHAUSTED: &[i32] = &[];
+        pub const TRANSIENT: &[i32] = &[];
+    }
+
+    pub use self::numbers::{EXHAUSTED, TRANSIENT};
+}
+
+// WSAEMFILE and WSAENOBUFS.
+#[cfg(windows)]
+fn is_exhausted(code: i32) -> bool {
+    code == 10024 || code == 10055
+}
+
+#[cfg(windows)]
+fn is_transient(_code: i32) -> bool {
+    false
+}
+
+/// A file descriptor held back for when the process runs out.
+///
+/// Without one, a connection that can't be accepted sits in the backlog
+/// until the client gives up. Freeing the spare for a moment is enough to
+/// accept the connection and close it straight away, so the client hears
+/// about it.
+pub struct Reserve(Option<File>);
+
+impl Reserve {
+    pub fn new() -> Reserve {
+        Reserve(File::open(NULL_DEVICE).ok())
+    }
+
+    /// Turns away one waiting connection, returning whether there was one.
+    pub fn shed(&mut self, listener: &TcpListener) -> bool {
+        if self.0.take().is_none() {
+            self.0 = File::open(NULL_DEVICE).ok();
+            return false;
+        }
+
+        let shed = listener.accept().is_ok();
+        self.0 = File::open(NULL_DEVICE).ok();
+        shed
+    }
+}
+
+#[cfg(test)]
+mod accept_should {
+    use super::*;
+
+    #[test]
+    fn back_off_when_out_of_descriptors() {
+        #[cfg(unix)]
+        let codes = [libc::EMFILE, libc::ENFILE, libc::ENOBUFS, libc::ENOMEM];
+        #[cfg(windows)]
+        let codes = [10024, 10055];
+
+        for &code in &codes {
+            let failure = Failure::classify(&io::Error::from_raw_os_error(code));
+            assert_eq!(Failure::Exhausted, failure, "{}", code);
+            assert!(failure.delay() >= Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn carry_on_after_a_connection_goes_away() {
+        for &kind in &[
+            io::ErrorKind::ConnectionAborted,
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::Interrupted,
+        ] {
+            let failure = Failure::classify(&io::Error::from(kind));
+            assert_eq!(Failure::Transient, failure, "{:?}", kind);
+            assert_eq!(Duration::from_millis(0), failure.delay());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn carry_on_after_a_network_error_on_the_new_socket() {
+        for &code in &[
+            libc::EPROTO,
+            libc::ENETDOWN,
+            libc::ENETUNREACH,
+            libc::EHOSTUNREACH,
+        ] {
+            let failure = Failure::classify(&io::Error::from_raw_os_error(code));
+            assert_eq!(Failure::Transient, failure, "{}", code);
+        }
+    }
+
+    #[test]
+    fn poll_non_blocking_listeners() {
+        let failure = Failure::classify(&io::Error::from(io::ErrorKind::WouldBlock));
+
+        assert_eq!(Failure::WouldBlock, failure);
+        assert_eq!(Duration::from_millis(POLL_MS), failure.delay());
+    }
+
+    #[test]
+    fn pause_after_anything_else() {
+        let failure = Failure::classify(&io::Error::from(io::ErrorKind::InvalidInput));
+
+        assert_eq!(Failure::Other, failure);
+        assert!(failure.delay() > Duration::from_millis(0));
+    }
+
+    #[test]
+    fn turn_away_a_waiting_connection() {
+        use std::net::TcpStream;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut reserve = Reserve::new();
+
+        assert!(reserve.shed(&listener));
+        assert!(reserve.0.is_some());
+    }
+}
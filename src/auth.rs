@@ -0,0 +1,218 @@
+use http::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use http::{Request, Response, StatusCode};
+
+/// The ways an `Authorization` header can fail to carry a bearer token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BearerError {
+    /// There's no `Authorization` header.
+    Missing,
+    /// There's more than one `Authorization` header.
+    Multiple,
+    /// The header uses some other scheme, like `Basic`.
+    WrongScheme,
+    /// The scheme is `Bearer`, but no token follows it.
+    Empty,
+    /// The token has characters that aren't allowed in one.
+    Malformed,
+}
+
+impl BearerError {
+    /// Whether the client should be asked to authenticate, rather than told
+    /// that its request was malformed.
+    ///
+    /// A client that didn't try to send a bearer token gets a `401`, and one
+    /// that sent a broken one gets a `400`, as RFC 6750 suggests.
+    pub fn is_unauthorized(self) -> bool {
+        match self {
+            BearerError::Missing | BearerError::WrongScheme => true,
+            BearerError::Multiple | BearerError::Empty | BearerError::Malformed => false,
+        }
+    }
+}
+
+/// Pulls the token out of a request's `Authorization: Bearer <token>` header.
+///
+/// The scheme is matched without regard to case, and whitespace around the
+/// token is ignored.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{bearer_token, Server, StatusCode};
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         match bearer_token(&request) {
+///             Ok(token) if token == "let-me-in" => {
+///                 Ok(response.body("Welcome!".as_bytes().to_vec())?)
+///             }
+///             _ => {
+///                 response.status(StatusCode::UNAUTHORIZED);
+///                 Ok(response.body("Go away!".as_bytes().to_vec())?)
+///             }
+///         }
+///     });
+/// }
+/// ```
+pub fn bearer_token<B>(request: &Request<B>) -> Result<&str, BearerError> {
+    let mut values = request.headers().get_all(AUTHORIZATION).iter();
+    let value = values.next().ok_or(BearerError::Missing)?;
+    if values.next().is_some() {
+        return Err(BearerError::Multiple);
+    }
+
+    let value = value.to_str().map_err(|_| BearerError::Malformed)?.trim();
+    let (scheme, token) = match value.find([' ', '\t']) {
+        Some(space) => (&value[..space], value[space..].trim()),
+        None => (value, ""),
+    };
+
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return Err(BearerError::WrongScheme);
+    }
+    if token.is_empty() {
+        return Err(BearerError::Empty);
+    }
+    if !is_token68(token) {
+        return Err(BearerError::Malformed);
+    }
+
+    Ok(token)
+}
+
+// Bearer tokens are made up of letters, digits and `-._~+/`, followed by
+// any amount of `=` padding.
+fn is_token68(token: &str) -> bool {
+    let body = token.trim_end_matches('=');
+    !body.is_empty()
+        && body
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~+/".contains(&b))
+}
+
+/// Checks a request's bearer token with `validate`, returning the response to
+/// send instead if there's no usable token or `validate` rejects it.
+///
+/// That's a `401 Unauthorized` with a `WWW-Authenticate: Bearer` challenge
+/// when the token is missing or rejected, or a `400 Bad Request` when the
+/// header is malformed.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{require_bearer_token, Server};
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         if let Some(denied) = require_bearer_token(&request, |token| token == "let-me-in") {
+///             return Ok(denied);
+///         }
+///
+///         Ok(response.body("Welcome!".as_bytes().to_vec())?)
+///     });
+/// }
+/// ```
+pub fn require_bearer_token<B, F>(request: &Request<B>, validate: F) -> Option<Response<Vec<u8>>>
+where
+    F: FnOnce(&str) -> bool,
+{
+    match bearer_token(request) {
+        Ok(token) if validate(token) => None,
+        Ok(_) => Some(denied(
+            StatusCode::UNAUTHORIZED,
+            "Bearer error=\"invalid_token\"",
+        )),
+        Err(e) if e.is_unauthorized() => Some(denied(StatusCode::UNAUTHORIZED, "Bearer")),
+        Err(_) => Some(denied(
+            StatusCode::BAD_REQUEST,
+            "Bearer error=\"invalid_request\"",
+        )),
+    }
+}
+
+fn denied(status: StatusCode, challenge: &'static str) -> Response<Vec<u8>> {
+    let body = format!("<h1>{}</h1><p>Authentication required!<p>", status.as_u16());
+    let mut response = Response::new(body.into_bytes());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    response
+        .headers_mut()
+        .insert(WWW_AUTHENTICATE, HeaderValue::from_static(challenge));
+    response
+}
+
+#[cfg(test)]
+mod auth_should {
+    use super::*;
+
+    fn request(values: &[&str]) -> Request<()> {
+        let mut request = Request::get("/");
+        for value in values {
+            request.header(AUTHORIZATION, *value);
+        }
+        request.body(()).unwrap()
+    }
+
+    fn token(values: &[&str]) -> Result<String, BearerError> {
+        bearer_token(&request(values)).map(str::to_string)
+    }
+
+    #[test]
+    fn extract_the_token() {
+        assert_eq!(
+            Ok("abc.DEF-123".to_string()),
+            token(&["Bearer abc.DEF-123"])
+        );
+        assert_eq!(Ok("abc".to_string()), token(&["bEaReR abc"]));
+        assert_eq!(Ok("abc==".to_string()), token(&["  Bearer \t abc==  "]));
+        assert_eq!(Ok("a/b+c~".to_string()), token(&["BEARER a/b+c~"]));
+    }
+
+    #[test]
+    fn tell_the_failures_apart() {
+        assert_eq!(Err(BearerError::Missing), token(&[]));
+        assert_eq!(Err(BearerError::Multiple), token(&["Bearer a", "Bearer b"]));
+        assert_eq!(
+            Err(BearerError::WrongScheme),
+            token(&["Basic dXNlcjpwYXNz"])
+        );
+        assert_eq!(Err(BearerError::WrongScheme), token(&["Bearerabc"]));
+        assert_eq!(Err(BearerError::Empty), token(&["Bearer"]));
+        assert_eq!(Err(BearerError::Empty), token(&["Bearer   "]));
+        assert_eq!(Err(BearerError::Malformed), token(&["Bearer a b"]));
+        assert_eq!(Err(BearerError::Malformed), token(&["Bearer ==="]));
+        assert_eq!(Err(BearerError::Malformed), token(&["Bearer a=b"]));
+    }
+
+    #[test]
+    fn let_valid_tokens_through() {
+        assert!(require_bearer_token(&request(&["Bearer good"]), |t| t == "good").is_none());
+    }
+
+    #[test]
+    fn challenge_missing_and_rejected_tokens() {
+        let missing = require_bearer_token(&request(&[]), |_| true).unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, missing.status());
+        assert_eq!("Bearer", missing.headers()[WWW_AUTHENTICATE]);
+
+        let rejected = require_bearer_token(&request(&["Bearer bad"]), |t| t == "good").unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, rejected.status());
+        assert_eq!(
+            "Bearer error=\"invalid_token\"",
+            rejected.headers()[WWW_AUTHENTICATE]
+        );
+    }
+
+    #[test]
+    fn reject_malformed_headers_with_400() {
+        let response = require_bearer_token(&request(&["Bearer a b"]), |_| true).unwrap();
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+/// The body of a response made by a handler.
+///
+/// Handlers can return a `Response` of anything that converts into a `Body`,
+/// so those that build their body as a `Vec<u8>` don't need to know about it.
+/// Returning a `Body::File` sends a file without reading it into memory
+/// first: it's streamed the way static files are, with its length taken from
+/// its metadata, and `Range` requests for it are honoured. If the file
+/// doesn't exist, the client gets a `404`, and if it can't be opened for any
+/// other reason, a `500`.
+///
+/// The path of a `Body::File` is used as it is, so it's up to the handler to
+/// make sure a client can't talk it into sending something it shouldn't.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{Body, Server};
+/// use std::path::PathBuf;
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         let body = match request.uri().path() {
+///             "/robots.txt" => Body::Static(b"User-agent: *\nDisallow:\n"),
+///             "/export" => Body::File(PathBuf::from("exports/latest.csv")),
+///             _ => Body::Bytes("Hello, world!".as_bytes().to_vec()),
+///         };
+///
+///         Ok(response.body(body)?)
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    /// Bytes made by the handler.
+    Bytes(Vec<u8>),
+    /// Bytes that live for as long as the program does, which are sent
+    /// without being copied, unless they need compressing or cutting down to
+    /// a range.
+    Static(&'static [u8]),
+    /// A file to be streamed from disk.
+    File(PathBuf),
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Body {
+        Body::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&'static [u8]> for Body {
+    fn from(bytes: &'static [u8]) -> Body {
+        Body::Static(bytes)
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(text: &'static str) -> Body {
+        Body::Static(text.as_bytes())
+    }
+}
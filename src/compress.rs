@@ -0,0 +1,232 @@
+use http::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use http::Response;
+use std::io::{self, Write};
+
+// The content codings the server can produce, most preferred first. Ties in
+// the client's preferences go to whichever comes first here.
+const CODINGS: &[&str] = &["br"];
+
+/// Picks the content coding to compress a response with, based on the
+/// request's `Accept-Encoding` headers.
+///
+/// Codings with a higher `q` value win, and ones with `q=0` are never used.
+/// `*` stands in for any coding that isn't mentioned by name.
+pub fn negotiate(headers: &HeaderMap<HeaderValue>) -> Option<&'static str> {
+    let mut best: Option<(&'static str, u16)> = None;
+
+    for &coding in CODINGS {
+        let q = match quality(headers, coding) {
+            Some(q) if q > 0 => q,
+            _ => continue,
+        };
+        let better = match best {
+            Some((_, best_q)) => q > best_q,
+            None => true,
+        };
+        if better {
+            best = Some((coding, q));
+        }
+    }
+
+    best.map(|(coding, _)| coding)
+}
+
+// The `q` value the client gave `coding`, in thousandths, or the one it gave
+// `*` if `coding` isn't listed.
+fn quality(headers: &HeaderMap<HeaderValue>, coding: &str) -> Option<u16> {
+    let mut wildcard = None;
+
+    for value in headers.get_all(ACCEPT_ENCODING) {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        for item in value.split(',') {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or("").trim();
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .map(parse_quality)
+                .next()
+                .unwrap_or(Some(1000));
+
+            if name.eq_ignore_ascii_case(coding) {
+                return q;
+            }
+            if name == "*" {
+                wildcard = q;
+            }
+        }
+    }
+
+    wildcard
+}
+
+// Parses a qvalue, which has at most three decimal places, into thousandths.
+fn parse_quality(q: &str) -> Option<u16> {
+    let (whole, fraction) = match q.trim().split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (q.trim(), ""),
+    };
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let thousandths = format!("{:0<3}", fraction).parse::<u16>().ok()?;
+    match whole {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// Whether responses of the given content type are worth compressing. Most
+/// images, audio, video and archives are compressed already.
+pub fn is_compressible(content_type: &HeaderValue) -> bool {
+    let content_type = content_type.to_str().unwrap_or("");
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || [
+            "application/javascript",
+            "application/json",
+            "application/wasm",
+            "application/xml",
+        ]
+        .contains(&essence.as_str())
+}
+
+/// Compresses the body of `response` with `coding`, if it's compressible and
+/// at least `threshold` bytes long, adjusting its headers to match.
+///
+/// Responses that already have a `Content-Encoding` are left alone.
+pub fn compress(
+    response: Response<Vec<u8>>,
+    coding: Option<&'static str>,
+    threshold: usize,
+) -> io::Result<Response<Vec<u8>>> {
+    let (mut parts, body) = response.into_parts();
+
+    let eligible = body.len() >= threshold
+        && !parts.headers.contains_key(CONTENT_ENCODING)
+        && parts.headers.get(CONTENT_TYPE).is_some_and(is_compressible);
+    if !eligible {
+        return Ok(Response::from_parts(parts, body));
+    }
+
+    // Whether or not this client gets it compressed, the next one might.
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    let body = match coding {
+        Some("br") => {
+            let mut compressed = Vec::with_capacity(body.len() / 2);
+            {
+                let mut writer = ::brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+                writer.write_all(&body)?;
+            }
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
+            parts.headers.remove(CONTENT_LENGTH);
+            compressed
+        }
+        _ => body,
+    };
+
+    Ok(Response::from_parts(parts, body))
+}
+
+#[cfg(test)]
+mod compress_should {
+    use super::*;
+    use std::io::Read;
+
+    fn accept(values: &[&str]) -> HeaderMap<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(ACCEPT_ENCODING, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn html(len: usize) -> Response<Vec<u8>> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(b"<p>hello</p>".iter().cycle().take(len).cloned().collect())
+            .unwrap()
+    }
+
+    #[test]
+    fn negotiate_brotli() {
+        assert_eq!(Some("br"), negotiate(&accept(&["gzip, deflate, br"])));
+        assert_eq!(Some("br"), negotiate(&accept(&["gzip", "br;q=0.5"])));
+        assert_eq!(Some("br"), negotiate(&accept(&["*"])));
+        assert_eq!(Some("br"), negotiate(&accept(&["BR;Q=1.0"])));
+    }
+
+    #[test]
+    fn respect_refusals() {
+        assert_eq!(None, negotiate(&accept(&[])));
+        assert_eq!(None, negotiate(&accept(&["gzip"])));
+        assert_eq!(None, negotiate(&accept(&["br;q=0, gzip"])));
+        assert_eq!(None, negotiate(&accept(&["*;q=0"])));
+        assert_eq!(None, negotiate(&accept(&["br;q=0.000", "*"])));
+        assert_eq!(None, negotiate(&accept(&["br;q=2"])));
+    }
+
+    #[test]
+    fn compress_big_text_responses() {
+        let response = compress(html(2000), Some("br"), 1024).unwrap();
+
+        assert_eq!("br", response.headers()[CONTENT_ENCODING]);
+        assert_eq!("accept-encoding", response.headers()[VARY]);
+
+        let mut body = vec![];
+        ::brotli::Decompressor::new(&response.body()[..], 4096)
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(html(2000).body(), &body);
+    }
+
+    #[test]
+    fn only_vary_for_clients_without_brotli() {
+        let response = compress(html(2000), None, 1024).unwrap();
+
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+        assert_eq!("accept-encoding", response.headers()[VARY]);
+        assert_eq!(html(2000).body(), response.body());
+    }
+
+    #[test]
+    fn leave_small_binary_and_encoded_responses_alone() {
+        let small = compress(html(100), Some("br"), 1024).unwrap();
+        assert!(!small.headers().contains_key(CONTENT_ENCODING));
+
+        let mut png = html(2000);
+        png.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        let png = compress(png, Some("br"), 1024).unwrap();
+        assert!(!png.headers().contains_key(CONTENT_ENCODING));
+
+        let mut gzipped = html(2000);
+        gzipped
+            .headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let gzipped = compress(gzipped, Some("br"), 1024).unwrap();
+        assert_eq!("gzip", gzipped.headers()[CONTENT_ENCODING]);
+        assert_eq!(html(2000).body(), gzipped.body());
+        assert!(!gzipped.headers().contains_key(VARY));
+    }
+}
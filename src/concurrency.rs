@@ -0,0 +1,20 @@
+/// How a server spreads connections over threads.
+///
+/// It's set with `Server::set_concurrency_model`. Either way, connections
+/// are handled the same, and a panicking handler only takes down its own
+/// connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcurrencyModel {
+    /// A fixed pool of `size` threads, fed from a queue of accepted
+    /// connections. Once every thread is busy and the queue is full,
+    /// connections are answered with a `503 Service Unavailable`. See
+    /// `Server::set_queue_depth`.
+    Pool { size: usize },
+
+    /// A new thread for every connection, so that a slow handler never holds
+    /// up anyone else's. That's simple and fine for a server that doesn't see
+    /// much traffic, but every connection costs a thread. Once `max_threads`
+    /// are running, if it's set, connections are answered with a
+    /// `503 Service Unavailable`.
+    ThreadPerConnection { max_threads: Option<usize> },
+}
@@ -0,0 +1,290 @@
+use date;
+use http::header::{
+    HeaderMap, HeaderValue, CONTENT_TYPE, IF_MATCH, IF_NONE_MATCH, IF_UNMODIFIED_SINCE,
+};
+use http::{Request, Response, StatusCode};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Evaluates the `If-Match` and `If-Unmodified-Since` preconditions of a
+/// request against the current state of the resource it would change.
+///
+/// `etag` is the resource's current entity tag, quotes included, like
+/// `"v42"` or `W/"v42"`, and `last_modified` is when it last changed. Pass
+/// `None` for both if the resource doesn't exist yet.
+///
+/// The preconditions are evaluated in the order RFC 7232 gives: when there's
+/// an `If-Match`, it decides on its own, matching `*` against any existing
+/// resource, and only ever matching strong entity tags. Otherwise, a valid
+/// `If-Unmodified-Since` fails if the resource changed after the given date.
+/// If a precondition fails, the `412 Precondition Failed` response to send is
+/// returned.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{check_preconditions, Method, Server};
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         if request.method() == Method::PUT {
+///             let current = "\"v42\"";
+///             if let Some(failed) = check_preconditions(&request, Some(current), None) {
+///                 return Ok(failed);
+///             }
+///
+///             // ... save the new version ...
+///         }
+///
+///         Ok(response.body("Saved!".as_bytes().to_vec())?)
+///     });
+/// }
+/// ```
+pub fn check_preconditions<B>(
+    request: &Request<B>,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> Option<Response<Vec<u8>>> {
+    let passed = match if_match(request.headers(), etag, last_modified.is_some()) {
+        Some(passed) => passed,
+        None => if_unmodified_since(request.headers(), last_modified),
+    };
+
+    if passed {
+        None
+    } else {
+        Some(precondition_failed())
+    }
+}
+
+/// Whether the request's `If-None-Match` says the client already has the
+/// representation tagged `etag`, so that a `304 Not Modified` will do.
+///
+/// Entity tags are compared weakly here, as RFC 7232 asks for this header.
+pub fn is_not_modified<B>(request: &Request<B>, etag: &str) -> bool {
+    request
+        .headers()
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(split_entity_tags)
+        .any(|tag| tag == "*" || weak_match(tag, etag))
+}
+
+// `None` if there's no `If-Match` header.
+fn if_match(headers: &HeaderMap<HeaderValue>, etag: Option<&str>, exists: bool) -> Option<bool> {
+    let mut values = headers.get_all(IF_MATCH).iter().peekable();
+    values.peek()?;
+
+    let mut passed = false;
+    for value in values {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        for tag in split_entity_tags(value) {
+            if tag == "*" {
+                passed |= exists || etag.is_some();
+            } else if let Some(etag) = etag {
+                passed |= strong_match(tag, etag);
+            }
+        }
+    }
+    Some(passed)
+}
+
+fn if_unmodified_since(
+    headers: &HeaderMap<HeaderValue>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    let since = headers
+        .get(IF_UNMODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(date::parse_imf_fixdate);
+
+    match (since, last_modified) {
+        (Some(since), Some(last_modified)) => {
+            let last_modified = last_modified
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0);
+            last_modified <= since
+        }
+        // An invalid date is ignored, as is one for a resource that doesn't
+        // keep track of when it changed.
+        _ => true,
+    }
+}
+
+// Splits a list of entity tags on the commas between them, which may also
+// appear inside the quotes of a tag.
+fn split_entity_tags(value: &str) -> Vec<&str> {
+    let mut tags = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                tags.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    tags.push(value[start..].trim());
+
+    tags.retain(|tag| !tag.is_empty());
+    tags
+}
+
+// Two entity tags match strongly if neither is weak and they're the same.
+fn strong_match(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+// Two entity tags match weakly if they're the same once any `W/` is dropped.
+fn weak_match(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+fn precondition_failed() -> Response<Vec<u8>> {
+    let mut response = Response::new(b"<h1>412</h1><p>Precondition failed!<p>".to_vec());
+    *response.status_mut() = StatusCode::PRECONDITION_FAILED;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    response
+}
+
+#[cfg(test)]
+mod conditional_should {
+    use super::*;
+    use std::time::Duration;
+
+    // Sun, 06 Nov 1994 08:49:37 GMT
+    const MODIFIED: u64 = 784_111_777;
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::put("/doc");
+        for &(name, value) in headers {
+            request.header(name, value);
+        }
+        request.body(()).unwrap()
+    }
+
+    fn modified() -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(MODIFIED))
+    }
+
+    fn passes(
+        headers: &[(&str, &str)],
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> bool {
+        check_preconditions(&request(headers), etag, last_modified).is_none()
+    }
+
+    #[test]
+    fn pass_without_preconditions() {
+        assert!(passes(&[], Some("\"v1\""), modified()));
+        assert!(passes(&[], None, None));
+    }
+
+    #[test]
+    fn match_strong_entity_tags() {
+        assert!(passes(&[("if-match", "\"v1\"")], Some("\"v1\""), None));
+        assert!(passes(
+            &[("if-match", "\"v0\", \"v1\"")],
+            Some("\"v1\""),
+            None
+        ));
+        assert!(passes(
+            &[("if-match", "\"v0\""), ("if-match", "\"v1\"")],
+            Some("\"v1\""),
+            None
+        ));
+        assert!(passes(&[("if-match", "\"a,b\"")], Some("\"a,b\""), None));
+        assert!(!passes(&[("if-match", "\"v0\"")], Some("\"v1\""), None));
+    }
+
+    #[test]
+    fn never_match_weak_entity_tags() {
+        assert!(!passes(&[("if-match", "W/\"v1\"")], Some("W/\"v1\""), None));
+        assert!(!passes(&[("if-match", "W/\"v1\"")], Some("\"v1\""), None));
+        assert!(!passes(&[("if-match", "\"v1\"")], Some("W/\"v1\""), None));
+    }
+
+    #[test]
+    fn match_any_existing_resource_with_a_star() {
+        assert!(passes(&[("if-match", "*")], Some("W/\"v1\""), None));
+        assert!(passes(&[("if-match", "*")], None, modified()));
+        assert!(!passes(&[("if-match", "*")], None, None));
+    }
+
+    #[test]
+    fn check_the_modification_date() {
+        let at = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let before = "Sun, 06 Nov 1994 08:49:36 GMT";
+
+        assert!(passes(&[("if-unmodified-since", at)], None, modified()));
+        assert!(!passes(
+            &[("if-unmodified-since", before)],
+            None,
+            modified()
+        ));
+    }
+
+    #[test]
+    fn ignore_unusable_dates() {
+        let before = "Sun, 06 Nov 1994 08:49:36 GMT";
+
+        assert!(passes(
+            &[("if-unmodified-since", "yesterday")],
+            None,
+            modified()
+        ));
+        assert!(passes(
+            &[("if-unmodified-since", before)],
+            Some("\"v1\""),
+            None
+        ));
+    }
+
+    #[test]
+    fn let_if_match_decide_over_the_date() {
+        let before = "Sun, 06 Nov 1994 08:49:36 GMT";
+        let headers = [("if-match", "\"v1\""), ("if-unmodified-since", before)];
+
+        assert!(passes(&headers, Some("\"v1\""), modified()));
+    }
+
+    #[test]
+    fn respond_with_412() {
+        let response =
+            check_preconditions(&request(&[("if-match", "\"v0\"")]), Some("\"v1\""), None).unwrap();
+
+        assert_eq!(StatusCode::PRECONDITION_FAILED, response.status());
+    }
+
+    #[test]
+    fn match_if_none_match_weakly() {
+        let not_modified = |value: &str, etag: &str| {
+            let request = Request::get("/")
+                .header("if-none-match", value)
+                .body(())
+                .unwrap();
+            is_not_modified(&request, etag)
+        };
+
+        assert!(not_modified("\"v1\"", "\"v1\""));
+        assert!(not_modified("W/\"v1\"", "\"v1\""));
+        assert!(not_modified("\"v0\", W/\"v1\"", "W/\"v1\""));
+        assert!(not_modified("*", "\"v1\""));
+        assert!(!not_modified("\"v0\"", "\"v1\""));
+        assert!(!is_not_modified(&request(&[]), "\"v1\""));
+    }
+}
@@ -0,0 +1,273 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use error::Error;
+
+/// Settings for a server, for setting it up with `Server::from_config`.
+///
+/// Every setting is optional, and those left as `None` keep the server's
+/// default. Each one does what the `Server` method of the same name does.
+///
+/// The same binary can be set up differently wherever it runs by reading the
+/// settings from the environment with `ServerConfig::from_env`.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate simple_server;
+///
+/// use simple_server::{Server, ServerConfig};
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let config = ServerConfig {
+///         pool_size: Some(8),
+///         request_timeout: Some(Duration::from_secs(5)),
+///         ..ServerConfig::default()
+///     };
+///
+///     let server = Server::from_config(config, |request, mut response| {
+///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+///     });
+///
+///     server.listen("127.0.0.1", "7979");
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// How many threads handle connections, from `{PREFIX}_POOL_SIZE`.
+    pub pool_size: Option<usize>,
+    /// How many connections can wait for a thread, from
+    /// `{PREFIX}_QUEUE_DEPTH`.
+    pub queue_depth: Option<usize>,
+    /// How long a client gets to send each request, from
+    /// `{PREFIX}_REQUEST_TIMEOUT_MS`.
+    pub request_timeout: Option<Duration>,
+    /// How long a kept-alive connection can sit idle, from
+    /// `{PREFIX}_IDLE_TIMEOUT_MS`.
+    pub idle_timeout: Option<Duration>,
+    /// How many requests a connection is kept alive for, from
+    /// `{PREFIX}_MAX_REQUESTS_PER_CONNECTION`.
+    pub max_requests_per_connection: Option<usize>,
+    /// How long a request line can be, from
+    /// `{PREFIX}_MAX_REQUEST_LINE_LENGTH`.
+    pub max_request_line_length: Option<usize>,
+    /// How many connections the operating system holds on to before they're
+    /// accepted, from `{PREFIX}_LISTEN_BACKLOG`.
+    pub listen_backlog: Option<i32>,
+    /// Where static files are served from, from `{PREFIX}_STATIC_DIR`.
+    pub static_dir: Option<PathBuf>,
+    /// Whether the server runs in development mode, from
+    /// `{PREFIX}_DEV_MODE`.
+    pub dev_mode: Option<bool>,
+    /// How long a request can take before it's logged as slow, from
+    /// `{PREFIX}_SLOW_REQUEST_THRESHOLD_MS`.
+    pub slow_request_threshold: Option<Duration>,
+}
+
+impl ServerConfig {
+    /// Reads the settings from environment variables whose names start with
+    /// `prefix` and an underscore, as in `MYAPP_POOL_SIZE`.
+    ///
+    /// Numbers are plain decimals, durations are whole milliseconds, and
+    /// switches are `true` or `false` (or `1` or `0`). Variables that aren't
+    /// set, or are set to nothing, leave their setting as `None`.
+    ///
+    /// # Errors
+    ///
+    /// This returns `Error::InvalidConfig`, naming the variable, for the first
+    /// one that's set to something that can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{Server, ServerConfig};
+    ///
+    /// fn main() {
+    ///     let config = ServerConfig::from_env("MYAPP").expect("Invalid configuration");
+    ///
+    ///     let server = Server::from_config(config, |request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn from_env(prefix: &str) -> Result<ServerConfig, Error> {
+        ServerConfig::from_vars(&Variables::from_env(prefix))
+    }
+
+    fn from_vars(vars: &Variables) -> Result<ServerConfig, Error> {
+        Ok(ServerConfig {
+            pool_size: vars.number("POOL_SIZE")?,
+            queue_depth: vars.number("QUEUE_DEPTH")?,
+            request_timeout: vars.millis("REQUEST_TIMEOUT_MS")?,
+            idle_timeout: vars.millis("IDLE_TIMEOUT_MS")?,
+            max_requests_per_connection: vars.number("MAX_REQUESTS_PER_CONNECTION")?,
+            max_request_line_length: vars.number("MAX_REQUEST_LINE_LENGTH")?,
+            listen_backlog: vars.number("LISTEN_BACKLOG")?,
+            static_dir: vars.get("STATIC_DIR").map(PathBuf::from),
+            dev_mode: vars.flag("DEV_MODE")?,
+            slow_request_threshold: vars.millis("SLOW_REQUEST_THRESHOLD_MS")?,
+        })
+    }
+}
+
+// Looks up a variable by its full name.
+type Lookup<'a> = dyn Fn(&str) -> Option<OsString> + 'a;
+
+/// Variables whose names share a prefix, and where to look them up.
+pub struct Variables<'a> {
+    prefix: &'a str,
+    lookup: Box<Lookup<'a>>,
+}
+
+impl<'a> Variables<'a> {
+    /// The environment variables starting with `prefix`.
+    pub fn from_env(prefix: &'a str) -> Variables<'a> {
+        Variables::new(prefix, |name: &str| env::var_os(name))
+    }
+
+    fn new<F: Fn(&str) -> Option<OsString> + 'a>(prefix: &'a str, lookup: F) -> Variables<'a> {
+        Variables {
+            prefix,
+            lookup: Box::new(lookup),
+        }
+    }
+
+    fn name(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", self.prefix, name)
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<OsString> {
+        (self.lookup)(&self.name(name)).filter(|value| !value.is_empty())
+    }
+
+    fn parse<T, F>(&self, name: &str, expected: &'static str, parse: F) -> Result<Option<T>, Error>
+    where
+        F: Fn(&str) -> Option<T>,
+    {
+        let value = match self.get(name) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        match value.to_str().and_then(|value| parse(value.trim())) {
+            Some(parsed) => Ok(Some(parsed)),
+            None => Err(Error::InvalidConfig {
+                variable: self.name(name),
+                value: value.to_string_lossy().into_owned(),
+                expected,
+            }),
+        }
+    }
+
+    /// Reads the variable `name` as a number.
+    pub fn number<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        self.parse(name, "a whole number", |value| value.parse().ok())
+    }
+
+    fn millis(&self, name: &str) -> Result<Option<Duration>, Error> {
+        self.parse(name, "a whole number of milliseconds", |value| {
+            value.parse().ok().map(Duration::from_millis)
+        })
+    }
+
+    fn flag(&self, name: &str) -> Result<Option<bool>, Error> {
+        self.parse(name, "true or false", |value| {
+            match &*value.to_ascii_lowercase() {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod config_should {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<ServerConfig, Error> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_string(), OsString::from(value)))
+            .collect();
+        let vars = Variables::new("MYAPP", |name: &str| vars.get(name).cloned());
+        ServerConfig::from_vars(&vars)
+    }
+
+    #[test]
+    fn read_every_setting() {
+        let config = config(&[
+            ("MYAPP_POOL_SIZE", "8"),
+            ("MYAPP_QUEUE_DEPTH", "256"),
+            ("MYAPP_REQUEST_TIMEOUT_MS", "5000"),
+            ("MYAPP_IDLE_TIMEOUT_MS", "30000"),
+            ("MYAPP_MAX_REQUESTS_PER_CONNECTION", "100"),
+            ("MYAPP_MAX_REQUEST_LINE_LENGTH", "4096"),
+            ("MYAPP_LISTEN_BACKLOG", "512"),
+            ("MYAPP_STATIC_DIR", "/var/www"),
+            ("MYAPP_DEV_MODE", "TRUE"),
+            ("MYAPP_SLOW_REQUEST_THRESHOLD_MS", " 250 "),
+            ("OTHERAPP_POOL_SIZE", "2"),
+        ]);
+
+        let expected = ServerConfig {
+            pool_size: Some(8),
+            queue_depth: Some(256),
+            request_timeout: Some(Duration::from_secs(5)),
+            idle_timeout: Some(Duration::from_secs(30)),
+            max_requests_per_connection: Some(100),
+            max_request_line_length: Some(4096),
+            listen_backlog: Some(512),
+            static_dir: Some(PathBuf::from("/var/www")),
+            dev_mode: Some(true),
+            slow_request_threshold: Some(Duration::from_millis(250)),
+        };
+        assert_eq!(expected, config.unwrap());
+    }
+
+    #[test]
+    fn leave_unset_and_empty_variables_alone() {
+        let config = config(&[("MYAPP_POOL_SIZE", ""), ("MYAPP_DEV_MODE", "0")]);
+
+        let expected = ServerConfig {
+            dev_mode: Some(false),
+            ..ServerConfig::default()
+        };
+        assert_eq!(expected, config.unwrap());
+    }
+
+    #[test]
+    fn name_the_variable_that_cant_be_read() {
+        for &(name, value) in &[
+            ("MYAPP_POOL_SIZE", "eight"),
+            ("MYAPP_REQUEST_TIMEOUT_MS", "5s"),
+            ("MYAPP_LISTEN_BACKLOG", "-"),
+            ("MYAPP_DEV_MODE", "maybe"),
+        ] {
+            match config(&[(name, value)]) {
+                Err(Error::InvalidConfig {
+                    variable,
+                    value: got,
+                    ..
+                }) => {
+                    assert_eq!(name, variable);
+                    assert_eq!(value, got);
+                }
+                other => panic!("Expected InvalidConfig for {}. Got {:?}", name, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use getrandom::getrandom;
+use hmac::Mac;
+use http::header::{HeaderValue, CONTENT_TYPE, COOKIE, SET_COOKIE};
+use http::{Method, Request, Response};
+use session::{base64_decode, base64_encode, mac, unescape, Session};
+
+const COOKIE_NAME: &str = "csrf";
+const FIELD_NAME: &str = "csrf_token";
+const HEADER_NAME: &str = "x-csrf-token";
+
+// Where the token is kept when sessions are in use.
+const SESSION_KEY: &str = "csrf_token";
+
+/// The CSRF token for a request, for embedding in forms and pages.
+///
+/// When CSRF protection is turned on with `Server::set_csrf_protection`,
+/// every request passed to the handler carries one of these in its
+/// extensions. Requests with methods other than `GET`, `HEAD`, `OPTIONS` and
+/// `TRACE` are refused with a `403 Forbidden` before reaching the handler
+/// unless they send the token back, either in a `csrf_token` form field or
+/// an `X-CSRF-Token` header.
+///
+/// With sessions turned on, the token is kept in the session, so a freshly
+/// made token only sticks if the handler applies the session to its
+/// response. Otherwise the token goes in a signed `csrf` cookie, which the
+/// server sets by itself.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{CsrfToken, Server};
+///
+/// fn main() {
+///     let mut server = Server::new(|request, mut response| {
+///         let csrf = request.extensions().get::<CsrfToken>().expect("protection is on");
+///         let form = format!(
+///             "<form method=\"post\" action=\"/comments\">{}<textarea name=\"text\"></textarea></form>",
+///             csrf.form_field()
+///         );
+///
+///         Ok(response.body(form.into_bytes())?)
+///     });
+///
+///     server.set_csrf_protection(true);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CsrfToken {
+    token: String,
+}
+
+impl CsrfToken {
+    /// The token itself, for sending in an `X-CSRF-Token` header from
+    /// scripts.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// A hidden `<input>` holding the token, to put inside a `<form>`.
+    pub fn form_field(&self) -> String {
+        // Tokens are base64url, so there's nothing to escape.
+        format!(
+            "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+            FIELD_NAME, self.token
+        )
+    }
+}
+
+/// Makes a key for signing CSRF cookies.
+pub fn random_key() -> [u8; 32] {
+    let mut key = [0; 32];
+    getrandom(&mut key).expect("couldn't get random bytes from the operating system");
+    key
+}
+
+fn new_token() -> String {
+    base64_encode(&random_key())
+}
+
+/// Checks that a state-changing request sent back the token it was given,
+/// unless its path starts with one of the `exempt` prefixes.
+///
+/// This has to happen before `attach_token`, which may replace a missing
+/// token in the session.
+pub fn verify(request: &Request<Vec<u8>>, key: &[u8; 32], exempt: &[String]) -> bool {
+    let method = request.method();
+    if method == Method::GET
+        || method == Method::HEAD
+        || method == Method::OPTIONS
+        || method == Method::TRACE
+    {
+        return true;
+    }
+
+    let path = request.uri().path();
+    if exempt
+        .iter()
+        .any(|prefix| path.starts_with(prefix.as_str()))
+    {
+        return true;
+    }
+
+    let expected = match expected_token(request, key) {
+        Some(expected) => expected,
+        None => {
+            debug!("Refusing a {} to {} without a CSRF token", method, path);
+            return false;
+        }
+    };
+
+    let matches = submitted_token(request)
+        .is_some_and(|submitted| constant_time_eq(submitted.as_bytes(), expected.as_bytes()));
+    if !matches {
+        debug!(
+            "Refusing a {} to {} with the wrong CSRF token",
+            method, path
+        );
+    }
+    matches
+}
+
+/// Gives the request a `CsrfToken`, making one up if it didn't have one.
+///
+/// Returns the `Set-Cookie` header to add to the response if a new token
+/// needs to go in a cookie.
+pub fn attach_token(request: &mut Request<Vec<u8>>, key: &[u8; 32]) -> Option<HeaderValue> {
+    let existing = expected_token(request, key);
+    let mut set_cookie = None;
+
+    let token = match existing {
+        Some(token) => token,
+        None => {
+            let token = new_token();
+            match request.extensions_mut().get_mut::<Session>() {
+                Some(session) => session.insert(SESSION_KEY, token.clone()),
+                None => set_cookie = Some(cookie(&token, key)),
+            }
+            token
+        }
+    };
+
+    request.extensions_mut().insert(CsrfToken { token });
+    set_cookie
+}
+
+/// Adds the cookie from `attach_token`, if there was one, to the handler's
+/// response.
+pub fn set_cookie<B>(mut response: Response<B>, cookie: Option<HeaderValue>) -> Response<B> {
+    if let Some(cookie) = cookie {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
+    response
+}
+
+// The token the request should have sent back: the one in its session if
+// there is one, or else the one in its cookie, as long as it's been signed by
+// us.
+fn expected_token<B>(request: &Request<B>, key: &[u8; 32]) -> Option<String> {
+    if let Some(session) = request.extensions().get::<Session>() {
+        return session.get(SESSION_KEY).map(str::to_string);
+    }
+
+    request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .filter(|&(name, _)| name == COOKIE_NAME)
+        .find_map(|(_, value)| {
+            let (token, signature) = value.split_once('.')?;
+            let mut mac = mac(key);
+            mac.update(token.as_bytes());
+            mac.verify_slice(&base64_decode(signature)?).ok()?;
+            Some(token.to_string())
+        })
+}
+
+fn cookie(token: &str, key: &[u8; 32]) -> HeaderValue {
+    let mut mac = mac(key);
+    mac.update(token.as_bytes());
+    let signature = base64_encode(&mac.finalize().into_bytes());
+
+    format!(
+        "{}={}.{}; Path=/; HttpOnly; SameSite=Lax",
+        COOKIE_NAME, token, signature
+    )
+    .parse()
+    .expect("base64url is always a valid header value")
+}
+
+// The token the request sent back, from the header or else from the form.
+fn submitted_token(request: &Request<Vec<u8>>) -> Option<String> {
+    if let Some(value) = request.headers().get(HEADER_NAME) {
+        return value.to_str().ok().map(str::to_string);
+    }
+
+    let is_form = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|essence| {
+            essence
+                .trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        });
+    if !is_form {
+        return None;
+    }
+
+    ::std::str::from_utf8(request.body())
+        .ok()?
+        .split('&')
+        .filter_map(|field| field.split_once('='))
+        .find(|&(name, _)| name == FIELD_NAME)
+        .and_then(|(_, value)| unescape(value))
+}
+
+// Compares every byte whatever the earlier ones were, so that how long it
+// takes doesn't give away how much of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod csrf_should {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const KEY: &[u8; 32] = b"0123456789abcdef0123456789abcdef";
+
+    fn empty_session() -> Session {
+        Session::from_request(
+            &Request::get("/").body(()).unwrap(),
+            Arc::new(*KEY),
+            Duration::from_secs(60),
+        )
+    }
+
+    fn token_from_cookie(cookie: &HeaderValue) -> String {
+        let cookie = cookie.to_str().unwrap();
+        let value = cookie.split(';').next().unwrap().split_once('=').unwrap().1;
+        value.split_once('.').unwrap().0.to_string()
+    }
+
+    fn post(cookie: Option<&HeaderValue>, body: &str) -> Request<Vec<u8>> {
+        let mut request = Request::post("/comments");
+        request.header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(cookie) = cookie {
+            let cookie = cookie.to_str().unwrap();
+            request.header(COOKIE, cookie.split(';').next().unwrap());
+        }
+        request.body(body.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn let_safe_methods_through() {
+        for method in &[Method::GET, Method::HEAD, Method::OPTIONS] {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri("/")
+                .body(vec![])
+                .unwrap();
+            assert!(verify(&request, KEY, &[]));
+        }
+    }
+
+    #[test]
+    fn refuse_state_changing_requests_without_a_token() {
+        for method in &[Method::POST, Method::PUT, Method::DELETE, Method::PATCH] {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri("/")
+                .body(vec![])
+                .unwrap();
+            assert!(!verify(&request, KEY, &[]));
+        }
+    }
+
+    #[test]
+    fn accept_the_token_from_the_cookie() {
+        let mut first = Request::get("/").body(vec![]).unwrap();
+        let cookie = attach_token(&mut first, KEY).unwrap();
+        let token = first
+            .extensions()
+            .get::<CsrfToken>()
+            .unwrap()
+            .token()
+            .to_string();
+        assert_eq!(token, token_from_cookie(&cookie));
+
+        let form = format!("text=hi&{}={}", FIELD_NAME, token);
+        assert!(verify(&post(Some(&cookie), &form), KEY, &[]));
+
+        let mut with_header = post(Some(&cookie), "text=hi");
+        with_header
+            .headers_mut()
+            .insert(HEADER_NAME, token.parse().unwrap());
+        assert!(verify(&with_header, KEY, &[]));
+    }
+
+    #[test]
+    fn keep_an_existing_token() {
+        let mut first = Request::get("/").body(vec![]).unwrap();
+        let cookie = attach_token(&mut first, KEY).unwrap();
+
+        let mut second = post(Some(&cookie), "");
+        assert_eq!(None, attach_token(&mut second, KEY));
+        assert_eq!(
+            token_from_cookie(&cookie),
+            second.extensions().get::<CsrfToken>().unwrap().token()
+        );
+    }
+
+    #[test]
+    fn refuse_the_wrong_token() {
+        let mut first = Request::get("/").body(vec![]).unwrap();
+        let cookie = attach_token(&mut first, KEY).unwrap();
+
+        let form = format!("{}={}", FIELD_NAME, new_token());
+        assert!(!verify(&post(Some(&cookie), &form), KEY, &[]));
+        assert!(!verify(&post(Some(&cookie), "text=hi"), KEY, &[]));
+    }
+
+    #[test]
+    fn refuse_cookies_signed_with_another_key() {
+        let other_key = b"another key that's 32 bytes long";
+        let mut first = Request::get("/").body(vec![]).unwrap();
+        let cookie = attach_token(&mut first, other_key).unwrap();
+
+        let form = format!("{}={}", FIELD_NAME, token_from_cookie(&cookie));
+        assert!(!verify(&post(Some(&cookie), &form), KEY, &[]));
+    }
+
+    #[test]
+    fn keep_the_token_in_the_session() {
+        let session = empty_session();
+        let mut first = Request::get("/").body(vec![]).unwrap();
+        first.extensions_mut().insert(session);
+
+        assert_eq!(None, attach_token(&mut first, KEY));
+        let token = first
+            .extensions()
+            .get::<CsrfToken>()
+            .unwrap()
+            .token()
+            .to_string();
+        let session = first.extensions().get::<Session>().unwrap().clone();
+        assert_eq!(Some(token.as_str()), session.get(SESSION_KEY));
+
+        let mut second = post(None, &format!("{}={}", FIELD_NAME, token));
+        second.extensions_mut().insert(session);
+        assert!(verify(&second, KEY, &[]));
+
+        let mut forged = post(None, &format!("{}={}", FIELD_NAME, new_token()));
+        forged.extensions_mut().insert(empty_session());
+        assert!(!verify(&forged, KEY, &[]));
+    }
+
+    #[test]
+    fn skip_exempt_paths() {
+        let request = Request::post("/api/comments").body(vec![]).unwrap();
+
+        assert!(verify(&request, KEY, &["/api/".to_string()]));
+        assert!(!verify(&request, KEY, &["/admin/".to_string()]));
+    }
+
+    #[test]
+    fn compare_tokens() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Formatting the date shows up in profiles for small responses, and the
+// header only has second precision anyway, so each worker thread keeps the
+// last formatted value around until the second changes.
+thread_local!(static CACHED: RefCell<CachedDate> = RefCell::new(CachedDate::new()));
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+struct CachedDate {
+    second: u64,
+    formatted: String,
+    renders: usize,
+}
+
+impl CachedDate {
+    fn new() -> CachedDate {
+        CachedDate {
+            second: u64::MAX,
+            formatted: String::new(),
+            renders: 0,
+        }
+    }
+
+    fn get(&mut self, now: SystemTime) -> &str {
+        let second = seconds_since_epoch(now);
+
+        if second != self.second {
+            self.formatted.clear();
+            write_imf_fixdate(&mut self.formatted, second).unwrap();
+            self.second = second;
+            self.renders += 1;
+        }
+
+        &self.formatted
+    }
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    // Clocks set before 1970 aren't worth a panic; claim it's the epoch.
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Writes `secs` (since the Unix epoch) in the RFC 7231 IMF-fixdate format,
+/// e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// This is a fixed, English-only layout, so it's done by hand rather than
+/// trusting a locale-sensitive `strftime`.
+pub fn write_imf_fixdate<W: Write>(out: &mut W, secs: u64) -> fmt::Result {
+    let days = secs / 86_400;
+    let secs_of_day = secs % 86_400;
+
+    // Converts days since the epoch into a civil date, following Howard
+    // Hinnant's `civil_from_days`. Years are counted from March, so the leap
+    // day falls at the very end of the (shifted) year.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    write!(
+        out,
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+    )
+}
+
+/// Parses an RFC 7231 IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, into
+/// seconds since the Unix epoch.
+///
+/// The obsolete RFC 850 and asctime formats aren't accepted. Dates before the
+/// epoch come back as `None`, like anything else that can't be parsed.
+pub fn parse_imf_fixdate(date: &str) -> Option<u64> {
+    let bytes = date.as_bytes();
+    if bytes.len() != 29
+        || !date.is_ascii()
+        || &bytes[3..5] != b", "
+        || bytes[7] != b' '
+        || bytes[11] != b' '
+        || bytes[16] != b' '
+        || bytes[19] != b':'
+        || bytes[22] != b':'
+        || &bytes[25..] != b" GMT"
+    {
+        return None;
+    }
+
+    let number = |range: ::std::ops::Range<usize>| -> Option<u64> {
+        let digits = &date[range];
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+
+    let day = number(5..7)?;
+    let month = MONTHS.iter().position(|&m| m == &date[8..11])? as u64 + 1;
+    let year = number(12..16)?;
+    let (hour, minute, second) = (number(17..19)?, number(20..22)?, number(23..25)?);
+
+    if year < 1970 || day == 0 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // The inverse of the conversion in `write_imf_fixdate`, after Howard
+    // Hinnant's `days_from_civil`.
+    let shifted_year = if month <= 2 { year - 1 } else { year };
+    let era = shifted_year / 400;
+    let year_of_era = shifted_year - era * 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+    if DAYS[(days % 7) as usize] != &date[..3] {
+        return None;
+    }
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Calls `f` with the current date, formatted for use in a `Date` header.
+pub fn with_now<F, R>(f: F) -> R
+where
+    F: FnOnce(&str) -> R,
+{
+    CACHED.with(|cached| f(cached.borrow_mut().get(SystemTime::now())))
+}
+
+#[cfg(test)]
+mod date_should {
+    use super::*;
+    use std::time::Duration;
+
+    fn format(secs: u64) -> String {
+        let mut out = String::new();
+        write_imf_fixdate(&mut out, secs).unwrap();
+        out
+    }
+
+    #[test]
+    fn format_known_timestamps() {
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", format(0));
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", format(784_111_777));
+        assert_eq!("Fri, 31 Dec 9999 23:59:59 GMT", format(253_402_300_799));
+    }
+
+    #[test]
+    fn format_leap_days() {
+        assert_eq!("Tue, 29 Feb 2000 00:00:00 GMT", format(951_782_400));
+        assert_eq!("Tue, 29 Feb 2000 23:59:59 GMT", format(951_868_799));
+        assert_eq!("Sat, 29 Feb 2020 00:00:00 GMT", format(1_582_934_400));
+        // 2100 isn't a leap year.
+        assert_eq!("Sun, 28 Feb 2100 23:59:59 GMT", format(4_107_542_399));
+    }
+
+    #[test]
+    fn format_the_end_of_a_year() {
+        assert_eq!("Thu, 31 Dec 2020 23:59:59 GMT", format(1_609_459_199));
+        assert_eq!("Fri, 01 Jan 2021 00:00:00 GMT", format(1_609_459_200));
+    }
+
+    #[test]
+    fn parse_what_it_formats() {
+        for &secs in &[0, 784_111_777, 951_782_400, 4_107_542_399, 253_402_300_799] {
+            assert_eq!(Some(secs), parse_imf_fixdate(&format(secs)));
+        }
+    }
+
+    #[test]
+    fn reject_other_date_formats() {
+        for date in &[
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Mon, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+            "Sun, 06 Nov 1994 08:+9:37 GMT",
+        ] {
+            assert_eq!(None, parse_imf_fixdate(date), "{}", date);
+        }
+    }
+
+    #[test]
+    fn only_format_once_per_second() {
+        let mut cached = CachedDate::new();
+        let now = UNIX_EPOCH;
+        let later = UNIX_EPOCH + Duration::from_millis(999);
+
+        let first = cached.get(now).to_string();
+        let second = cached.get(later).to_string();
+
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", first);
+        assert_eq!(first, second);
+        assert_eq!(1, cached.renders);
+    }
+
+    #[test]
+    fn reformat_when_the_second_changes() {
+        let mut cached = CachedDate::new();
+
+        cached.get(UNIX_EPOCH);
+        let next = cached.get(UNIX_EPOCH + Duration::from_secs(1));
+
+        assert_eq!("Thu, 01 Jan 1970 00:00:01 GMT", next);
+        assert_eq!(2, cached.renders);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+/// When the time allowed for a request runs out.
+///
+/// For a server made with `Server::with_timeout`, every request passed to the
+/// handler carries one of these in its extensions. It's when the request
+/// started being read plus the timeout, which is the same deadline the server
+/// holds the client to while reading the request. Whatever's left of it is
+/// the handler's to spend, so a handler that calls out to other services can
+/// give them a shorter timeout rather than keep the client waiting past it.
+///
+/// Without a timeout, there's no deadline, and requests don't carry one.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{Deadline, Server};
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let server = Server::with_timeout(Duration::from_secs(5), |request, mut response| {
+///         let budget = request
+///             .extensions()
+///             .get::<Deadline>()
+///             .map(Deadline::remaining)
+///             .unwrap_or(Duration::from_secs(30));
+///
+///         // ... call another service, giving up after `budget` ...
+///
+///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub(crate) fn new(instant: Instant) -> Deadline {
+        Deadline(instant)
+    }
+
+    /// Returns the moment the deadline passes.
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Returns how much time is left before the deadline, which is zero once
+    /// it's passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod deadline_should {
+    use super::*;
+
+    #[test]
+    fn count_down_to_the_deadline() {
+        let deadline = Deadline::new(Instant::now() + Duration::from_secs(60));
+
+        let remaining = deadline.remaining();
+
+        assert!(remaining > Duration::from_secs(59));
+        assert!(remaining <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn have_nothing_left_once_passed() {
+        let deadline = Deadline::new(Instant::now() - Duration::from_millis(10));
+
+        assert_eq!(Duration::from_secs(0), deadline.remaining());
+    }
+}
@@ -0,0 +1,108 @@
+use http::header::HeaderValue;
+use std::fmt::Write;
+
+/// Builds a `Content-Disposition` header value that tells the browser to
+/// download the response as a file called `filename`, rather than showing it.
+///
+/// Plain ASCII names are sent as a quoted `filename` parameter. Anything else
+/// also gets an RFC 5987 `filename*` parameter with the name percent-encoded
+/// as UTF-8, alongside an ASCII approximation for clients that don't
+/// understand it. Control characters can't be sent in a header at all, so
+/// they're replaced with underscores.
+///
+/// # Examples
+///
+/// ```
+/// extern crate http;
+/// extern crate simple_server;
+///
+/// use http::header::CONTENT_DISPOSITION;
+/// use simple_server::{attachment, Server};
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         response.header(CONTENT_DISPOSITION, attachment("report 2018.csv"));
+///         Ok(response.body(b"month,total\n".to_vec())?)
+///     });
+/// }
+/// ```
+pub fn attachment(filename: &str) -> HeaderValue {
+    let filename: String = filename
+        .chars()
+        .map(|c| if c.is_control() { '_' } else { c })
+        .collect();
+
+    let mut value = String::from("attachment; filename=\"");
+    for c in filename.chars() {
+        match c {
+            '"' | '\\' => {
+                value.push('\\');
+                value.push(c);
+            }
+            c if c.is_ascii() => value.push(c),
+            _ => value.push('_'),
+        }
+    }
+    value.push('"');
+
+    if !filename.is_ascii() {
+        value.push_str("; filename*=UTF-8''");
+        for &byte in filename.as_bytes() {
+            if is_attr_char(byte) {
+                value.push(byte as char);
+            } else {
+                write!(value, "%{:02X}", byte).unwrap();
+            }
+        }
+    }
+
+    HeaderValue::from_str(&value).expect("Content-Disposition should be visible ASCII")
+}
+
+// The characters RFC 5987 allows unescaped in an extended parameter value.
+fn is_attr_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte)
+}
+
+#[cfg(test)]
+mod disposition_should {
+    use super::*;
+
+    #[test]
+    fn quote_ascii_filenames() {
+        assert_eq!(
+            "attachment; filename=\"annual report.pdf\"",
+            attachment("annual report.pdf")
+        );
+    }
+
+    #[test]
+    fn escape_quotes_and_backslashes() {
+        assert_eq!(
+            "attachment; filename=\"say \\\"hi\\\" \\\\o/.txt\"",
+            attachment("say \"hi\" \\o/.txt")
+        );
+    }
+
+    #[test]
+    fn add_an_extended_filename_for_non_ascii_names() {
+        assert_eq!(
+            "attachment; filename=\"my \\\"report\\\" __.pdf\"; \
+             filename*=UTF-8''my%20%22report%22%20%E5%A0%B1%E5%91%8A.pdf",
+            attachment("my \"report\" 報告.pdf")
+        );
+    }
+
+    #[test]
+    fn replace_control_characters() {
+        assert_eq!(
+            "attachment; filename=\"a__b.txt\"",
+            attachment("a\r\nb.txt")
+        );
+        assert_eq!("attachment; filename=\"a_b.txt\"", attachment("a\tb.txt"));
+        assert_eq!(
+            "attachment; filename=\"a_b.txt\"",
+            attachment("a\u{7f}b.txt")
+        );
+    }
+}
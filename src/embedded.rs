@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+
+use etag;
+
+/// A file compiled into the program, to be served as a static file would be.
+#[derive(Debug)]
+pub struct Asset {
+    pub bytes: &'static [u8],
+    pub content_type: &'static str,
+    pub etag: String,
+}
+
+/// Turns a map from request paths to bytes and content types into assets,
+/// working out their entity tags up front, as they never change.
+pub fn assets(files: HashMap<String, (&'static [u8], &'static str)>) -> HashMap<String, Asset> {
+    files
+        .into_iter()
+        .map(|(path, (bytes, content_type))| {
+            let asset = Asset {
+                bytes,
+                content_type,
+                etag: etag::for_bytes(bytes),
+            };
+            (path, asset)
+        })
+        .collect()
+}
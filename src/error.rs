@@ -3,7 +3,11 @@ use httparse;
 use std;
 
 /// Various errors that may happen while handling requests.
+///
+/// More kinds of errors are added as the server learns to catch them, so
+/// matching on this needs a catch-all arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// An error while doing I/O.
     Io(std::io::Error),
@@ -11,16 +15,72 @@ pub enum Error {
     Http(http::Error),
     /// An error while parsing the HTTP request.
     HttpParse(httparse::Error),
+    /// The request line had no HTTP version, as in an HTTP/0.9 request.
+    MissingVersion,
+    /// A header in the request had a name or value that isn't allowed.
+    InvalidHeader,
+    /// A header in the request was folded onto several lines, which is
+    /// obsolete and not supported.
+    ObsoleteLineFolding,
     /// An error while parsing the URI of the request.
     InvalidUri(http::uri::InvalidUri),
+    /// The request target had characters that aren't allowed in a URI.
+    InvalidTarget,
+    /// The request's headers gave the length of its body in a way that's
+    /// invalid or ambiguous.
+    Framing(FramingError),
     /// The request timed out.
     Timeout,
     #[doc(hidden)]
     RequestIncomplete,
     /// The request's size (headers + body) exceeded the application's limit.
     RequestTooLarge,
+    /// The request line was longer than the server allows.
+    RequestLineTooLong,
+    /// The request had an `Expect` header with an expectation other than
+    /// `100-continue`, which can't be met.
+    ExpectationFailed,
     /// The connection was closed while reading the request.
     ConnectionClosed,
+    /// An interim response was sent with a status that isn't an interim one.
+    NotInterimStatus(http::StatusCode),
+    /// A reason phrase had a character in it that can't go in a status line.
+    InvalidReasonPhrase,
+    /// A configuration variable was set to something that can't be read.
+    InvalidConfig {
+        /// The name of the variable.
+        variable: String,
+        /// What it was set to.
+        value: String,
+        /// What it should have been set to.
+        expected: &'static str,
+    },
+    /// A session got too big to fit in a cookie. The size it would have had
+    /// is included. Only sessions, behind the `sessions` feature, can run
+    /// into this, but it's always here so that turning the feature on
+    /// doesn't change the enum.
+    SessionTooLarge(usize),
+}
+
+/// Ways a request's headers can get the length of its body wrong.
+///
+/// Servers and proxies that settle ambiguities like these differently can be
+/// tricked into seeing different requests in the same bytes, so such requests
+/// are refused outright.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FramingError {
+    /// The request had both a `Content-Length` and a `Transfer-Encoding`.
+    ContentLengthWithTransferEncoding,
+    /// The last coding in the `Transfer-Encoding` wasn't `chunked`.
+    UnsupportedTransferEncoding,
+    /// The request had several `Content-Length` values that disagree.
+    ConflictingContentLengths,
+    /// A `Content-Length` wasn't a plain decimal number that fits in a
+    /// `u64`.
+    InvalidContentLength,
+    /// A chunked body's framing was malformed.
+    InvalidChunk,
 }
 
 impl From<std::io::Error> for Error {
@@ -46,3 +106,9 @@ impl From<http::uri::InvalidUri> for Error {
         Error::InvalidUri(err)
     }
 }
+
+impl From<http::header::InvalidHeaderValue> for Error {
+    fn from(err: http::header::InvalidHeaderValue) -> Error {
+        Error::Http(err.into())
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::UNIX_EPOCH;
+
+use walk;
+
+/// Entity tags for the files in the static directories, worked out from
+/// their contents ahead of time.
+///
+/// Hashing a file on every request costs too much, and tags made from
+/// modification times change on every deploy even when the contents don't,
+/// so the static directories are indexed up front instead. Files that turn
+/// up or change after indexing get a weak tag made from their length and
+/// modification time until the next index.
+#[derive(Debug, Default)]
+pub struct Etags {
+    files: RwLock<HashMap<PathBuf, Indexed>>,
+}
+
+#[derive(Debug)]
+struct Indexed {
+    len: u64,
+    modified: Option<u64>,
+    etag: String,
+}
+
+impl Etags {
+    /// Replaces the index with one of the files under `roots`, returning how
+    /// many there were. Anything that can't be read is left out.
+    pub fn index(&self, roots: &[PathBuf]) -> usize {
+        let mut files = HashMap::new();
+        for root in roots {
+            walk::files(root, &mut |path, metadata| {
+                if files.contains_key(&path) {
+                    return;
+                }
+                match hash_file(&path) {
+                    Ok(hash) => {
+                        let indexed = Indexed {
+                            len: metadata.len(),
+                            modified: modified(metadata),
+                            etag: strong(metadata.len(), hash),
+                        };
+                        files.insert(path, indexed);
+                    }
+                    Err(e) => debug!("Skipping {} while indexing ETags: {}", path.display(), e),
+                }
+            });
+        }
+
+        let count = files.len();
+        match self.files.write() {
+            Ok(mut indexed) => *indexed = files,
+            Err(poisoned) => *poisoned.into_inner() = files,
+        }
+        count
+    }
+
+    /// The entity tag for the file at `path`, whose metadata is `metadata`.
+    pub fn get(&self, path: &Path, metadata: &Metadata) -> Option<String> {
+        let files = match self.files.read() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        match files.get(path) {
+            Some(indexed)
+                if indexed.len == metadata.len() && indexed.modified == modified(metadata) =>
+            {
+                Some(indexed.etag.clone())
+            }
+            _ => modified(metadata)
+                .map(|modified| format!("W/\"{:x}-{:x}\"", metadata.len(), modified)),
+        }
+    }
+}
+
+fn modified(metadata: &Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_secs())
+}
+
+/// The entity tag for `bytes`, made the same way as the tags for indexed
+/// files, so a file served from memory gets the same tag it would from disk.
+pub fn for_bytes(bytes: &[u8]) -> String {
+    strong(bytes.len() as u64, hash(FNV_OFFSET_BASIS, bytes))
+}
+
+fn strong(len: u64, hash: u64) -> String {
+    format!("\"{:x}-{:016x}\"", len, hash)
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+// FNV-1a, which is plenty to tell versions of a file apart, and gives the same
+// answer whichever build of the server computes it.
+fn hash(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0; 8192];
+    let mut hash_so_far = FNV_OFFSET_BASIS;
+
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => return Ok(hash_so_far),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        hash_so_far = hash(hash_so_far, &buffer[..read]);
+    }
+}
+
+#[cfg(test)]
+mod etag_should {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("simple-server-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, contents: &[u8]) {
+        File::create(path).unwrap().write_all(contents).unwrap();
+    }
+
+    fn etag(etags: &Etags, path: &Path) -> String {
+        etags.get(path, &fs::metadata(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hash_file_contents() {
+        let roots = [temp_dir("etag-hash")];
+        let dir = &roots[0];
+        write(&dir.join("a.txt"), b"same");
+        write(&dir.join("nested/b.txt"), b"same");
+        write(&dir.join("c.txt"), b"different");
+
+        let etags = Etags::default();
+        assert_eq!(3, etags.index(&roots));
+
+        let a = etag(&etags, &dir.join("a.txt"));
+        assert!(a.starts_with('"'));
+        assert_eq!(a, etag(&etags, &dir.join("nested/b.txt")));
+        assert_ne!(a, etag(&etags, &dir.join("c.txt")));
+    }
+
+    #[test]
+    fn fall_back_to_weak_tags_for_new_or_changed_files() {
+        let roots = [temp_dir("etag-fallback")];
+        let dir = &roots[0];
+        write(&dir.join("a.txt"), b"first");
+
+        let etags = Etags::default();
+        etags.index(&roots);
+        write(&dir.join("b.txt"), b"added");
+        write(&dir.join("a.txt"), b"second version");
+
+        assert!(etag(&etags, &dir.join("a.txt")).starts_with("W/\""));
+        assert!(etag(&etags, &dir.join("b.txt")).starts_with("W/\""));
+
+        assert_eq!(2, etags.index(&roots));
+        assert!(etag(&etags, &dir.join("b.txt")).starts_with('"'));
+    }
+
+    #[test]
+    fn tag_bytes_as_they_would_be_tagged_on_disk() {
+        let roots = [temp_dir("etag-bytes")];
+        write(&roots[0].join("a.txt"), b"contents");
+
+        let etags = Etags::default();
+        etags.index(&roots);
+
+        assert_eq!(
+            etag(&etags, &roots[0].join("a.txt")),
+            for_bytes(b"contents")
+        );
+    }
+
+    #[test]
+    fn skip_missing_directories() {
+        let etags = Etags::default();
+
+        assert_eq!(0, etags.index(&[PathBuf::from("/no/such/directory")]));
+    }
+}
@@ -0,0 +1,152 @@
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+
+// How much of a file is read into memory at a time when copying it through
+// userspace.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Sends `len` bytes of `file`, starting at `offset`, to `stream`.
+///
+/// On Linux with the `sendfile` feature enabled, the kernel copies the bytes
+/// from the file to the socket directly. Everywhere else, or if `sendfile`
+/// gives up part way through, the rest is copied through a buffer instead.
+pub fn send(file: &mut File, offset: u64, len: u64, stream: &mut TcpStream) -> io::Result<()> {
+    let sent = sendfile(file, offset, len, stream);
+    copy(file, offset + sent, len - sent, stream)
+}
+
+/// Copies `len` bytes of `file`, starting at `offset`, to `out` one chunk at
+/// a time.
+pub fn copy<W: Write>(file: &mut File, offset: u64, len: u64, out: &mut W) -> io::Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut buffer = vec![0; cmp::min(CHUNK_SIZE, len) as usize];
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let wanted = cmp::min(buffer.len() as u64, remaining) as usize;
+        let read = match file.read(&mut buffer[..wanted]) {
+            // The file shrank since we looked at its length, so we can't send
+            // the number of bytes we promised.
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        out.write_all(&buffer[..read])?;
+        remaining -= read as u64;
+    }
+
+    Ok(())
+}
+
+// Returns how many bytes made it out, so that the caller can copy the rest.
+#[cfg(all(target_os = "linux", feature = "sendfile"))]
+fn sendfile(file: &File, offset: u64, len: u64, stream: &TcpStream) -> u64 {
+    use libc;
+    use std::os::unix::io::AsRawFd;
+
+    // sendfile(2) transfers at most this much per call anyway.
+    const MAX_PER_CALL: u64 = 0x7fff_f000;
+
+    let mut file_offset = offset as libc::off_t;
+    let mut sent = 0;
+
+    while sent < len {
+        let count = cmp::min(len - sent, MAX_PER_CALL) as usize;
+        let result = unsafe {
+            libc::sendfile(
+                stream.as_raw_fd(),
+                file.as_raw_fd(),
+                &mut file_offset,
+                count,
+            )
+        };
+
+        match result {
+            n if n > 0 => sent += n as u64,
+            0 => break,
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                debug!("sendfile failed, falling back to copying: {}", err);
+                break;
+            }
+        }
+    }
+
+    sent
+}
+
+#[cfg(not(all(target_os = "linux", feature = "sendfile")))]
+fn sendfile(_file: &File, _offset: u64, _len: u64, _stream: &TcpStream) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod file_should {
+    use super::*;
+    use std::env;
+    use std::net::TcpListener;
+    use std::process;
+    use std::thread;
+
+    fn temp_file(name: &str, contents: &[u8]) -> File {
+        let path = env::temp_dir().join(format!("simple-server-{}-{}", process::id(), name));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        File::open(&path).unwrap()
+    }
+
+    #[test]
+    fn copy_a_range_of_a_file() {
+        let mut file = temp_file("copy-range", b"0123456789");
+        let mut out = vec![];
+
+        copy(&mut file, 3, 4, &mut out).unwrap();
+
+        assert_eq!(&b"3456"[..], &out[..]);
+    }
+
+    #[test]
+    fn fail_when_the_file_is_shorter_than_promised() {
+        let mut file = temp_file("copy-short", b"0123");
+        let mut out = vec![];
+
+        let err = copy(&mut file, 2, 10, &mut out).unwrap_err();
+
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn send_a_file_over_a_socket() {
+        let contents: Vec<u8> = (0..CHUNK_SIZE * 3).map(|i| i as u8).collect();
+        let mut file = temp_file("send", &contents);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reader = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        send(&mut file, 10, contents.len() as u64 - 20, &mut stream).unwrap();
+        drop(stream);
+
+        assert_eq!(
+            &contents[10..contents.len() - 10],
+            &reader.join().unwrap()[..]
+        );
+    }
+}
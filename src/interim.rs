@@ -0,0 +1,166 @@
+use error::Error;
+use http::header::HeaderMap;
+use http::{StatusCode, Version};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Sends interim (`1xx`) responses ahead of the final one.
+///
+/// Every request passed to a handler carries one of these in its extensions.
+/// A handler that's about to do something slow can use it to send, say, a
+/// `103 Early Hints` so that the client can start fetching stylesheets while
+/// it waits. The final response is still whatever the handler returns.
+///
+/// Interim responses only exist in HTTP/1.1, so for HTTP/1.0 clients they're
+/// silently skipped.
+///
+/// # Examples
+///
+/// ```
+/// extern crate http;
+/// extern crate simple_server;
+///
+/// use http::header::{HeaderMap, LINK};
+/// use simple_server::{Interim, Server, StatusCode};
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         if let Some(interim) = request.extensions().get::<Interim>() {
+///             let mut hints = HeaderMap::new();
+///             hints.insert(LINK, "</style.css>; rel=preload; as=style".parse().unwrap());
+///             interim.send_interim(StatusCode::from_u16(103).unwrap(), hints)?;
+///         }
+///
+///         // ... slow work ...
+///
+///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+///     });
+/// }
+/// ```
+pub struct Interim {
+    stream: Mutex<Box<dyn Write + Send>>,
+    version: Version,
+}
+
+impl Interim {
+    pub(crate) fn new<W: Write + Send + 'static>(stream: W, version: Version) -> Interim {
+        Interim {
+            stream: Mutex::new(Box::new(stream)),
+            version,
+        }
+    }
+
+    /// Writes an interim response with the given status and headers to the
+    /// client straight away.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::NotInterimStatus` if the status isn't a `1xx` one, or
+    /// is `101 Switching Protocols`, which ends the HTTP exchange rather than
+    /// preceding a final response. Errors writing to the connection are
+    /// returned as `Error::Io`.
+    pub fn send_interim(&self, status: StatusCode, headers: HeaderMap) -> Result<(), Error> {
+        if !status.is_informational() || status == StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Error::NotInterimStatus(status));
+        }
+
+        if self.version < Version::HTTP_11 {
+            return Ok(());
+        }
+
+        let reason = match status.as_u16() {
+            103 => "Early Hints",
+            _ => status.canonical_reason().unwrap_or(""),
+        };
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", status.as_str(), reason).into_bytes();
+        for name in headers.keys() {
+            for value in headers.get_all(name) {
+                head.extend_from_slice(name.as_str().as_bytes());
+                head.extend_from_slice(b": ");
+                head.extend_from_slice(value.as_bytes());
+                head.extend_from_slice(b"\r\n");
+            }
+        }
+        head.extend_from_slice(b"\r\n");
+
+        // A handler that panicked while holding the lock can't have left a
+        // half-written response behind, as `write_all` happens under it.
+        let mut stream = match self.stream.lock() {
+            Ok(stream) => stream,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        stream.write_all(&head)?;
+        Ok(stream.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod interim_should {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn hints() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.append("link", "</a.css>; rel=preload".parse().unwrap());
+        headers.append("link", "</b.js>; rel=preload".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn write_early_hints() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(vec![])));
+        let interim = Interim::new(buffer.clone(), Version::HTTP_11);
+
+        interim
+            .send_interim(StatusCode::from_u16(103).unwrap(), hints())
+            .unwrap();
+
+        assert_eq!(
+            &b"HTTP/1.1 103 Early Hints\r\n\
+               link: </a.css>; rel=preload\r\n\
+               link: </b.js>; rel=preload\r\n\
+               \r\n"[..],
+            &buffer.0.lock().unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn skip_interim_responses_for_http10() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(vec![])));
+        let interim = Interim::new(buffer.clone(), Version::HTTP_10);
+
+        interim
+            .send_interim(StatusCode::from_u16(103).unwrap(), hints())
+            .unwrap();
+
+        assert!(buffer.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reject_final_statuses() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(vec![])));
+        let interim = Interim::new(buffer.clone(), Version::HTTP_11);
+
+        for status in &[StatusCode::OK, StatusCode::SWITCHING_PROTOCOLS] {
+            match interim.send_interim(*status, HeaderMap::new()) {
+                Err(Error::NotInterimStatus(s)) => assert_eq!(*status, s),
+                _ => panic!("Expected NotInterimStatus"),
+            }
+        }
+        assert!(buffer.0.lock().unwrap().is_empty());
+    }
+}
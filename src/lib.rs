@@ -23,14 +23,29 @@
 //! To see examples of this crate in use, please consult the `examples`
 //! directory.
 
+#[cfg(not(feature = "tracing"))]
 #[macro_use]
 extern crate log;
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+#[cfg(all(test, feature = "tracing"))]
+extern crate tracing_core;
 
+#[cfg(feature = "brotli")]
+extern crate brotli;
+#[cfg(feature = "csrf")]
+extern crate getrandom;
+#[cfg(feature = "sessions")]
+extern crate hmac;
 extern crate http;
 extern crate httparse;
+#[cfg(all(unix, any(feature = "sendfile", test)))]
+extern crate libc;
 extern crate num_cpus;
-extern crate scoped_threadpool;
-extern crate time;
+#[cfg(feature = "sessions")]
+extern crate sha2;
+extern crate socket2;
 
 pub use http::method::Method;
 pub use http::response::Builder as ResponseBuilder;
@@ -38,53 +53,260 @@ pub use http::response::{Builder, Parts, Response};
 pub use http::status::{InvalidStatusCode, StatusCode};
 pub use http::Request;
 
-use scoped_threadpool::Pool;
-
-use std::env;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io;
 use std::io::prelude::*;
-use std::net::{TcpListener, TcpStream};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use std::any::Any;
 use std::borrow::Borrow;
 
+mod accept;
+mod auth;
+mod body;
+#[cfg(feature = "brotli")]
+mod compress;
+mod concurrency;
+mod conditional;
+mod config;
+#[cfg(feature = "csrf")]
+mod csrf;
+mod date;
+mod deadline;
+mod disposition;
+mod embedded;
 mod error;
+mod etag;
+mod file;
+mod interim;
+mod metrics;
+mod mime;
+mod normalize;
 mod parsing;
+mod pool;
+mod progress;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod range;
+mod reaper;
+mod reason;
 mod request;
+mod responder;
+#[cfg(feature = "sessions")]
+mod session;
+mod static_config;
+mod thread_state;
+mod timing;
+mod trace;
+mod upgrade;
+mod walk;
+mod watch;
 
-pub use error::Error;
+pub use auth::{bearer_token, require_bearer_token, BearerError};
+pub use body::Body;
+pub use concurrency::ConcurrencyModel;
+pub use conditional::check_preconditions;
+pub use config::ServerConfig;
+#[cfg(feature = "csrf")]
+pub use csrf::CsrfToken;
+pub use deadline::Deadline;
+pub use disposition::attachment;
+pub use error::{Error, FramingError};
+pub use interim::Interim;
+use metrics::Counted;
+pub use metrics::{Metrics, PoolStats};
+pub use progress::RequestHeadInfo;
+use reaper::{Registration, Registry, Watched};
+pub use reason::ReasonPhrase;
+pub use responder::Responder;
+#[cfg(feature = "sessions")]
+pub use session::{Session, SessionExt};
+pub use static_config::StaticConfigHandle;
+pub use thread_state::with_thread_state;
+pub use upgrade::Upgrade;
+
+use request::Connection;
 
 pub type ResponseResult = Result<Response<Vec<u8>>, Error>;
 
+/// A boxed handler that responds with a `Vec<u8>`.
+///
+/// Handlers passed to `Server::new` can respond with anything that turns into
+/// a `Body`, so this is only one kind of handler the server takes, not the one
+/// it keeps. It can still be passed to `Server::new` like any other.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate simple_server;
+///
+/// use simple_server::{Handler, Server};
+///
+/// fn main() {
+///     let handler: Handler = Box::new(|request, mut response| {
+///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+///     });
+///
+///     let server = Server::new(handler);
+///
+///     server.listen("127.0.0.1", "7979");
+/// }
+/// ```
 pub type Handler =
-    Box<Fn(Request<Vec<u8>>, ResponseBuilder) -> ResponseResult + 'static + Send + Sync>;
+    Box<dyn Fn(Request<Vec<u8>>, ResponseBuilder) -> ResponseResult + 'static + Send + Sync>;
+
+// What the server keeps a handler as, whatever its response body was.
+type BodyHandler = Box<
+    dyn Fn(Request<Vec<u8>>, ResponseBuilder) -> Result<Response<Body>, Error>
+        + 'static
+        + Send
+        + Sync,
+>;
 
 /// A web server.
 ///
 /// This is the core type of this crate, and is used to create a new
 /// server and listen for connections.
 pub struct Server {
-    handler: Handler,
+    handler: BodyHandler,
     timeout: Option<Duration>,
-    static_directory: Option<PathBuf>,
+    idle_timeout: Option<Duration>,
+    keep_alive_timeout: Duration,
+    max_requests_per_connection: usize,
+    max_request_line: usize,
+    max_request_size: u64,
+    drain_bytes: u64,
+    drain_time: Duration,
+    queue_depth: usize,
+    concurrency_model: Option<ConcurrencyModel>,
+    thread_state: Option<Box<thread_state::Init>>,
+    connections: Arc<Registry>,
+    metrics: Arc<Metrics>,
+    listen_backlog: Option<i32>,
+    nonblocking_accept: bool,
+    static_directories: StaticConfigHandle,
+    static_only: bool,
+    embedded_assets: HashMap<String, embedded::Asset>,
+    fallback_file: Option<PathBuf>,
+    error_pages: HashMap<StatusCode, PathBuf>,
+    index_files: Vec<PathBuf>,
+    redirect_directories: bool,
+    clean_urls: bool,
+    mime_types: HashMap<String, String>,
+    static_charset: Option<String>,
+    download_extensions: HashSet<String>,
+    allowed_methods: Option<Vec<Method>>,
+    disallowed_method_status: StatusCode,
+    answer_options_asterisk: bool,
+    debug_errors: bool,
+    content_etags: Option<etag::Etags>,
+    handler_ranges: bool,
+    default_content_type: Option<http::header::HeaderValue>,
+    dev_mode: bool,
+    slow_request_threshold: Option<Duration>,
+    parse_failure_hook: Option<Box<request::ParseFailureHook<'static>>>,
+    body_progress_hook: Option<Box<progress::BodyProgressHook<'static>>>,
+    body_progress_bytes: u64,
+    body_progress_interval: Duration,
+    #[cfg(feature = "brotli")]
+    compression_threshold: usize,
+    #[cfg(feature = "sessions")]
+    session_secret: Option<Arc<[u8; 32]>>,
+    #[cfg(feature = "sessions")]
+    session_max_age: Duration,
+    #[cfg(feature = "csrf")]
+    csrf_key: Option<[u8; 32]>,
+    #[cfg(feature = "csrf")]
+    csrf_exempt_paths: Vec<String>,
+    #[cfg(feature = "prometheus")]
+    metrics_endpoint: Option<String>,
+    #[cfg(feature = "prometheus")]
+    metrics_loopback_only: bool,
 }
 
 impl fmt::Debug for Server {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Server {{ timeout: {:?}, static_directory: {:?} }}",
-            self.timeout, self.static_directory
-        )
+        let mut debug = f.debug_struct("Server");
+        debug
+            .field("timeout", &self.timeout)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("keep_alive_timeout", &self.keep_alive_timeout)
+            .field(
+                "max_requests_per_connection",
+                &self.max_requests_per_connection,
+            )
+            .field("max_request_line", &self.max_request_line)
+            .field("max_request_size", &self.max_request_size)
+            .field("drain_bytes", &self.drain_bytes)
+            .field("drain_time", &self.drain_time)
+            .field("queue_depth", &self.queue_depth)
+            .field("concurrency_model", &self.concurrency_model)
+            .field("thread_state", &self.thread_state.is_some())
+            .field("metrics", &self.metrics)
+            .field("listen_backlog", &self.listen_backlog)
+            .field("nonblocking_accept", &self.nonblocking_accept)
+            .field("static_directories", &self.static_directories.current())
+            .field("static_only", &self.static_only)
+            .field("embedded_assets", &self.embedded_assets.len())
+            .field("fallback_file", &self.fallback_file)
+            .field("error_pages", &self.error_pages)
+            .field("index_files", &self.index_files)
+            .field("redirect_directories", &self.redirect_directories)
+            .field("clean_urls", &self.clean_urls)
+            .field("mime_types", &self.mime_types)
+            .field("static_charset", &self.static_charset)
+            .field("download_extensions", &self.download_extensions)
+            .field("allowed_methods", &self.allowed_methods)
+            .field("disallowed_method_status", &self.disallowed_method_status)
+            .field("answer_options_asterisk", &self.answer_options_asterisk)
+            .field("debug_errors", &self.debug_errors)
+            .field("content_etags", &self.content_etags.is_some())
+            .field("handler_ranges", &self.handler_ranges)
+            .field("default_content_type", &self.default_content_type)
+            .field("dev_mode", &self.dev_mode)
+            .field("slow_request_threshold", &self.slow_request_threshold)
+            .field("on_parse_failure", &self.parse_failure_hook.is_some())
+            .field("on_body_progress", &self.body_progress_hook.is_some())
+            .field("body_progress_bytes", &self.body_progress_bytes)
+            .field("body_progress_interval", &self.body_progress_interval);
+        #[cfg(feature = "brotli")]
+        debug.field("compression_threshold", &self.compression_threshold);
+        #[cfg(feature = "sessions")]
+        debug
+            .field(
+                "session_secret",
+                &self.session_secret.as_ref().map(|_| "..."),
+            )
+            .field("session_max_age", &self.session_max_age);
+        #[cfg(feature = "csrf")]
+        debug
+            .field("csrf_protection", &self.csrf_key.is_some())
+            .field("csrf_exempt_paths", &self.csrf_exempt_paths);
+        #[cfg(feature = "prometheus")]
+        debug
+            .field("metrics_endpoint", &self.metrics_endpoint)
+            .field("metrics_loopback_only", &self.metrics_loopback_only);
+        debug.finish()
     }
 }
 
 impl Server {
     /// Constructs a new server with the given handler.
     ///
-    /// The handler function is called on all requests.
+    /// The handler function is called on all requests. A request's URI
+    /// includes the scheme and the authority from its `Host` header, so
+    /// `request.uri().host()` can be used to build absolute URLs, unless the
+    /// `Host` is missing or invalid, in which case it's just the path and
+    /// query.
     ///
     /// # Errors
     ///
@@ -109,21 +331,117 @@ impl Server {
     ///     });
     /// }
     /// ```
-    pub fn new<H>(handler: H) -> Server
+    pub fn new<H, B>(handler: H) -> Server
     where
-        H: Fn(Request<Vec<u8>>, ResponseBuilder) -> ResponseResult + 'static + Send + Sync,
+        H: Fn(Request<Vec<u8>>, ResponseBuilder) -> Result<Response<B>, Error>
+            + 'static
+            + Send
+            + Sync,
+        B: Into<Body>,
     {
         Server {
-            handler: Box::new(handler),
+            handler: Box::new(move |request, response| {
+                handler(request, response).map(|response| response.map(Into::into))
+            }),
             timeout: None,
-            static_directory: Some(PathBuf::from("public")),
+            idle_timeout: None,
+            keep_alive_timeout: Duration::from_millis(DEFAULT_KEEP_ALIVE_MS),
+            max_requests_per_connection: 1000,
+            max_request_line: request::DEFAULT_MAX_REQUEST_LINE,
+            max_request_size: request::DEFAULT_MAX_REQUEST_SIZE,
+            drain_bytes: DEFAULT_DRAIN_BYTES,
+            drain_time: Duration::from_millis(DEFAULT_DRAIN_MS),
+            queue_depth: 128,
+            concurrency_model: None,
+            thread_state: None,
+            connections: Registry::new(),
+            metrics: Arc::new(Metrics::default()),
+            listen_backlog: None,
+            nonblocking_accept: false,
+            static_directories: StaticConfigHandle::new(vec![PathBuf::from("public")]),
+            static_only: false,
+            embedded_assets: HashMap::new(),
+            fallback_file: None,
+            error_pages: [
+                (StatusCode::NOT_FOUND, "404.html"),
+                (StatusCode::PAYLOAD_TOO_LARGE, "413.html"),
+                (StatusCode::INTERNAL_SERVER_ERROR, "500.html"),
+            ]
+            .iter()
+            .map(|&(status, page)| (status, PathBuf::from(page)))
+            .collect(),
+            index_files: vec![PathBuf::from("index.html")],
+            redirect_directories: true,
+            clean_urls: false,
+            mime_types: HashMap::new(),
+            static_charset: Some(String::from("utf-8")),
+            download_extensions: HashSet::new(),
+            allowed_methods: None,
+            disallowed_method_status: StatusCode::NOT_IMPLEMENTED,
+            answer_options_asterisk: true,
+            debug_errors: false,
+            content_etags: None,
+            handler_ranges: false,
+            default_content_type: None,
+            dev_mode: false,
+            slow_request_threshold: None,
+            parse_failure_hook: None,
+            body_progress_hook: None,
+            body_progress_bytes: progress::DEFAULT_PROGRESS_BYTES,
+            body_progress_interval: progress::DEFAULT_PROGRESS_INTERVAL,
+            #[cfg(feature = "brotli")]
+            compression_threshold: 1024,
+            #[cfg(feature = "sessions")]
+            session_secret: None,
+            #[cfg(feature = "sessions")]
+            session_max_age: Duration::from_secs(24 * 60 * 60),
+            #[cfg(feature = "csrf")]
+            csrf_key: None,
+            #[cfg(feature = "csrf")]
+            csrf_exempt_paths: Vec::new(),
+            #[cfg(feature = "prometheus")]
+            metrics_endpoint: None,
+            #[cfg(feature = "prometheus")]
+            metrics_loopback_only: false,
         }
     }
 
+    /// Constructs a new server with a handler that returns anything that
+    /// implements `Responder`, rather than building a `Response` itself.
+    ///
+    /// This suits handlers that mostly send text or a bare status, and works
+    /// just like `new` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{Method, Server, StatusCode};
+    ///
+    /// fn main() {
+    ///     let server = Server::new_with_responder(|request| {
+    ///         match (request.method(), request.uri().path()) {
+    ///             (&Method::GET, "/") => (StatusCode::OK, "Hello, world!"),
+    ///             _ => (StatusCode::NOT_FOUND, "Not found!"),
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn new_with_responder<H, R>(handler: H) -> Server
+    where
+        H: Fn(Request<Vec<u8>>) -> R + 'static + Send + Sync,
+        R: Responder,
+    {
+        Server::new(move |request, _| handler(request).into_response())
+    }
+
     /// Constructs a new server with the given handler and the specified request
     /// timeout.
     ///
-    /// The handler function is called on all requests.
+    /// The handler function is called on all requests. Each one carries a
+    /// `Deadline` in its extensions, saying how much of the timeout is left
+    /// once it's been read.
     ///
     /// # Errors
     ///
@@ -149,15 +467,169 @@ impl Server {
     ///     });
     /// }
     /// ```
-    pub fn with_timeout<H>(timeout: Duration, handler: H) -> Server
+    pub fn with_timeout<H, B>(timeout: Duration, handler: H) -> Server
     where
-        H: Fn(Request<Vec<u8>>, ResponseBuilder) -> ResponseResult + 'static + Send + Sync,
+        H: Fn(Request<Vec<u8>>, ResponseBuilder) -> Result<Response<B>, Error>
+            + 'static
+            + Send
+            + Sync,
+        B: Into<Body>,
     {
-        Server {
-            handler: Box::new(handler),
-            timeout: Some(timeout),
-            static_directory: Some(PathBuf::from("public")),
+        let mut server = Server::new(handler);
+        server.timeout = Some(timeout);
+        server
+    }
+
+    /// Constructs a new server with the settings in `config`, and the same
+    /// defaults as `new` for everything it leaves out.
+    ///
+    /// See `ServerConfig` for the settings, and `ServerConfig::from_env` for
+    /// reading them from environment variables.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{Server, ServerConfig};
+    ///
+    /// fn main() {
+    ///     let config = ServerConfig {
+    ///         static_dir: Some("assets".into()),
+    ///         ..ServerConfig::default()
+    ///     };
+    ///
+    ///     let server = Server::from_config(config, |request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    /// }
+    /// ```
+    pub fn from_config<H, B>(config: ServerConfig, handler: H) -> Server
+    where
+        H: Fn(Request<Vec<u8>>, ResponseBuilder) -> Result<Response<B>, Error>
+            + 'static
+            + Send
+            + Sync,
+        B: Into<Body>,
+    {
+        let mut server = Server::new(handler);
+        server.timeout = config.request_timeout;
+
+        if let Some(size) = config.pool_size {
+            server.set_concurrency_model(ConcurrencyModel::Pool { size });
+        }
+        if let Some(depth) = config.queue_depth {
+            server.set_queue_depth(depth);
         }
+        if let Some(idle_timeout) = config.idle_timeout {
+            server.set_idle_timeout(idle_timeout);
+        }
+        if let Some(max_requests) = config.max_requests_per_connection {
+            server.set_max_requests_per_connection(max_requests);
+        }
+        if let Some(max_length) = config.max_request_line_length {
+            server.set_max_request_line_length(max_length);
+        }
+        if let Some(backlog) = config.listen_backlog {
+            server.set_listen_backlog(backlog);
+        }
+        if let Some(static_dir) = config.static_dir {
+            server.set_static_directory(static_dir);
+        }
+        if let Some(dev_mode) = config.dev_mode {
+            server.set_dev_mode(dev_mode);
+        }
+        if let Some(threshold) = config.slow_request_threshold {
+            server.set_slow_request_threshold(threshold);
+        }
+
+        server
+    }
+
+    /// Constructs a new server that redirects every request to another origin.
+    ///
+    /// Each request is answered with a `301 Moved Permanently`, pointing at the
+    /// same path and query string under `target_base_url`. This is useful for
+    /// sending plain HTTP visitors over to HTTPS. Static files aren't served,
+    /// and no handler is ever called.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let server = Server::redirect("https://example.com");
+    ///
+    ///     server.listen("0.0.0.0", "80");
+    /// }
+    /// ```
+    pub fn redirect<S: Into<String>>(target_base_url: S) -> Server {
+        let target_base_url = target_base_url.into();
+
+        let mut server = Server::new(move |request, mut response| {
+            let location = redirect_location(&target_base_url, request.uri());
+
+            response.status(StatusCode::MOVED_PERMANENTLY);
+            response.header(http::header::LOCATION, location.as_str());
+            Ok(response.body(Vec::new())?)
+        });
+        server.dont_serve_static_files();
+        server
+    }
+
+    /// Constructs a new server that serves the static files in `path`, and
+    /// nothing else.
+    ///
+    /// Requests that don't match a file get a `404 Not Found`, or the page
+    /// set for it with `set_error_page`. Everything else about serving
+    /// static files works as it does with a handler: index files, content
+    /// types, entity tags and the rest can all be set up as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let server = Server::static_files("/var/www/");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn static_files<P: Into<PathBuf>>(path: P) -> Server {
+        let mut server = Server::new(|_request, mut response| {
+            response.status(StatusCode::NOT_FOUND);
+            Ok(response.body(Vec::new())?)
+        });
+        server.set_static_directory(path);
+        server.static_only = true;
+        server
+    }
+
+    /// Listens on a specified host and port, redirecting every request to
+    /// another origin.
+    ///
+    /// This is a shorthand for `Server::redirect(target_base_url).listen(host,
+    /// port)`; see those methods for details.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     Server::listen_redirect("0.0.0.0", "80", "https://example.com");
+    /// }
+    /// ```
+    pub fn listen_redirect(host: &str, port: &str, target_base_url: &str) -> ! {
+        Server::redirect(target_base_url).listen(host, port)
     }
 
     /// Tells the server to listen on a specified host and port.
@@ -182,15 +654,17 @@ impl Server {
     /// There are several circumstances in which `listen` can currently panic:
     ///
     /// * If there's an error [constructing a TcpListener][constructing], generally if the port
-    ///    or host is incorrect. See `TcpListener`'s docs for more.
-    /// * If the connection fails, see [`incoming`'s docs] for more.
+    ///   or host is incorrect. See `TcpListener`'s docs for more.
+    ///
+    /// A panic while handling a connection, whether in the handler or from an
+    /// error reading the stream, only takes down that connection. The thread
+    /// that was handling it goes on to the next one.
     ///
-    /// Finally, if reading from the stream fails. Timeouts and connection closes
-    /// are handled, other errors may result in a panic. This will only take down
-    /// one of the threads in the threadpool, rather than the whole server.
+    /// Errors accepting a connection don't panic. If the process runs out of
+    /// file descriptors, the server pauses briefly before accepting again,
+    /// and counts it in `Metrics::exhausted_accepts`.
     ///
     /// [constructing]: https://doc.rust-lang.org/std/net/struct.TcpListener.html#method.bind
-    /// [`incoming`'s docs]: https://doc.rust-lang.org/std/net/struct.TcpListener.html#method.incoming
     ///
     /// # Examples
     ///
@@ -208,8 +682,7 @@ impl Server {
     /// }
     /// ```
     pub fn listen(&self, host: &str, port: &str) -> ! {
-        let listener =
-            TcpListener::bind(format!("{}:{}", host, port)).expect("Error starting the server.");
+        let listener = self.bind(host, port).expect("Error starting the server.");
 
         info!("Server started at http://{}:{}", host, port);
 
@@ -247,34 +720,172 @@ impl Server {
     /// }
     /// ```
     pub fn listen_on_socket(&self, listener: TcpListener) -> ! {
-        const READ_TIMEOUT_MS: u64 = 20;
-        let num_threads = self.pool_size();
-        let mut pool = Pool::new(num_threads);
-        let mut incoming = listener.incoming();
+        self.listen_on_sockets(vec![listener])
+    }
 
-        loop {
-            // Incoming is an endless iterator, so it's okay to unwrap on it.
-            let stream = incoming.next().unwrap();
-            let stream = stream.expect("Error handling TCP stream.");
-
-            stream
-                .set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))
-                .expect("FATAL: Couldn't set read timeout on socket");
-
-            pool.scoped(|scope| {
-                scope.execute(|| {
-                    self.handle_connection(stream)
-                        .expect("Error handling connection.");
-                });
+    /// Tells the server to listen on several provided `TcpListener`s at once.
+    ///
+    /// Each listener gets a thread of its own to accept connections on, and
+    /// they all feed the same threadpool. Apart from that, this works just like
+    /// `listen_on_socket`: the same handler, static files, and settings apply
+    /// to connections from every listener.
+    ///
+    /// This method blocks forever.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `listeners` is empty, as there would be nothing to
+    /// wait for.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::net::TcpListener;
+    ///
+    /// fn main() {
+    ///     let local = TcpListener::bind(("127.0.0.1", 7979)).expect("Error starting the server.");
+    ///     let public = TcpListener::bind(("0.0.0.0", 8080)).expect("Error starting the server.");
+    ///
+    ///     let server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.listen_on_sockets(vec![local, public]);
+    /// }
+    /// ```
+    pub fn listen_on_sockets(&self, listeners: Vec<TcpListener>) -> ! {
+        const WATCH_INTERVAL_MS: u64 = 1000;
+        let (sender, incoming) = mpsc::channel();
+        let (refuse, refusals) = mpsc::sync_channel(REFUSAL_BACKLOG);
+
+        self.refresh_content_etags();
+
+        if self.dev_mode {
+            let roots = self.static_directories.clone();
+
+            spawn_named(String::from("simple-server-watcher"), move || {
+                let mut before = watch::scan(&roots.current());
+                loop {
+                    thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+                    let after = watch::scan(&roots.current());
+                    for (change, path) in watch::changes(&before, &after) {
+                        info!("{:?}: {}", change, path.display());
+                    }
+                    before = after;
+                }
+            });
+        }
+
+        for (i, listener) in listeners.into_iter().enumerate() {
+            let sender = sender.clone();
+            let metrics = self.metrics.clone();
+            let name = listener
+                .local_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|_| String::from("<unknown>"));
+
+            spawn_named(format!("simple-server-accept-{}", i + 1), move || {
+                let mut reserve = accept::Reserve::new();
+                let mut exhausted = false;
+
+                loop {
+                    let error = match listener.accept() {
+                        // Some platforms hand out non-blocking streams from
+                        // non-blocking listeners, but connections are handled
+                        // with blocking I/O.
+                        Ok((stream, _)) => match stream.set_nonblocking(false) {
+                            Ok(()) => {
+                                exhausted = false;
+                                if sender.send((stream, name.clone())).is_err() {
+                                    break;
+                                }
+                                continue;
+                            }
+                            Err(e) => e,
+                        },
+                        Err(e) => e,
+                    };
+
+                    let failure = accept::Failure::classify(&error);
+                    match failure {
+                        accept::Failure::WouldBlock | accept::Failure::Transient => {}
+                        accept::Failure::Exhausted => {
+                            metrics.accept_exhausted();
+                            // Only the start of a run is worth a warning, as
+                            // it goes on for as long as the shortage does.
+                            if !exhausted {
+                                warn!("Out of resources accepting on {}: {}", name, error);
+                            }
+                            exhausted = true;
+                            if reserve.shed(&listener) {
+                                debug!("Turned away a connection on {}", name);
+                            }
+                        }
+                        accept::Failure::Other => {
+                            warn!("Error accepting on {}: {}", name, error);
+                        }
+                    }
+                    thread::sleep(failure.delay());
+                }
+            });
+        }
+        drop(sender);
+
+        if let Some(idle_timeout) = self.idle_timeout {
+            let connections = self.connections.clone();
+            let interval = cmp::max(idle_timeout / 4, Duration::from_millis(10));
+
+            spawn_named(String::from("simple-server-reaper"), move || loop {
+                thread::sleep(interval);
+                let reaped = connections.sweep(idle_timeout);
+                if reaped > 0 {
+                    debug!("Reaping {} idle connection(s)", reaped);
+                }
             });
         }
+
+        // Turning a client away is quick, but waiting for it to finish
+        // sending its request isn't, so that's left to a thread of its own.
+        spawn_named(String::from("simple-server-refuser"), move || {
+            for stream in refusals {
+                refuse_overloaded(stream);
+            }
+        });
+
+        match self.concurrency_model {
+            Some(ConcurrencyModel::Pool { size }) => {
+                self.dispatch_to_pool(size, &incoming, &refuse)
+            }
+            Some(ConcurrencyModel::ThreadPerConnection { max_threads }) => {
+                self.dispatch_to_threads(max_threads, &incoming, &refuse)
+            }
+            None => self.dispatch_to_pool(self.pool_size(), &incoming, &refuse),
+        }
     }
 
-    /// Sets the proper directory for serving static files.
+    /// Waits for a single connection on `listener` and handles it on the
+    /// calling thread, returning once it's closed.
     ///
-    /// By default, the server will serve static files inside a `public`
-    /// directory. This method lets you set a path to whatever location
-    /// you'd like.
+    /// This is for when a server is only needed for a moment, like catching
+    /// the redirect at the end of an OAuth flow in a command line tool, or a
+    /// test that wants to know exactly when the server's done. Everything
+    /// but the threads works the way it does for `listen_on_socket`: the
+    /// same handler, static files, timeouts and error responses apply. There's
+    /// no pool, and nothing is left running in the background afterwards.
+    ///
+    /// The connection is served until the client closes it, which a browser
+    /// keeping it alive may not do for a while. A server made with
+    /// `Server::with_timeout` gives up on a connection that's been idle for
+    /// that long.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if accepting the connection failed, or if the
+    /// connection failed while it was being handled. A panicking handler
+    /// isn't caught, so the panic carries on up the calling thread.
     ///
     /// # Examples
     ///
@@ -282,28 +893,39 @@ impl Server {
     /// extern crate simple_server;
     ///
     /// use simple_server::Server;
+    /// use std::net::TcpListener;
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
     ///
     /// fn main() {
-    ///     let mut server = Server::new(|request, mut response| {
-    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
-    ///     });
+    ///     let listener = TcpListener::bind(("127.0.0.1", 7979))
+    ///         .expect("Error starting the server.");
     ///
-    ///     server.set_static_directory("/var/www/");
+    ///     let (sender, codes) = mpsc::channel();
+    ///     let server = Server::with_timeout(Duration::from_secs(5), move |request, mut response| {
+    ///         let query = request.uri().query().unwrap_or("").to_string();
+    ///         let _ = sender.send(query);
+    ///         Ok(response.body("You can close this window now.".as_bytes().to_vec())?)
+    ///     });
     ///
-    ///     server.listen("127.0.0.1", "7979");
+    ///     server.accept_one(&listener).expect("Error handling the redirect.");
+    ///     println!("Got {}", codes.recv().unwrap());
     /// }
     /// ```
-    pub fn set_static_directory<P: Into<PathBuf>>(&mut self, path: P) {
-        self.static_directory = Some(path.into());
+    pub fn accept_one(&self, listener: &TcpListener) -> Result<(), Error> {
+        self.refresh_content_etags();
+        self.serve_next(listener)
     }
 
-    /// Disables serving static files.
+    /// Handles `n` connections on `listener`, one after the other, on the
+    /// calling thread, returning once the last of them is closed.
     ///
-    /// By default, the server will serve static files inside a `public`
-    /// directory, or the directory set by `set_static_directory`. This
-    /// method lets you disable this.
+    /// This works just like calling `accept_one` `n` times.
     ///
-    /// It can be re-enabled by a subsequent call to `set_static_directory`.
+    /// # Errors
+    ///
+    /// This stops at the first connection that fails, as `accept_one` would,
+    /// and returns its error.
     ///
     /// # Examples
     ///
@@ -311,160 +933,2898 @@ impl Server {
     /// extern crate simple_server;
     ///
     /// use simple_server::Server;
+    /// use std::net::TcpListener;
     ///
     /// fn main() {
-    ///     let mut server = Server::new(|request, mut response| {
+    ///     let listener = TcpListener::bind(("127.0.0.1", 7979))
+    ///         .expect("Error starting the server.");
+    ///
+    ///     let server = Server::new(|request, mut response| {
     ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
     ///     });
     ///
-    ///     server.dont_serve_static_files();
-    ///
-    ///     server.listen("127.0.0.1", "7979");
+    ///     server.serve_n(&listener, 3).expect("Error handling a connection.");
     /// }
     /// ```
-    pub fn dont_serve_static_files(&mut self) {
-        self.static_directory = None;
+    pub fn serve_n(&self, listener: &TcpListener, n: usize) -> Result<(), Error> {
+        self.refresh_content_etags();
+        for _ in 0..n {
+            self.serve_next(listener)?;
+        }
+        Ok(())
     }
 
-    // Try and fetch the environment variable SIMPLESERVER_THREADS and parse it as a u32.
-    // If this fails we fall back to using the num_cpus crate.
-    fn pool_size(&self) -> u32 {
-        const NUM_THREADS: &str = "SIMPLESERVER_THREADS";
-        let logical_cores = num_cpus::get() as u32;
+    // Accepts a connection and handles it on the current thread.
+    fn serve_next(&self, listener: &TcpListener) -> Result<(), Error> {
+        let (stream, peer) = listener.accept()?;
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))?;
 
-        match env::var(NUM_THREADS) {
-            Ok(v) => v.parse::<u32>().unwrap_or(logical_cores),
-            Err(_) => logical_cores,
-        }
+        let _state = self.install_thread_state();
+        let _busy = self.metrics.busy();
+        let mut current = None;
+        trace::connection(Some(peer)).in_scope(|| self.handle_connection(stream, &mut current))
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) -> Result<(), Error> {
-        let request = match request::read(&mut stream, self.timeout) {
-            Err(Error::ConnectionClosed) | Err(Error::Timeout) | Err(Error::HttpParse(_)) => {
-                return Ok(())
-            }
+    // Hands connections to a pool of `size` threads through a queue, turning
+    // them away when the queue is full.
+    fn dispatch_to_pool(
+        &self,
+        size: usize,
+        incoming: &mpsc::Receiver<(TcpStream, String)>,
+        refuse: &mpsc::SyncSender<TcpStream>,
+    ) -> ! {
+        let (work, queue) = mpsc::sync_channel::<TcpStream>(self.queue_depth);
+        let queue = Mutex::new(queue);
 
-            Err(Error::RequestTooLarge) => {
-                let resp = Response::builder()
-                    .status(StatusCode::PAYLOAD_TOO_LARGE)
-                    .body("<h1>413</h1><p>Request too large!<p>".as_bytes())
-                    .unwrap();
-                write_response(resp, stream)?;
-                return Ok(());
-            }
+        thread::scope(|scope| {
+            for i in 0..size {
+                let queue = &queue;
+                thread::Builder::new()
+                    .name(format!("simple-server-worker-{}", i + 1))
+                    .spawn_scoped(scope, move || {
+                        let _state = self.install_thread_state();
 
-            Err(e) => return Err(e),
+                        loop {
+                            // The lock is only held while waiting for a
+                            // connection, and nothing in between can panic.
+                            let next = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                            match next {
+                                Ok(stream) => {
+                                    self.metrics.connection_dequeued();
+                                    self.serve(stream);
+                                }
+                                Err(_) => return,
+                            }
+                        }
+                    })
+                    .expect("FATAL: Couldn't spawn a worker thread");
+            }
 
-            Ok(r) => r,
-        };
+            loop {
+                let (stream, listener) = next_connection(incoming);
 
-        let mut response_builder = Response::builder();
+                // When every worker is busy and the queue is full, the
+                // connection is answered with a 503 straight away rather than
+                // left waiting behind all the others.
+                self.metrics.connection_queued();
+                if let Err(mpsc::TrySendError::Full(stream)) = work.try_send(stream) {
+                    self.metrics.connection_dequeued();
+                    self.refuse(stream, &listener, refuse);
+                }
+            }
+        });
 
-        // first, we serve static files
-        if let Some(ref static_directory) = self.static_directory {
-            let fs_path = request.uri().to_string();
+        unreachable!("The server stopped dispatching connections.")
+    }
 
-            // the uri always includes a leading /, which means that join will over-write the static directory...
-            let fs_path = PathBuf::from(&fs_path[1..]);
+    // Spawns a thread for every connection, turning connections away once
+    // `max_threads` of them are running.
+    fn dispatch_to_threads(
+        &self,
+        max_threads: Option<usize>,
+        incoming: &mpsc::Receiver<(TcpStream, String)>,
+        refuse: &mpsc::SyncSender<TcpStream>,
+    ) -> ! {
+        // Only this thread adds to the count, so it can't go over the limit
+        // between checking it and spawning.
+        let running = AtomicUsize::new(0);
+        let mut spawned = 0_u64;
 
-            // ... you trying to do something bad?
-            let traversal_attempt = fs_path.components().any(|component| match component {
-                std::path::Component::Normal(_) => false,
-                _ => true,
-            });
+        thread::scope(|scope| loop {
+            let (stream, listener) = next_connection(incoming);
 
-            if traversal_attempt {
-                // GET OUT
-                response_builder.status(StatusCode::NOT_FOUND);
+            if max_threads.is_some_and(|max| running.load(Ordering::SeqCst) >= max) {
+                self.refuse(stream, &listener, refuse);
+                continue;
+            }
 
-                let response = response_builder
-                    .body("<h1>404</h1><p>Not found!<p>".as_bytes())
-                    .unwrap();
+            spawned += 1;
+            running.fetch_add(1, Ordering::SeqCst);
+            let running = &running;
+            let thread = thread::Builder::new()
+                .name(format!("simple-server-connection-{}", spawned))
+                .spawn_scoped(scope, move || {
+                    let _state = self.install_thread_state();
+                    self.serve(stream);
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
 
-                write_response(response, stream)?;
-                return Ok(());
+            if let Err(e) = thread {
+                running.fetch_sub(1, Ordering::SeqCst);
+                warn!(
+                    "Couldn't spawn a thread for a connection on {}: {}",
+                    listener, e
+                );
             }
+        });
 
-            let fs_path = static_directory.join(fs_path);
-
-            if Path::new(&fs_path).is_file() {
-                let mut f = File::open(&fs_path)?;
+        unreachable!("The server stopped dispatching connections.")
+    }
 
-                let mut source = Vec::new();
+    // Sets up the state for a thread that's about to handle connections, if
+    // there's any to set up, until the returned guard is dropped.
+    fn install_thread_state(&self) -> Option<thread_state::Installed> {
+        self.thread_state.as_deref().map(thread_state::install)
+    }
 
-                f.read_to_end(&mut source)?;
+    // Handles a connection from start to finish. A panicking handler takes its
+    // connection down with it, but nothing else, and the panic is logged along
+    // with the request that caused it.
+    fn serve(&self, stream: TcpStream) {
+        let _busy = self.metrics.busy();
+        let peer = stream.peer_addr().ok();
+        let mut current = None;
 
-                let response = response_builder.body(source)?;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            trace::connection(peer).in_scope(|| self.handle_connection(stream, &mut current))
+        }));
 
-                write_response(response, stream)?;
-                return Ok(());
-            }
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => trace::connection_failed(&e),
+            Err(panic) => trace::panicked(&trace::Panic {
+                thread: thread::current().name().unwrap_or("<unnamed>"),
+                message: panic_message(&*panic),
+                peer,
+                request: current.as_deref(),
+            }),
         }
+    }
 
-        match (self.handler)(request, response_builder) {
-            Ok(response) => Ok(write_response(response, stream)?),
-            Err(_) => {
-                let mut response_builder = Response::builder();
-                response_builder.status(StatusCode::INTERNAL_SERVER_ERROR);
-
-                let response = response_builder
-                    .body("<h1>500</h1><p>Internal Server Error!<p>".as_bytes())
-                    .unwrap();
-
-                Ok(write_response(response, stream)?)
-            }
+    // Turns a connection away with a 503, as the server has no room for it.
+    fn refuse(&self, stream: TcpStream, listener: &str, refuse: &mpsc::SyncSender<TcpStream>) {
+        self.metrics.connection_overloaded();
+        if refuse.try_send(stream).is_err() {
+            debug!("Too busy to even refuse a connection on {}", listener);
         }
     }
-}
-
-fn write_response<T: Borrow<[u8]>, S: Write>(
-    response: Response<T>,
-    mut stream: S,
-) -> Result<(), Error> {
-    use fmt::Write;
 
-    let (parts, body) = response.into_parts();
-    let body: &[u8] = body.borrow();
-
-    let mut text = format!(
-        "HTTP/1.1 {} {}\r\n",
-        parts.status.as_str(),
-        parts
-            .status
-            .canonical_reason()
-            .expect("Unsupported HTTP Status"),
-    );
-
-    if !parts.headers.contains_key(http::header::DATE) {
-        let date = time::strftime("%a, %d %b %Y %H:%M:%S GMT", &time::now_utc()).unwrap();
-        write!(text, "date: {}\r\n", date).unwrap();
-    }
-    if !parts.headers.contains_key(http::header::CONNECTION) {
-        write!(text, "connection: close\r\n").unwrap();
-    }
-    if !parts.headers.contains_key(http::header::CONTENT_LENGTH) {
-        write!(text, "content-length: {}\r\n", body.len()).unwrap();
-    }
-    for (k, v) in parts.headers.iter() {
-        write!(text, "{}: {}\r\n", k.as_str(), v.to_str().unwrap()).unwrap();
+    /// Sets how connections are spread over threads.
+    ///
+    /// By default, they're handled by a pool with a thread for every logical
+    /// core, or as many as the `SIMPLESERVER_THREADS` environment variable
+    /// says. See `ConcurrencyModel` for the alternative.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{ConcurrencyModel, Server};
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_concurrency_model(ConcurrencyModel::ThreadPerConnection {
+    ///         max_threads: Some(64),
+    ///     });
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_concurrency_model(&mut self, model: ConcurrencyModel) {
+        self.concurrency_model = Some(model);
     }
 
-    write!(text, "\r\n").unwrap();
-
-    stream.write(text.as_bytes())?;
-    stream.write(body)?;
-    Ok(stream.flush()?)
-}
-
-#[test]
-fn test_write_response() {
-    let mut builder = http::response::Builder::new();
-    builder.status(http::StatusCode::OK);
-    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
-    builder.header(http::header::CONTENT_TYPE, "text/plain".as_bytes());
-
+    /// Gives every thread that handles connections some state of its own,
+    /// made by calling `init` on the thread before it handles any.
+    ///
+    /// Handlers get at it with `with_thread_state`. As each thread has its
+    /// own, the state doesn't need to be `Sync`, which suits things like
+    /// database connections. The state is dropped when its thread stops
+    /// handling connections. With `ConcurrencyModel::ThreadPerConnection`,
+    /// that's after every connection, so each one gets fresh state.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{with_thread_state, Server};
+    ///
+    /// struct Database;
+    ///
+    /// impl Database {
+    ///     fn connect() -> Database {
+    ///         Database
+    ///     }
+    ///
+    ///     fn greeting(&mut self) -> String {
+    ///         String::from("Hello, world!")
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         let greeting = with_thread_state(|db: &mut Database| db.greeting());
+    ///         Ok(response.body(greeting.unwrap_or_default().into_bytes())?)
+    ///     });
+    ///
+    ///     server.set_thread_state(Database::connect);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_thread_state<T, F>(&mut self, init: F)
+    where
+        T: 'static,
+        F: Fn() -> T + 'static + Send + Sync,
+    {
+        self.thread_state = Some(Box::new(move || Box::new(init())));
+    }
+
+    /// Sets how many accepted connections may wait for a worker thread.
+    ///
+    /// Connections are handed to the threadpool through a queue. When every
+    /// thread is busy and the queue is full, new connections are answered with
+    /// a `503 Service Unavailable` and closed, and counted in
+    /// `Metrics::overloaded_connections`, so that an overloaded server tells
+    /// clients so quickly instead of keeping them waiting. With a depth of
+    /// zero, connections are only accepted when a thread is free to take them.
+    /// By default, 128 connections may wait.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_queue_depth(16);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_queue_depth(&mut self, depth: usize) {
+        self.queue_depth = depth;
+    }
+
+    /// Sets how long a connection may sit without sending anything before
+    /// it's closed.
+    ///
+    /// Clients that connect and then go quiet would otherwise tie up a worker
+    /// thread for as long as they like. Idle connections are swept up in the
+    /// background, and counted in `Metrics::reaped_connections`. By default,
+    /// they're left alone.
+    ///
+    /// This is separate from the timeout set with `with_timeout`, which
+    /// limits how long a whole request may take to arrive, however busy the
+    /// client is.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_idle_timeout(Duration::from_secs(30));
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = Some(idle_timeout);
+    }
+
+    /// Sets how long a kept-alive connection is held open, once a response
+    /// has been sent, for the client to start on its next request.
+    ///
+    /// Browsers keep connections open long after they're done with them, and
+    /// each one ties up a worker thread while it's waiting, so it's closed if
+    /// nothing arrives in time. By default, that's five seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_keep_alive_timeout(Duration::from_secs(15));
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_keep_alive_timeout(&mut self, keep_alive_timeout: Duration) {
+        self.keep_alive_timeout = keep_alive_timeout;
+    }
+
+    /// Sets how many requests a client may send over one kept-alive
+    /// connection before it's closed.
+    ///
+    /// The response to the last request says `Connection: close`, and any
+    /// requests the client pipelined after it go unanswered, so that it has
+    /// to connect again and take its turn with everyone else. The count
+    /// starts over with each connection. By default, the limit is 1000, and
+    /// a limit of 0 is treated as 1.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_max_requests_per_connection(100);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_max_requests_per_connection(&mut self, max_requests: usize) {
+        self.max_requests_per_connection = max_requests;
+    }
+
+    /// Sets the longest request line, the method, target and version that
+    /// start a request, that the server accepts.
+    ///
+    /// A client sending a longer one gets a `414 URI Too Long` as soon as
+    /// that much has arrived, without the server waiting for, or buffering,
+    /// the rest of it. The length doesn't include the line break. By
+    /// default, the limit is 8 KB.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_max_request_line_length(2048);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_max_request_line_length(&mut self, max_length: usize) {
+        self.max_request_line = max_length;
+    }
+
+    /// Sets the most bytes a request can take up, its head and body
+    /// together, that the server accepts.
+    ///
+    /// A client sending a bigger one gets a `413 Payload Too Large`. One whose
+    /// head alone is too big is turned away as soon as that much has arrived,
+    /// and one whose `Content-Length` says its body is too big is turned away
+    /// before any of the body is read. A chunked body, whose length isn't
+    /// known up front, is turned away once too much of it has arrived. By
+    /// default, the limit is 16 MB.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_max_request_size(1024 * 1024);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_max_request_size(&mut self, max_size: u64) {
+        self.max_request_size = max_size;
+    }
+
+    /// Sets how much of a request that's turned away early the server reads,
+    /// and for how long, before closing the connection.
+    ///
+    /// When the server answers a request with an error before reading all of
+    /// it, like a `417 Expectation Failed` for a large upload, the client may
+    /// still be sending its body. Closing the connection with that unread
+    /// makes it reset, and the client can lose the response before reading
+    /// it. So the server reads what the client is still sending and throws
+    /// it away, up to `max_bytes` of it or for up to `max_time`, whichever
+    /// runs out first, and closes the connection after that. The same goes
+    /// for a request whose body the server can't find the end of. By
+    /// default, it's 1 MB or one second.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_early_response_drain(8 * 1024 * 1024, Duration::from_secs(5));
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_early_response_drain(&mut self, max_bytes: u64, max_time: Duration) {
+        self.drain_bytes = max_bytes;
+        self.drain_time = max_time;
+    }
+
+    /// Counters describing what the server has been up to.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let server = Arc::new(Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     }));
+    ///
+    ///     let listening = server.clone();
+    ///     thread::spawn(move || listening.listen("127.0.0.1", "7979"));
+    ///
+    ///     loop {
+    ///         thread::sleep(Duration::from_secs(60));
+    ///         println!("reaped: {}", server.metrics().reaped_connections());
+    ///     }
+    /// }
+    /// ```
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns how busy the server's threads are right now, and have been at
+    /// their busiest.
+    ///
+    /// A pool whose workers are often all busy, or with connections queued
+    /// for them, is too small for its load.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let server = Arc::new(Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     }));
+    ///
+    ///     let listening = server.clone();
+    ///     thread::spawn(move || listening.listen("127.0.0.1", "7979"));
+    ///
+    ///     loop {
+    ///         thread::sleep(Duration::from_secs(60));
+    ///         let stats = server.pool_stats();
+    ///         println!("busy: {} (peak {})", stats.busy_workers, stats.peak_busy_workers);
+    ///     }
+    /// }
+    /// ```
+    pub fn pool_stats(&self) -> PoolStats {
+        self.metrics.pool_stats()
+    }
+
+    /// Sets the size of the queue of connections waiting to be accepted by
+    /// `listen`.
+    ///
+    /// When a burst of connections arrives faster than they can be accepted,
+    /// the ones that don't fit in the queue are refused. The default is 128,
+    /// and the operating system may cap whatever is set here.
+    ///
+    /// This has no effect on listeners passed to `listen_on_socket`, which
+    /// have their backlog set when they're created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_listen_backlog(1024);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_listen_backlog(&mut self, backlog: i32) {
+        self.listen_backlog = Some(backlog);
+    }
+
+    /// Sets whether the socket created by `listen` is put into non-blocking
+    /// mode.
+    ///
+    /// Connections are accepted by polling the socket instead of waiting on
+    /// it, so that accepting can be interrupted. Connections themselves are
+    /// still handled with blocking I/O. It's off by default.
+    ///
+    /// Listeners passed to `listen_on_socket` may be blocking or not, as the
+    /// caller made them.
+    pub fn set_nonblocking_accept(&mut self, nonblocking: bool) {
+        self.nonblocking_accept = nonblocking;
+    }
+
+    /// Sets the proper directory for serving static files.
+    ///
+    /// By default, the server will serve static files inside a `public`
+    /// directory. This method lets you set a path to whatever location
+    /// you'd like. It replaces any directories added with
+    /// `add_static_directory`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_static_directory("/var/www/");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_static_directory<P: Into<PathBuf>>(&mut self, path: P) {
+        self.static_directories.set_directory(path);
+    }
+
+    /// Adds another directory to serve static files from.
+    ///
+    /// Static directories are searched in the order they were set up in, and
+    /// the first one containing the requested file wins. This lets a small
+    /// directory of overrides sit on top of the main one, for example a
+    /// deployment-specific `robots.txt`:
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_static_directory("/etc/my-site/overrides");
+    ///     server.add_static_directory("/var/www/");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn add_static_directory<P: Into<PathBuf>>(&mut self, path: P) {
+        self.static_directories.add(path.into());
+    }
+
+    /// Disables serving static files.
+    ///
+    /// By default, the server will serve static files inside a `public`
+    /// directory, or the directory set by `set_static_directory`. This
+    /// method lets you disable this.
+    ///
+    /// It can be re-enabled by a subsequent call to `set_static_directory`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.dont_serve_static_files();
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn dont_serve_static_files(&mut self) {
+        self.static_directories.disable();
+    }
+
+    /// Returns a handle for changing the static directory once the server is
+    /// listening.
+    ///
+    /// See `StaticConfigHandle` for how and when changes apply. As with the
+    /// other static file settings, content entity tags aren't updated for a
+    /// new directory until `refresh_content_etags` is called.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     let static_config = server.static_config_handle();
+    ///     static_config.set_directory("/var/www/");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn static_config_handle(&self) -> StaticConfigHandle {
+        self.static_directories.clone()
+    }
+
+    /// Sets files to serve from memory, keyed by the path they're requested
+    /// at.
+    ///
+    /// This is meant for programs that carry their frontend around with them,
+    /// built in with `include_bytes!`, so that it doesn't need routing by
+    /// hand in the handler. Each path maps to the file's contents and its
+    /// content type. Paths are matched exactly against the path of the
+    /// request, without its query string, so `/` has to be listed itself to
+    /// be served.
+    ///
+    /// Embedded files are sent the way static files from disk are: with
+    /// content entity tags if `set_content_etags` is on, `Cache-Control:
+    /// no-store` in development mode, and `Range` requests honoured. They're
+    /// checked before the static directories, so a path that's embedded is
+    /// never looked for on disk. Their contents are sent without being
+    /// copied. Setting them again replaces the ones set before.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::collections::HashMap;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     let mut assets = HashMap::new();
+    ///     assets.insert(
+    ///         String::from("/"),
+    ///         (&b"<script src=app.js></script>"[..], "text/html; charset=utf-8"),
+    ///     );
+    ///     assets.insert(
+    ///         String::from("/app.js"),
+    ///         (&b"document.write('Hello, world!')"[..], "text/javascript"),
+    ///     );
+    ///     server.set_embedded_assets(assets);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_embedded_assets<A>(&mut self, assets: A)
+    where
+        A: Into<HashMap<String, (&'static [u8], &'static str)>>,
+    {
+        self.embedded_assets = embedded::assets(assets.into());
+    }
+
+    /// Sets a file to serve when a request doesn't match any static file.
+    ///
+    /// This is meant for single-page apps, whose client-side router owns paths
+    /// like `/settings/profile`: reloading such a page should get the app's
+    /// `index.html`, rather than a 404. The path is relative to the static
+    /// directory.
+    ///
+    /// The fallback only applies to `GET` and `HEAD` requests whose path has no
+    /// file extension, so that a missing `/app.js` still goes on to the
+    /// handler rather than quietly getting HTML back. With `set_clean_urls`,
+    /// a page found by adding `.html` to the path is sent instead of the
+    /// fallback.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_fallback_file("index.html");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_fallback_file<P: Into<PathBuf>>(&mut self, path: P) {
+        self.fallback_file = Some(path.into());
+    }
+
+    /// Sets a page from the static directory to send in place of the server's
+    /// own response for an error status.
+    ///
+    /// When the server itself answers with an error, such as a `404` for a
+    /// path traversal attempt or a `500` when the handler returns an `Err`, it
+    /// looks for the page set up for that status in the static directory. If
+    /// it's there, its contents are sent as HTML with the same status;
+    /// otherwise a short built-in body is used.
+    ///
+    /// By default, `404.html`, `413.html`, and `500.html` are used for their
+    /// respective statuses. The path is relative to the static directory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{Server, StatusCode};
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_error_page(StatusCode::INTERNAL_SERVER_ERROR, "errors/oops.html");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_error_page<P: Into<PathBuf>>(&mut self, status: StatusCode, path: P) {
+        self.error_pages.insert(status, path.into());
+    }
+
+    /// Sets the files to look for when a request is for a directory under the
+    /// static directory.
+    ///
+    /// They're tried in order, and the first one that exists in the directory
+    /// is sent. By default, this is just `index.html`. An empty list means that
+    /// directories have no default document, and requests for them go on to
+    /// the handler instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_index_files(vec!["index.html", "index.htm"]);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_index_files<I, P>(&mut self, files: I)
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.index_files = files.into_iter().map(Into::into).collect();
+    }
+
+    /// Stops redirecting requests for static directories that lack a
+    /// trailing slash.
+    ///
+    /// By default, a request for `/docs` where `docs` is a directory with an
+    /// index file gets a `301 Moved Permanently` to `/docs/`, so that relative
+    /// links in the index page work. With this, the index page is sent
+    /// straight away instead, which suits single-page apps that do their own
+    /// routing.
+    pub fn dont_redirect_directories(&mut self) {
+        self.redirect_directories = false;
+    }
+
+    /// Sets whether static pages can be requested without their `.html`
+    /// extension. It's off by default.
+    ///
+    /// Static site generators make pages like `about.html`, which are nicer to
+    /// link to as `/about`. With clean URLs, a `GET` or `HEAD` request for a
+    /// path without an extension gets, in order of preference:
+    ///
+    /// 1. the file at that path, if there is one,
+    /// 2. the file at that path with `.html` added, and
+    /// 3. the index file of the directory at that path, which is redirected
+    ///    to a trailing slash as usual.
+    ///
+    /// Only when none of those exist does the request go on to the fallback
+    /// file set with `set_fallback_file`, if there is one, and then the
+    /// handler.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_clean_urls(true);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_clean_urls(&mut self, enabled: bool) {
+        self.clean_urls = enabled;
+    }
+
+    /// Sets the content type to send static files with the given extension as.
+    ///
+    /// The server knows the content types of common file extensions, like
+    /// `html`, `css` and `png`. This method adds to, or replaces, those. If the
+    /// content type already has a `charset` parameter, it's sent as is.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_mime_type("rss", "application/rss+xml");
+    ///     server.set_mime_type("txt", "text/plain; charset=iso-8859-1");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_mime_type(&mut self, extension: &str, content_type: &str) {
+        self.mime_types
+            .insert(extension.to_ascii_lowercase(), content_type.to_string());
+    }
+
+    /// Sets the charset announced for text static files.
+    ///
+    /// Text content types, like `text/html`, `application/javascript` and
+    /// `application/json`, are sent with a `charset` parameter so that browsers
+    /// don't have to guess at the encoding. By default, it's `utf-8`.
+    pub fn set_static_charset(&mut self, charset: &str) {
+        self.static_charset = Some(charset.to_string());
+    }
+
+    /// Stops adding a `charset` parameter to the content types of text static
+    /// files.
+    ///
+    /// It can be re-enabled by a subsequent call to `set_static_charset`.
+    pub fn dont_add_static_charset(&mut self) {
+        self.static_charset = None;
+    }
+
+    /// Sets the extensions of static files that browsers should download
+    /// rather than display.
+    ///
+    /// Files with these extensions are sent with a `Content-Disposition:
+    /// attachment` header carrying their file name. See `attachment` to do
+    /// the same from a handler.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_download_extensions(vec!["csv", "zip"]);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_download_extensions<I, S>(&mut self, extensions: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.download_extensions = extensions
+            .into_iter()
+            .map(|extension| extension.as_ref().to_ascii_lowercase())
+            .collect();
+    }
+
+    /// Sets whether static files get entity tags made from their contents.
+    /// It's off by default.
+    ///
+    /// When it's on, the static directories are indexed when the server
+    /// starts, hashing every file in them, so that each response can carry an
+    /// `ETag` that only changes when the file does, and a request whose
+    /// `If-None-Match` matches gets a `304 Not Modified` instead of the file.
+    /// Files added or changed later get a weak tag made from their size and
+    /// modification time until `refresh_content_etags` is called.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_content_etags(true);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_content_etags(&mut self, enabled: bool) {
+        self.content_etags = if enabled {
+            Some(etag::Etags::default())
+        } else {
+            None
+        };
+    }
+
+    /// Indexes the static directories again for content entity tags,
+    /// returning how many files were found. Call it after deploying new
+    /// files, if the server keeps running.
+    ///
+    /// Files that can't be read are skipped. If content entity tags aren't
+    /// turned on with `set_content_etags`, this does nothing.
+    pub fn refresh_content_etags(&self) -> usize {
+        if self.dev_mode {
+            return 0;
+        }
+
+        match self.content_etags {
+            Some(ref etags) => {
+                let count = etags.index(&self.static_directories.current());
+                info!("Indexed {} static file(s) for ETags", count);
+                count
+            }
+            None => 0,
+        }
+    }
+
+    /// Sets whether the server runs in development mode. It's off by
+    /// default.
+    ///
+    /// In development mode, edits to static files show up on the next
+    /// request: static responses are sent with `Cache-Control: no-store` and
+    /// without entity tags, even if `set_content_etags` is on. The static
+    /// directories are also checked every second, and files that are added,
+    /// modified or removed are logged.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::env;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_dev_mode(env::var("DEVELOPMENT").is_ok());
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_dev_mode(&mut self, dev_mode: bool) {
+        self.dev_mode = dev_mode;
+    }
+
+    /// Sets whether `Range` requests are honoured for responses made by the
+    /// handler, as they are for static files. It's off by default.
+    ///
+    /// When it's on, a `200` response to a `GET` with a single byte range is
+    /// cut down to that range and sent as a `206 Partial Content`, and a range
+    /// that lies outside the body gets a `416`. Every other `200` is sent whole
+    /// with `Accept-Ranges: bytes`, so that clients know they can resume it.
+    /// Responses with any other status, or that already have a
+    /// `Content-Range`, are left as the handler made them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_handler_ranges(true);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_handler_ranges(&mut self, enabled: bool) {
+        self.handler_ranges = enabled;
+    }
+
+    /// Sets the `Content-Type` to send with responses from the handler that
+    /// don't have one.
+    ///
+    /// Without a content type, browsers guess what a response is from what's
+    /// in it, which can turn text into a page that runs scripts. By default,
+    /// responses are sent as the handler made them. Static files get their
+    /// content type from their extension instead, and `204 No Content` and
+    /// `304 Not Modified` responses, which have no body to describe, are left
+    /// alone.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `content_type` isn't a valid header value.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("<h1>Hello, world!</h1>".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_default_content_type("text/html; charset=utf-8");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_default_content_type(&mut self, content_type: &str) {
+        let content_type = content_type
+            .parse()
+            .expect("The default content type isn't a valid header value");
+        self.default_content_type = Some(content_type);
+    }
+
+    /// Logs a warning for every request that takes longer than `threshold`
+    /// to answer.
+    ///
+    /// The warning says how the time was split between reading the request
+    /// from the client, running the handler, and writing the response, which
+    /// tells a slow client apart from a slow handler. Static files count as
+    /// all writing. By default, nothing is logged.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_slow_request_threshold(Duration::from_millis(500));
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_slow_request_threshold(&mut self, threshold: Duration) {
+        self.slow_request_threshold = Some(threshold);
+    }
+
+    /// Calls `hook` with what a client actually sent whenever its request
+    /// head is rejected as malformed, or abandoned because the client took
+    /// too long sending it or sent too long a request line.
+    ///
+    /// This is meant for debugging clients without reaching for a packet
+    /// capture. The hook is only shown the first 4 KB of the head, and only
+    /// observes: the client gets the same response either way. By default,
+    /// there's no hook.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.on_parse_failure(|head, error| {
+    ///         eprintln!("{:?}: {:?}", error, String::from_utf8_lossy(head));
+    ///     });
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn on_parse_failure<F>(&mut self, hook: F)
+    where
+        F: Fn(&[u8], &Error) + 'static + Send + Sync,
+    {
+        self.parse_failure_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a callback that's told how a request's body is coming along while
+    /// it's still arriving, long before the handler sees it.
+    ///
+    /// The callback is shown the request's method, path and peer, so that
+    /// uploads arriving at the same time can be told apart, along with how
+    /// many bytes of the body have arrived and how many there are in all.
+    /// It's called each time another 64 KB has arrived or 500 ms has passed
+    /// since it was last called, whichever comes first, and once more when the
+    /// whole body is in. See `set_body_progress_granularity` to change that.
+    /// Only bodies with a `Content-Length` are reported on, and only those
+    /// that didn't arrive along with their head.
+    ///
+    /// The callback runs on the thread reading the body, which waits for it,
+    /// so it should be quick: log a line or send a message somewhere, and
+    /// leave anything more to another thread. If it panics, the panic is
+    /// logged, the upload carries on, and the callback isn't called again for
+    /// the rest of that body. By default, there's no callback.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.on_body_progress(|head, received, total| {
+    ///         eprintln!("{} {}: {} of {:?} bytes", head.method(), head.path(), received, total);
+    ///     });
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn on_body_progress<F>(&mut self, hook: F)
+    where
+        F: Fn(&RequestHeadInfo, u64, Option<u64>) + 'static + Send + Sync,
+    {
+        self.body_progress_hook = Some(Box::new(hook));
+    }
+
+    /// Sets how often the `on_body_progress` callback is called while a body
+    /// arrives: each time another `bytes` bytes have arrived, or once
+    /// `interval` has passed since it was last called, whichever comes first.
+    ///
+    /// By default, it's every 64 KB or 500 ms.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.on_body_progress(|head, received, total| {
+    ///         eprintln!("{} {}: {} of {:?} bytes", head.method(), head.path(), received, total);
+    ///     });
+    ///     server.set_body_progress_granularity(1024 * 1024, Duration::from_secs(5));
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_body_progress_granularity(&mut self, bytes: u64, interval: Duration) {
+        self.body_progress_bytes = bytes;
+        self.body_progress_interval = interval;
+    }
+
+    /// Restricts the methods that requests may use.
+    ///
+    /// By default, requests with any method, including made-up ones, are
+    /// passed on to static file serving and the handler. Once this is set,
+    /// requests using any other method are answered with a `501 Not
+    /// Implemented` right away, along with an `Allow` header listing the
+    /// methods that are allowed. See `set_disallowed_method_status` to send a
+    /// `405 Method Not Allowed` instead.
+    ///
+    /// Note that `OPTIONS` requests are only let through if `OPTIONS` is in
+    /// the list.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{Method, Server};
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_allowed_methods(&[Method::GET, Method::HEAD, Method::POST]);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_allowed_methods(&mut self, methods: &[Method]) {
+        self.allowed_methods = Some(methods.to_vec());
+    }
+
+    /// Sets the status to answer requests with when they use a method that
+    /// isn't allowed by `set_allowed_methods`.
+    ///
+    /// This is `501 Not Implemented` by default. `405 Method Not Allowed` is
+    /// the other sensible choice.
+    pub fn set_disallowed_method_status(&mut self, status: StatusCode) {
+        self.disallowed_method_status = status;
+    }
+
+    /// Sets whether the server answers `OPTIONS *` requests itself.
+    ///
+    /// `OPTIONS *` asks what the server as a whole supports, rather than
+    /// anything at a path. By default, it's answered with a `200 OK`, along
+    /// with an `Allow` header if `set_allowed_methods` was used. Turn this
+    /// off to pass these requests on to the handler instead, with `*` as the
+    /// URI. Either way, they never reach the static directory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::{Method, Server};
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         if request.method() == Method::OPTIONS && request.uri() == "*" {
+    ///             response.header("allow", "GET, HEAD, OPTIONS, POST");
+    ///         }
+    ///         Ok(response.body(Vec::new())?)
+    ///     });
+    ///
+    ///     server.set_answer_options_asterisk(false);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_answer_options_asterisk(&mut self, answer: bool) {
+        self.answer_options_asterisk = answer;
+    }
+
+    /// Sets whether the `500` sent when a handler returns an error shows what
+    /// the error was.
+    ///
+    /// This saves a trip to the terminal while developing. It's off by
+    /// default. A custom error page set up for `500` is still sent instead, if
+    /// there is one.
+    ///
+    /// **Never turn this on in production.** Errors can carry file paths,
+    /// queries, and other details that nobody outside should see.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::env;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_debug_errors(env::var("DEVELOPMENT").is_ok());
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    pub fn set_debug_errors(&mut self, debug_errors: bool) {
+        self.debug_errors = debug_errors;
+    }
+
+    /// Sets how big a response from the handler has to be before it's
+    /// compressed.
+    ///
+    /// With the `brotli` feature enabled, text responses, like HTML, CSS,
+    /// JavaScript and JSON, are compressed for clients that accept it. Below a
+    /// kilobyte or so, it isn't worth the effort, which is the default. Static
+    /// files, and responses that already have a `Content-Encoding`, are sent
+    /// as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_compression_threshold(4096);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    #[cfg(feature = "brotli")]
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Sets the secret that session cookies are signed with, which turns
+    /// sessions on.
+    ///
+    /// Every request passed to the handler then carries a `Session`; see its
+    /// docs for how to use it. The secret should be random, and kept out of
+    /// version control. Changing it logs everybody out.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    /// use std::fs;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     let mut secret = [0; 32];
+    ///     secret.copy_from_slice(&fs::read("session.key").unwrap()[..32]);
+    ///     server.set_session_secret(secret);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    #[cfg(feature = "sessions")]
+    pub fn set_session_secret(&mut self, secret: [u8; 32]) {
+        self.session_secret = Some(Arc::new(secret));
+    }
+
+    /// Sets how long a session lasts after it was last applied to a
+    /// response. It's a day by default.
+    #[cfg(feature = "sessions")]
+    pub fn set_session_max_age(&mut self, max_age: Duration) {
+        self.session_max_age = max_age;
+    }
+
+    /// Turns protection against cross-site request forgery on or off. It's
+    /// off by default.
+    ///
+    /// While it's on, requests that change things, i.e. anything but `GET`,
+    /// `HEAD`, `OPTIONS` and `TRACE`, get a `403 Forbidden` instead of
+    /// reaching the handler unless they send back the token from the
+    /// `CsrfToken` in an earlier request's extensions. See `CsrfToken` for how
+    /// to put it in forms.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_csrf_protection(true);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    #[cfg(feature = "csrf")]
+    pub fn set_csrf_protection(&mut self, enabled: bool) {
+        if !enabled {
+            self.csrf_key = None;
+        } else if self.csrf_key.is_none() {
+            self.csrf_key = Some(csrf::random_key());
+        }
+    }
+
+    /// Lets requests whose path starts with `prefix` through without a CSRF
+    /// token.
+    ///
+    /// This is meant for APIs whose clients authenticate with a token in a
+    /// header, like those checked by `require_bearer_token`, since browsers
+    /// don't add those to forged requests by themselves.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.set_csrf_protection(true);
+    ///     server.add_csrf_exempt_path("/api/");
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    #[cfg(feature = "csrf")]
+    pub fn add_csrf_exempt_path<S: Into<String>>(&mut self, prefix: S) {
+        self.csrf_exempt_paths.push(prefix.into());
+    }
+
+    /// Serves the server's metrics at `path`, in the text format Prometheus
+    /// scrapes.
+    ///
+    /// `GET` requests for exactly that path are answered by the server itself,
+    /// before the static directory or the handler see them. What's served is
+    /// what `metrics` and `pool_stats` report: responses by the class of their
+    /// status, bytes received and sent, connections being handled and
+    /// waiting, and connections turned away. Anyone who can reach the server
+    /// can read them, unless it's restricted with
+    /// `set_metrics_loopback_only`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate simple_server;
+    ///
+    /// use simple_server::Server;
+    ///
+    /// fn main() {
+    ///     let mut server = Server::new(|request, mut response| {
+    ///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+    ///     });
+    ///
+    ///     server.enable_metrics_endpoint("/metrics");
+    ///     server.set_metrics_loopback_only(true);
+    ///
+    ///     server.listen("127.0.0.1", "7979");
+    /// }
+    /// ```
+    #[cfg(feature = "prometheus")]
+    pub fn enable_metrics_endpoint<S: Into<String>>(&mut self, path: S) {
+        self.metrics_endpoint = Some(path.into());
+    }
+
+    /// Sets whether the metrics endpoint only answers clients connecting from
+    /// a loopback address, like `127.0.0.1` or `::1`. It's off by default.
+    ///
+    /// Other clients get a `403 Forbidden` from the metrics endpoint's path.
+    #[cfg(feature = "prometheus")]
+    pub fn set_metrics_loopback_only(&mut self, loopback_only: bool) {
+        self.metrics_loopback_only = loopback_only;
+    }
+
+    // Creates the listener for `listen` by hand rather than with
+    // `TcpListener::bind`, so that the backlog can be set.
+    fn bind(&self, host: &str, port: &str) -> io::Result<TcpListener> {
+        use socket2::{Domain, Socket, Type};
+
+        const DEFAULT_BACKLOG: i32 = 128;
+
+        let mut last_error = None;
+        for addr in format!("{}:{}", host, port).to_socket_addrs()? {
+            let bound =
+                Socket::new(Domain::for_address(addr), Type::STREAM, None).and_then(|socket| {
+                    #[cfg(unix)]
+                    socket.set_reuse_address(true)?;
+                    socket.bind(&addr.into())?;
+                    socket.listen(self.listen_backlog.unwrap_or(DEFAULT_BACKLOG))?;
+                    socket.set_nonblocking(self.nonblocking_accept)?;
+                    Ok(TcpListener::from(socket))
+                });
+
+            match bound {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    // Try and fetch the environment variable SIMPLESERVER_THREADS and parse it as a usize.
+    // If this fails we fall back to using the num_cpus crate.
+    fn pool_size(&self) -> usize {
+        let logical_cores = num_cpus::get();
+
+        match config::Variables::from_env("SIMPLESERVER").number("THREADS") {
+            Ok(Some(threads)) => threads,
+            Ok(None) => logical_cores,
+            Err(e) => {
+                warn!("Ignoring {:?}", e);
+                logical_cores
+            }
+        }
+    }
+
+    // Reads and answers requests on a connection until it's closed, leaving
+    // the method and path of the one being answered in `current`.
+    fn handle_connection(
+        &self,
+        mut stream: TcpStream,
+        current: &mut Option<String>,
+    ) -> Result<(), Error> {
+        let registration = Registry::register(&self.connections);
+        let mut buffered = Vec::new();
+        let mut served = 0;
+        let progress = self
+            .body_progress_hook
+            .as_deref()
+            .map(|hook| progress::Watch {
+                hook,
+                peer: stream.peer_addr().ok(),
+                bytes: self.body_progress_bytes,
+                interval: self.body_progress_interval,
+            });
+
+        loop {
+            if served > 0
+                && buffered.is_empty()
+                && !next_request_started(&stream, &registration, self.keep_alive_timeout)
+            {
+                debug!("Closing kept-alive connection with no next request");
+                return Ok(());
+            }
+
+            let started = Instant::now();
+            timing::start();
+            let request = request::read(
+                &mut Watched::new(&mut Counted::new(&mut stream, &self.metrics), &registration),
+                &mut buffered,
+                self.timeout,
+                self.max_request_line,
+                self.max_request_size,
+                self.parse_failure_hook.as_deref(),
+                progress.as_ref(),
+            );
+            served += 1;
+
+            let mut request = match request {
+                Err(Error::ConnectionClosed) if registration.reap_requested() => {
+                    debug!("Closing idle connection");
+                    self.metrics.connection_reaped();
+                    return Ok(());
+                }
+
+                Err(Error::ConnectionClosed) | Err(Error::Timeout) => return Ok(()),
+
+                Err(Error::MissingVersion) => {
+                    return self.reject(
+                        StatusCode::BAD_REQUEST,
+                        "<h1>400</h1><p>Bad request: HTTP/0.9 requests are not supported!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::InvalidHeader) => {
+                    info!("Rejecting request with an invalid header");
+                    return self.reject(
+                        StatusCode::BAD_REQUEST,
+                        "<h1>400</h1><p>Bad request: invalid header!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::ObsoleteLineFolding) => {
+                    info!("Rejecting request with a folded header");
+                    return self.reject(
+                        StatusCode::BAD_REQUEST,
+                        "<h1>400</h1><p>Bad request: folded headers (obs-fold) are not supported!<p>",
+                        stream,
+                    );
+                }
+
+                Err(e @ Error::HttpParse(_)) => {
+                    trace::malformed_request(&e);
+                    return self.reject(
+                        StatusCode::BAD_REQUEST,
+                        "<h1>400</h1><p>Bad request!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::InvalidTarget) | Err(Error::InvalidUri(_)) => {
+                    info!("Rejecting request with an invalid target");
+                    return self.reject(
+                        StatusCode::BAD_REQUEST,
+                        "<h1>400</h1><p>Bad request: invalid request target!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::Framing(e)) => {
+                    info!("Rejecting request with invalid framing: {:?}", e);
+                    return self.reject(
+                        StatusCode::BAD_REQUEST,
+                        "<h1>400</h1><p>Bad request: invalid message framing!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::ExpectationFailed) => {
+                    info!("Rejecting request with an unsupported expectation");
+                    return self.reject(
+                        StatusCode::EXPECTATION_FAILED,
+                        "<h1>417</h1><p>Expectation failed!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::RequestLineTooLong) => {
+                    info!("Rejecting request with a request line that's too long");
+                    return self.reject(
+                        StatusCode::URI_TOO_LONG,
+                        "<h1>414</h1><p>URI too long!<p>",
+                        stream,
+                    );
+                }
+
+                Err(Error::RequestTooLarge) => {
+                    return self.reject(
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        "<h1>413</h1><p>Request too large!<p>",
+                        stream,
+                    );
+                }
+
+                Err(e) => return Err(e),
+
+                Ok(r) => r,
+            };
+
+            *current = Some(format!("{} {}", request.method(), request.uri().path()));
+
+            // The handler gets the same deadline the request was read under.
+            if let Some(timeout) = self.timeout {
+                request
+                    .extensions_mut()
+                    .insert(Deadline::new(started + timeout));
+            }
+
+            // This isn't a proxy, so tunnels are refused before the target, which
+            // is a host and port rather than a path, goes anywhere near the
+            // static directory or the handler.
+            if request.method() == Method::CONNECT {
+                debug!("Rejecting a CONNECT to {}", request.uri());
+                self.metrics.connect_rejected();
+                return self.reject(
+                    StatusCode::NOT_IMPLEMENTED,
+                    "<h1>501</h1><p>Not implemented!<p>",
+                    stream,
+                );
+            }
+
+            // Once a connection has served its share of requests, it's closed,
+            // so that a busy client can't hold on to a worker forever. Anything
+            // the client pipelined past the last one is left unanswered.
+            let mut connection = request::connection(request.version(), request.headers());
+            if connection == Connection::KeepAlive && served >= self.max_requests_per_connection {
+                debug!("Closing connection after {} requests", served);
+                connection = Connection::Close;
+            }
+
+            let mut upgrade = None;
+            let result = self.respond_timed(
+                request,
+                connection,
+                &mut stream,
+                started,
+                served,
+                &mut upgrade,
+            );
+            self.metrics.responded(&timing::finish());
+
+            // Once the handler has taken the connection over, the server lets
+            // go of it entirely.
+            if let (Ok(()), Some(upgrade)) = (&result, upgrade) {
+                drop(registration);
+                stream.set_read_timeout(None)?;
+                upgrade.take_over(stream, buffered);
+                return Ok(());
+            }
+
+            if result.is_err() || connection == Connection::Close {
+                return result;
+            }
+        }
+    }
+
+    // Answers a request that's turned away before all of it has been read,
+    // and closes the connection once the client has had a chance to finish
+    // sending it.
+    fn reject(&self, status: StatusCode, body: &str, mut stream: TcpStream) -> Result<(), Error> {
+        self.write_error(status, body, Connection::Close, false, &mut stream)?;
+        self.metrics.responded(&timing::finish());
+        linger(stream, self.drain_bytes, self.drain_time);
+        Ok(())
+    }
+
+    // Answers a request like `respond`, logging it if the slow request
+    // threshold is set and it took longer than that from when it started to
+    // be read.
+    fn respond_timed(
+        &self,
+        request: Request<Vec<u8>>,
+        connection: Connection,
+        stream: &mut TcpStream,
+        started: Instant,
+        served: usize,
+        upgrade: &mut Option<Upgrade>,
+    ) -> Result<(), Error> {
+        let threshold = match self.slow_request_threshold {
+            Some(threshold) => threshold,
+            None => {
+                return trace::request(&request, served)
+                    .in_scope(|| self.respond(request, connection, stream, upgrade));
+            }
+        };
+
+        let read = started.elapsed();
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let peer = stream.peer_addr().ok();
+
+        let result = trace::request(&request, served)
+            .in_scope(|| self.respond(request, connection, stream, upgrade));
+        let exchange = timing::finish();
+
+        let total = started.elapsed();
+        if total > threshold {
+            trace::slow_request(&trace::SlowRequest {
+                method: &method,
+                path: &path,
+                status: exchange.status,
+                peer,
+                read,
+                handler: exchange.handler,
+                write: total - read - exchange.handler,
+            });
+        }
+
+        result
+    }
+
+    // Answers a request that's been read in full, whether from the static
+    // directory, with an error, or by calling the handler. If the handler
+    // wants to take the connection over, `upgrade` is left holding the
+    // callback to hand it to.
+    fn respond(
+        &self,
+        mut request: Request<Vec<u8>>,
+        connection: Connection,
+        stream: &mut TcpStream,
+        upgrade: &mut Option<Upgrade>,
+    ) -> Result<(), Error> {
+        let head_only = request.method() == Method::HEAD;
+
+        if let Some(ref allowed_methods) = self.allowed_methods {
+            if !allowed_methods.contains(request.method()) {
+                let allow = allow_header(allowed_methods);
+
+                let status = self.disallowed_method_status;
+                let mut response = self.error_response(
+                    status,
+                    &format!("<h1>{}</h1><p>Method not supported!<p>", status.as_str()),
+                )?;
+                response
+                    .headers_mut()
+                    .insert(http::header::ALLOW, allow.parse()?);
+
+                return write_response(response, connection, head_only, stream);
+            }
+        }
+
+        // The asterisk-form target is about the server as a whole, and only
+        // makes sense for OPTIONS. It's never a path.
+        let asterisk = request.uri() == "*";
+        if asterisk {
+            if request.method() != Method::OPTIONS {
+                return self.write_error(
+                    StatusCode::BAD_REQUEST,
+                    "<h1>400</h1><p>Bad request: invalid request target!<p>",
+                    connection,
+                    head_only,
+                    stream,
+                );
+            }
+
+            if self.answer_options_asterisk {
+                let mut response = Response::builder();
+                if let Some(ref allowed_methods) = self.allowed_methods {
+                    response.header(http::header::ALLOW, allow_header(allowed_methods));
+                }
+                return write_response(response.body(Vec::new())?, connection, head_only, stream);
+            }
+        }
+
+        #[cfg(feature = "prometheus")]
+        {
+            let endpoint = self.metrics_endpoint.as_deref();
+            if request.method() == Method::GET && endpoint == Some(request.uri().path()) {
+                return self.write_metrics(connection, stream);
+            }
+        }
+
+        // first, we serve static files, starting with those built into the
+        // program...
+        if !asterisk {
+            if let Some(asset) = self.embedded_assets.get(request.uri().path()) {
+                return self.write_embedded_asset(&request, asset, connection, stream);
+            }
+        }
+
+        // ... and then from the directories as they were when the request came
+        // in
+        let static_directories = self.static_directories.current();
+        if !asterisk && !static_directories.is_empty() {
+            // the uri always includes a leading /, which would make join over-write the static
+            // directory, so the path is decoded and resolved into a relative one first...
+            let fs_path = normalize::static_path(request.uri().path());
+
+            // ... you trying to do something bad?
+            let fs_path = fs_path.filter(|fs_path| {
+                fs_path
+                    .components()
+                    .all(|component| matches!(component, std::path::Component::Normal(_)))
+            });
+            let fs_path = match fs_path {
+                Some(fs_path) => fs_path,
+                None => {
+                    // GET OUT
+                    return self.write_error(
+                        StatusCode::NOT_FOUND,
+                        "<h1>404</h1><p>Not found!<p>",
+                        connection,
+                        head_only,
+                        stream,
+                    );
+                }
+            };
+
+            if let Some(page) = self.find_clean_url(&request, &static_directories, &fs_path) {
+                return self.write_static_file(&request, &page, connection, stream);
+            }
+
+            if let Some(file) = self.find_static_file(&static_directories, &fs_path) {
+                // A directory's index page has to be fetched from a path
+                // ending in a slash for its relative links to work. The
+                // redirect goes to the normalized path rather than the one
+                // the client sent, which could start with `//` and so lead
+                // to another host.
+                if self.redirect_directories
+                    && !request.uri().path().ends_with('/')
+                    && !fs_path.as_os_str().is_empty()
+                    && !file.ends_with(&fs_path)
+                {
+                    let path = normalize::url_path(&fs_path);
+                    let location = match request.uri().query() {
+                        Some(query) => format!("{}/?{}", path, query),
+                        None => format!("{}/", path),
+                    };
+                    let response = Response::builder()
+                        .status(StatusCode::MOVED_PERMANENTLY)
+                        .header(http::header::LOCATION, location.as_str())
+                        .body(Vec::new())?;
+                    return write_response(response, connection, head_only, stream);
+                }
+
+                return self.write_static_file(&request, &file, connection, stream);
+            }
+
+            if let Some(ref fallback_file) = self.fallback_file {
+                let method = request.method();
+
+                if (method == Method::GET || method == Method::HEAD)
+                    && fs_path.extension().is_none()
+                {
+                    if let Some(fallback) =
+                        self.find_static_file(&static_directories, fallback_file)
+                    {
+                        return self.write_static_file(&request, &fallback, connection, stream);
+                    }
+                }
+            }
+        }
+
+        // A server without a handler of its own has nothing more to offer.
+        if self.static_only {
+            return self.write_error(
+                StatusCode::NOT_FOUND,
+                "<h1>404</h1><p>Not found!<p>",
+                connection,
+                head_only,
+                stream,
+            );
+        }
+
+        if let Ok(interim_stream) = stream.try_clone() {
+            let version = request.version();
+            request
+                .extensions_mut()
+                .insert(Interim::new(interim_stream, version));
+        }
+
+        #[cfg(feature = "sessions")]
+        {
+            if let Some(ref secret) = self.session_secret {
+                let session =
+                    session::Session::from_request(&request, secret.clone(), self.session_max_age);
+                request.extensions_mut().insert(session);
+            }
+        }
+
+        #[cfg(feature = "csrf")]
+        let csrf_cookie = match self.csrf_key {
+            Some(ref key) => {
+                if !csrf::verify(&request, key, &self.csrf_exempt_paths) {
+                    return self.write_error(
+                        StatusCode::FORBIDDEN,
+                        "<h1>403</h1><p>Forbidden!<p>",
+                        connection,
+                        head_only,
+                        stream,
+                    );
+                }
+                csrf::attach_token(&mut request, key)
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "brotli")]
+        let coding = compress::negotiate(request.headers());
+
+        let range = if request.method() == Method::GET {
+            request.headers().get(http::header::RANGE).cloned()
+        } else {
+            None
+        };
+
+        match timing::handler(|| (self.handler)(request, Response::builder())) {
+            Ok(mut response) => {
+                if let Some(take_over) = response.extensions_mut().remove::<Upgrade>() {
+                    let (parts, _) = response.into_parts();
+                    let head = serialize_upgrade_head(&parts);
+                    stream.write_all(&head)?;
+                    timing::sent(head.len() as u64);
+                    *upgrade = Some(take_over);
+                    return Ok(());
+                }
+
+                #[cfg(feature = "csrf")]
+                let response = csrf::set_cookie(response, csrf_cookie);
+
+                // Static bytes are only copied when something might change
+                // them on the way out.
+                let transformed = self.handler_ranges || cfg!(feature = "brotli");
+                let (mut parts, body) = response.into_parts();
+                self.add_default_content_type(&mut parts);
+                let body = match body {
+                    Body::Bytes(bytes) => bytes,
+                    Body::Static(bytes) if !transformed => {
+                        let response = Response::from_parts(parts, bytes);
+                        return write_response(response, connection, head_only, stream);
+                    }
+                    Body::Static(bytes) => bytes.to_vec(),
+                    Body::File(path) => {
+                        return self.write_handler_file(
+                            parts,
+                            &path,
+                            range.as_ref(),
+                            connection,
+                            head_only,
+                            stream,
+                        );
+                    }
+                };
+                let response = Response::from_parts(parts, body);
+
+                #[cfg(feature = "brotli")]
+                let response = compress::compress(response, coding, self.compression_threshold)?;
+                // Ranges are taken from the body as it's sent, so that
+                // compression happens first.
+                let response = if self.handler_ranges {
+                    range::slice(response, range.as_ref())
+                } else {
+                    response
+                };
+                Ok(write_response(response, connection, head_only, stream)?)
+            }
+            Err(e) => {
+                trace::handler_failed(&e);
+
+                let mut body = String::from("<h1>500</h1><p>Internal Server Error!<p>");
+                if self.debug_errors {
+                    body.push_str("<pre>");
+                    escape_html(&format!("{:?}", e), &mut body);
+                    body.push_str("</pre>");
+                }
+
+                self.write_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &body,
+                    connection,
+                    head_only,
+                    stream,
+                )
+            }
+        }
+    }
+
+    // Gives a response from the handler the default content type, if there is
+    // one and the response has a body that needs it.
+    fn add_default_content_type(&self, parts: &mut Parts) {
+        let content_type = match self.default_content_type {
+            Some(ref content_type) => content_type,
+            None => return,
+        };
+
+        if parts.status == StatusCode::NO_CONTENT || parts.status == StatusCode::NOT_MODIFIED {
+            return;
+        }
+
+        if !parts.headers.contains_key(http::header::CONTENT_TYPE) {
+            parts
+                .headers
+                .insert(http::header::CONTENT_TYPE, content_type.clone());
+        }
+    }
+
+    // Writes one of the server's own error responses.
+    fn write_error<S: Write>(
+        &self,
+        status: StatusCode,
+        body: &str,
+        connection: Connection,
+        head_only: bool,
+        stream: S,
+    ) -> Result<(), Error> {
+        let response = self.error_response(status, body)?;
+        write_response(response, connection, head_only, stream)
+    }
+
+    // Streams a file that the handler asked to have sent, the way static files
+    // are. Only a file that's missing gets a `404`; anything else that stops
+    // it being opened is the server's fault.
+    fn write_handler_file(
+        &self,
+        parts: Parts,
+        path: &Path,
+        range: Option<&http::header::HeaderValue>,
+        connection: Connection,
+        head_only: bool,
+        stream: &mut TcpStream,
+    ) -> Result<(), Error> {
+        let opened = File::open(path).and_then(|file| {
+            if file.metadata()?.is_file() {
+                Ok(file)
+            } else {
+                Err(io::ErrorKind::NotFound.into())
+            }
+        });
+
+        match opened {
+            Ok(file) => write_file_response(
+                Response::from_parts(parts, file),
+                range,
+                connection,
+                head_only,
+                stream,
+            ),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => self.write_error(
+                StatusCode::NOT_FOUND,
+                "<h1>404</h1><p>Not found!<p>",
+                connection,
+                head_only,
+                stream,
+            ),
+            Err(e) => {
+                warn!("Couldn't open {} for the handler: {}", path.display(), e);
+                self.write_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "<h1>500</h1><p>Internal Server Error!<p>",
+                    connection,
+                    head_only,
+                    stream,
+                )
+            }
+        }
+    }
+
+    // Builds one of the server's own error responses. If an error page is set
+    // up for the status, and it can be read from the static directory, that's
+    // sent. Otherwise, the inline `body` is.
+    fn error_response(&self, status: StatusCode, body: &str) -> ResponseResult {
+        let page = self
+            .error_pages
+            .get(&status)
+            .and_then(|page| self.find_static_file(&self.static_directories.current(), page))
+            .and_then(|page| fs::read(page).ok());
+        let body = page.unwrap_or_else(|| body.as_bytes().to_vec());
+
+        Ok(Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "text/html")
+            .body(body)?)
+    }
+
+    // With clean URLs, looks for the page a path without an extension stands
+    // for, which is the file at that path itself, or else the one with `.html`
+    // added. Directories are left to `find_static_file`, so they come last.
+    fn find_clean_url(
+        &self,
+        request: &Request<Vec<u8>>,
+        static_directories: &[PathBuf],
+        path: &Path,
+    ) -> Option<PathBuf> {
+        let method = request.method();
+        if !self.clean_urls
+            || !(method == Method::GET || method == Method::HEAD)
+            || path.extension().is_some()
+            || path.as_os_str().is_empty()
+            || request.uri().path().ends_with('/')
+        {
+            return None;
+        }
+
+        let mut page = path.as_os_str().to_owned();
+        page.push(".html");
+
+        [path, Path::new(&page)].iter().find_map(|candidate| {
+            static_directories
+                .iter()
+                .map(|static_directory| static_directory.join(candidate))
+                .find(|file| file.is_file())
+        })
+    }
+
+    // Looks for a file in each of the static directories in turn. If the path
+    // is a directory there, it's a match if one of the index files is in it.
+    fn find_static_file(&self, static_directories: &[PathBuf], path: &Path) -> Option<PathBuf> {
+        static_directories.iter().find_map(|static_directory| {
+            let path = static_directory.join(path);
+
+            if path.is_file() {
+                return Some(path);
+            }
+
+            if path.is_dir() {
+                return self
+                    .index_files
+                    .iter()
+                    .map(|index| path.join(index))
+                    .find(|index| index.is_file());
+            }
+
+            None
+        })
+    }
+
+    // Sends the file at `path`, honouring any `Range` header on the request.
+    fn write_static_file(
+        &self,
+        request: &Request<Vec<u8>>,
+        path: &Path,
+        connection: Connection,
+        stream: &mut TcpStream,
+    ) -> Result<(), Error> {
+        let file = File::open(path)?;
+
+        let mut response_builder = Response::builder();
+        if self.dev_mode {
+            response_builder.header(http::header::CACHE_CONTROL, "no-store");
+        } else if let Some(ref etags) = self.content_etags {
+            let metadata = file.metadata()?;
+            if let Some(etag) = etags.get(path, &metadata) {
+                let method = request.method();
+                if (method == Method::GET || method == Method::HEAD)
+                    && conditional::is_not_modified(request, &etag)
+                {
+                    let response = Response::builder()
+                        .status(StatusCode::NOT_MODIFIED)
+                        .header(http::header::ETAG, &*etag)
+                        .header(http::header::CONTENT_LENGTH, metadata.len())
+                        .body(Vec::new())?;
+                    return write_response(response, connection, false, stream);
+                }
+
+                response_builder.header(http::header::ETAG, &*etag);
+            }
+        }
+
+        let range = if request.method() == Method::GET {
+            request.headers().get(http::header::RANGE)
+        } else {
+            None
+        };
+
+        if let Some(content_type) = mime::guess(path, &self.mime_types) {
+            match self.static_charset {
+                Some(ref charset) => response_builder.header(
+                    http::header::CONTENT_TYPE,
+                    &*mime::with_charset(content_type, charset),
+                ),
+                None => response_builder.header(http::header::CONTENT_TYPE, content_type),
+            };
+        }
+
+        let download = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                self.download_extensions
+                    .contains(&extension.to_ascii_lowercase())
+            });
+        if download {
+            if let Some(name) = path.file_name() {
+                response_builder.header(
+                    http::header::CONTENT_DISPOSITION,
+                    attachment(&name.to_string_lossy()),
+                );
+            }
+        }
+
+        let head_only = request.method() == Method::HEAD;
+        let response = response_builder.body(file)?;
+        write_file_response(response, range, connection, head_only, stream)
+    }
+
+    // Answers a request to the metrics endpoint, as long as it's from a client
+    // that's allowed to see them.
+    #[cfg(feature = "prometheus")]
+    fn write_metrics(&self, connection: Connection, stream: &mut TcpStream) -> Result<(), Error> {
+        if self.metrics_loopback_only
+            && !stream.peer_addr().is_ok_and(|peer| is_loopback(peer.ip()))
+        {
+            return self.write_error(
+                StatusCode::FORBIDDEN,
+                "<h1>403</h1><p>Forbidden!<p>",
+                connection,
+                false,
+                stream,
+            );
+        }
+
+        let response = Response::builder()
+            .header(http::header::CONTENT_TYPE, prometheus::CONTENT_TYPE)
+            .header(http::header::CACHE_CONTROL, "no-store")
+            .body(prometheus::render(&self.metrics).into_bytes())?;
+        write_response(response, connection, false, stream)
+    }
+
+    // Sends a file built into the program, the way `write_static_file` would
+    // send it from disk.
+    fn write_embedded_asset(
+        &self,
+        request: &Request<Vec<u8>>,
+        asset: &embedded::Asset,
+        connection: Connection,
+        stream: &mut TcpStream,
+    ) -> Result<(), Error> {
+        let mut response_builder = Response::builder();
+        if self.dev_mode {
+            response_builder.header(http::header::CACHE_CONTROL, "no-store");
+        } else if self.content_etags.is_some() {
+            let method = request.method();
+            if (method == Method::GET || method == Method::HEAD)
+                && conditional::is_not_modified(request, &asset.etag)
+            {
+                let response = Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header(http::header::ETAG, &*asset.etag)
+                    .header(http::header::CONTENT_LENGTH, asset.bytes.len())
+                    .body(Vec::new())?;
+                return write_response(response, connection, false, stream);
+            }
+
+            response_builder.header(http::header::ETAG, &*asset.etag);
+        }
+
+        let range = if request.method() == Method::GET {
+            request.headers().get(http::header::RANGE)
+        } else {
+            None
+        };
+
+        let response = response_builder
+            .header(http::header::CONTENT_TYPE, asset.content_type)
+            .body(asset.bytes)?;
+        let head_only = request.method() == Method::HEAD;
+        write_response(range::slice(response, range), connection, head_only, stream)
+    }
+}
+
+// Whether `ip` is a loopback address, including an IPv4 one mapped into IPv6,
+// as a dual-stack listener sees local IPv4 clients.
+#[cfg(feature = "prometheus")]
+fn is_loopback(ip: std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(ip) => ip.is_loopback(),
+        std::net::IpAddr::V6(ip) => {
+            ip.is_loopback() || ip.to_ipv4_mapped().is_some_and(|ip| ip.is_loopback())
+        }
+    }
+}
+
+// Lists methods for an `Allow` header.
+fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Appends `text` to `out`, escaped so that it's shown as is in HTML.
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+// Builds the `Location` for a redirect to the same path and query string
+// under another origin. They're copied exactly as the client sent them.
+fn redirect_location(target_base_url: &str, uri: &http::Uri) -> String {
+    let path_and_query = uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+
+    format!(
+        "{}{}",
+        target_base_url.trim_end_matches('/'),
+        path_and_query
+    )
+}
+
+// The largest body that gets copied next to the response head so both go out
+// in one write.
+const COALESCE_BODY_LIMIT: usize = 8 * 1024;
+
+// Spawns a thread with a name that says what it's for, so that it can be told
+// apart from the others in panic messages, debuggers, and `top -H`.
+fn spawn_named<F: FnOnce() + Send + 'static>(name: String, f: F) {
+    thread::Builder::new()
+        .name(name)
+        .spawn(f)
+        .expect("FATAL: Couldn't spawn a thread");
+}
+
+// Gets the message out of a panic's payload, which is a string for anything
+// raised with `panic!`.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+// How long reading a connection blocks before checking whether the request
+// has timed out.
+const READ_TIMEOUT_MS: u64 = 20;
+
+// Takes the next connection from the listener threads, ready to be handled,
+// along with the address it came in on.
+fn next_connection(incoming: &mpsc::Receiver<(TcpStream, String)>) -> (TcpStream, String) {
+    let (stream, listener) = incoming
+        .recv()
+        .expect("No listeners to accept connections from.");
+
+    if let Ok(peer) = stream.peer_addr() {
+        debug!("Accepted a connection from {} on {}", peer, listener);
+    }
+
+    stream
+        .set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))
+        .expect("FATAL: Couldn't set read timeout on socket");
+
+    (stream, listener)
+}
+
+// How many connections may wait to be turned away while the server's
+// overloaded. Past that, they're simply closed.
+const REFUSAL_BACKLOG: usize = 64;
+
+// How long a client that's being turned away gets to finish sending its
+// request.
+const REFUSAL_LINGER_MS: u64 = 100;
+
+// How much of a request that's turned away early is read and thrown away,
+// and for how long, unless the server is set up otherwise.
+const DEFAULT_DRAIN_BYTES: u64 = 1024 * 1024;
+const DEFAULT_DRAIN_MS: u64 = 1000;
+
+// How long a kept-alive connection waits for its next request, unless the
+// server is set up otherwise.
+const DEFAULT_KEEP_ALIVE_MS: u64 = 5000;
+
+// Waits up to `timeout` for the client to send something on a connection
+// that's between requests, returning false if it doesn't. Anything else, like
+// the client closing the connection or it being reaped, is left for reading
+// the request to find.
+fn next_request_started(
+    stream: &TcpStream,
+    registration: &Registration,
+    timeout: Duration,
+) -> bool {
+    let started = Instant::now();
+    loop {
+        if registration.reap_requested() {
+            return true;
+        }
+
+        match stream.peek(&mut [0]) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {}
+            _ => return true,
+        }
+
+        if started.elapsed() >= timeout {
+            return false;
+        }
+    }
+}
+
+// Answers a connection the server has no room for with a `503`, asking the
+// client to try again shortly, and closes it.
+fn refuse_overloaded(mut stream: TcpStream) {
+    let response = Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(http::header::RETRY_AFTER, "1")
+        .header(http::header::CONTENT_TYPE, "text/html")
+        .body(&b"<h1>503</h1><p>Service unavailable!<p>"[..]);
+    let written = response
+        .map_err(Error::from)
+        .and_then(|response| write_response(response, Connection::Close, false, &mut stream));
+    if written.is_ok() {
+        linger(stream, u64::MAX, Duration::from_millis(REFUSAL_LINGER_MS));
+    }
+}
+
+// Closes a connection whose response has been written, but only once the
+// client has finished sending whatever it was in the middle of, reading and
+// throwing away up to `max_bytes` of it for up to `max_time`. Closing a
+// connection with unread data on it resets it, which can destroy the response
+// before the client reads it. The limits keep a client that never stops
+// sending from holding on to the thread.
+fn linger(mut stream: TcpStream, max_bytes: u64, max_time: Duration) {
+    if stream.shutdown(Shutdown::Write).is_err() {
+        return;
+    }
+
+    let started = Instant::now();
+    let mut discarded = 0;
+    let mut discard = [0; 4096];
+    while discarded < max_bytes {
+        let remaining = max_time.saturating_sub(started.elapsed());
+        if remaining.is_zero() || stream.set_read_timeout(Some(remaining)).is_err() {
+            return;
+        }
+
+        let wanted = cmp::min(discard.len() as u64, max_bytes - discarded) as usize;
+        match stream.read(&mut discard[..wanted]) {
+            Ok(0) => return,
+            Ok(n) => discarded += n as u64,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(_) => return,
+        }
+    }
+}
+
+// A response to a `HEAD` request is written with `head_only`, which leaves the
+// body out but keeps the `Content-Length` it would have had.
+fn write_response<T: Borrow<[u8]>, S: Write>(
+    response: Response<T>,
+    connection: Connection,
+    head_only: bool,
+    mut stream: S,
+) -> Result<(), Error> {
+    let (parts, body) = response.into_parts();
+    let body: &[u8] = body.borrow();
+
+    // Small bodies ride along with the head in a single write; copying them is
+    // cheaper than another syscall. Bigger ones are handed over next to the
+    // head as a vectored write instead.
+    let mut head = serialize_head(&parts, body.len() as u64, connection);
+    let body = if head_only { &[][..] } else { body };
+    let sent = (head.len() + body.len()) as u64;
+    if body.len() <= COALESCE_BODY_LIMIT {
+        head.extend_from_slice(body);
+        stream.write_all(&head)?;
+    } else {
+        write_all_vectored(&mut stream, &head, body)?;
+    }
+    timing::sent(sent);
+    pool::give(head);
+    Ok(stream.flush()?)
+}
+
+// Writes a response whose body is an opened file, which is streamed rather
+// than read into memory first. If the request had a `Range` header, only the
+// ranges it asked for are sent. With `head_only`, none of the file is.
+fn write_file_response(
+    response: Response<File>,
+    range: Option<&http::header::HeaderValue>,
+    connection: Connection,
+    head_only: bool,
+    stream: &mut TcpStream,
+) -> Result<(), Error> {
+    use http::header::{HeaderValue, ACCEPT_RANGES, CONTENT_RANGE, CONTENT_TYPE};
+    use range::Ranges;
+
+    let (mut parts, mut file) = response.into_parts();
+    let len = file.metadata()?.len();
+
+    parts
+        .headers
+        .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let ranges = match range {
+        Some(range) => range::parse(range.as_bytes(), len),
+        None => Ranges::Full,
+    };
+
+    match ranges {
+        Ranges::Full => {
+            let head = serialize_head(&parts, len, connection);
+            stream.write_all(&head)?;
+            timing::sent(head.len() as u64);
+            pool::give(head);
+            if !head_only {
+                file::send(&mut file, 0, len, stream)?;
+                timing::sent(len);
+            }
+        }
+        Ranges::Unsatisfiable => {
+            parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+            parts
+                .headers
+                .insert(CONTENT_RANGE, format!("bytes */{}", len).parse()?);
+            parts
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+
+            let response =
+                Response::from_parts(parts, "<h1>416</h1><p>Range not satisfiable!<p>".as_bytes());
+            return write_response(response, connection, head_only, stream);
+        }
+        Ranges::Satisfiable(ref ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts
+                .headers
+                .insert(CONTENT_RANGE, range.content_range(len).parse()?);
+
+            let head = serialize_head(&parts, range.len(), connection);
+            stream.write_all(&head)?;
+            timing::sent(head.len() as u64);
+            pool::give(head);
+            if !head_only {
+                file::send(&mut file, range.start, range.len(), stream)?;
+                timing::sent(range.len());
+            }
+        }
+        Ranges::Satisfiable(ranges) => {
+            let boundary = range::boundary();
+            let content_type = parts
+                .headers
+                .get(CONTENT_TYPE)
+                .map(|value| value.as_bytes().to_vec())
+                .unwrap_or_else(|| b"application/octet-stream".to_vec());
+
+            let parts_framing: Vec<(Vec<u8>, Vec<u8>)> = ranges
+                .iter()
+                .enumerate()
+                .map(|(i, range)| {
+                    (
+                        range::part_head(&boundary, &content_type, range, len),
+                        range::part_tail(&boundary, i == ranges.len() - 1),
+                    )
+                })
+                .collect();
+            let body_len = ranges
+                .iter()
+                .zip(&parts_framing)
+                .map(|(range, (head, tail))| (head.len() + tail.len()) as u64 + range.len())
+                .sum();
+
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts.headers.insert(
+                CONTENT_TYPE,
+                format!("multipart/byteranges; boundary={}", boundary).parse()?,
+            );
+
+            let head = serialize_head(&parts, body_len, connection);
+            stream.write_all(&head)?;
+            timing::sent(head.len() as u64);
+            pool::give(head);
+            if !head_only {
+                for (range, (part_head, part_tail)) in ranges.iter().zip(&parts_framing) {
+                    stream.write_all(part_head)?;
+                    file::send(&mut file, range.start, range.len(), stream)?;
+                    stream.write_all(part_tail)?;
+                    timing::sent((part_head.len() + part_tail.len()) as u64 + range.len());
+                }
+            }
+        }
+    }
+
+    Ok(stream.flush()?)
+}
+
+// The head is written into a buffer from the pool, which the caller should
+// give back once it's been sent. It's built from bytes directly rather than
+// going through `fmt`, so nothing gets allocated or checked for UTF-8 along
+// the way.
+fn serialize_head(parts: &Parts, content_length: u64, connection: Connection) -> Vec<u8> {
+    let mut head = start_head(parts);
+
+    if !parts.headers.contains_key(http::header::CONNECTION) {
+        write_header(&mut head, b"connection", connection.as_str().as_bytes());
+    }
+    if !parts.headers.contains_key(http::header::CONTENT_LENGTH) {
+        let mut digits = [0; 20];
+        write_header(
+            &mut head,
+            b"content-length",
+            format_u64(content_length, &mut digits),
+        );
+    }
+
+    finish_head(parts, head)
+}
+
+// The head of a response that hands the connection over to the handler. What
+// follows it is up to the new protocol, so it's sent without any framing.
+fn serialize_upgrade_head(parts: &Parts) -> Vec<u8> {
+    finish_head(parts, start_head(parts))
+}
+
+// Starts a response head with the status line and a `Date`.
+fn start_head(parts: &Parts) -> Vec<u8> {
+    trace::status(parts.status);
+    timing::status(parts.status);
+
+    let mut head = pool::take();
+
+    head.extend_from_slice(b"HTTP/1.1 ");
+    head.extend_from_slice(parts.status.as_str().as_bytes());
+    head.push(b' ');
+    // Statuses without a standard reason phrase, like 599, get an empty one,
+    // which is allowed, unless the response brought its own.
+    let reason = match parts.extensions.get::<ReasonPhrase>() {
+        Some(reason) => reason.as_str(),
+        None => parts.status.canonical_reason().unwrap_or(""),
+    };
+    head.extend_from_slice(reason.as_bytes());
+    head.extend_from_slice(b"\r\n");
+
+    if !parts.headers.contains_key(http::header::DATE) {
+        date::with_now(|date| write_header(&mut head, b"date", date.as_bytes()));
+    }
+    head
+}
+
+// Ends a response head with the response's own headers.
+fn finish_head(parts: &Parts, mut head: Vec<u8>) -> Vec<u8> {
+    // Headers with several values, like `set-cookie`, get one line per value,
+    // in the order they were added.
+    for name in parts.headers.keys() {
+        for value in parts.headers.get_all(name) {
+            write_header(&mut head, name.as_str().as_bytes(), value.as_bytes());
+        }
+    }
+
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+fn write_header(head: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    head.extend_from_slice(name);
+    head.extend_from_slice(b": ");
+    head.extend_from_slice(value);
+    head.extend_from_slice(b"\r\n");
+}
+
+// Writes `n` in decimal at the end of `buf`, which is big enough for any
+// `u64`, and returns the digits.
+fn format_u64(mut n: u64, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    &buf[start..]
+}
+
+// Writes `head` followed by `body`, using a single vectored write where the
+// writer supports it. Writers without vectored support fall back to writing
+// one slice at a time, which is what `write_vectored` does by default.
+fn write_all_vectored<S: Write>(
+    stream: &mut S,
+    mut head: &[u8],
+    mut body: &[u8],
+) -> std::io::Result<()> {
+    use std::io::{ErrorKind, IoSlice};
+
+    while !head.is_empty() {
+        let written = match stream.write_vectored(&[IoSlice::new(head), IoSlice::new(body)]) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        if written < head.len() {
+            head = &head[written..];
+        } else {
+            body = &body[written - head.len()..];
+            head = &[];
+        }
+    }
+
+    stream.write_all(body)
+}
+
+#[test]
+fn test_bind_with_backlog_and_nonblocking_accept() {
+    let mut server = Server::new(|_, mut response| Ok(response.body(vec![])?));
+    server.set_listen_backlog(16);
+    server.set_nonblocking_accept(true);
+
+    let listener = server.bind("127.0.0.1", "0").unwrap();
+    match listener.accept() {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        other => panic!("Expected WouldBlock. Got {:?}", other.map(|_| ())),
+    }
+
+    let addr = listener.local_addr().unwrap();
+    let _client = TcpStream::connect(addr).unwrap();
+    listener.set_nonblocking(false).unwrap();
+    assert!(listener.accept().is_ok());
+}
+
+#[test]
+fn test_escape_html() {
+    let mut out = String::from("<pre>");
+    escape_html("Io(Custom { error: \"<db> & 'co'\" })", &mut out);
+
+    assert_eq!(
+        "<pre>Io(Custom { error: &quot;&lt;db&gt; &amp; &#39;co&#39;&quot; })",
+        out
+    );
+}
+
+#[test]
+fn test_format_u64() {
+    let mut buf = [0; 20];
+    for &n in &[0, 7, 10, 4096, 1_000_000_007, u64::MAX] {
+        assert_eq!(n.to_string().as_bytes(), format_u64(n, &mut buf));
+    }
+}
+
+#[test]
+fn test_write_response() {
+    let mut builder = http::response::Builder::new();
+    builder.status(http::StatusCode::OK);
+    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
+    builder.header(http::header::CONTENT_TYPE, "text/plain".as_bytes());
+
     let mut output = vec![];
-    let _ = write_response(builder.body("Hello rust".as_bytes()).unwrap(), &mut output).unwrap();
+    write_response(
+        builder.body("Hello rust".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
     let expected = b"HTTP/1.1 200 OK\r\n\
         connection: close\r\n\
         content-length: 10\r\n\
@@ -475,6 +3835,100 @@ fn test_write_response() {
     assert_eq!(&expected[..], &output[..]);
 }
 
+#[test]
+fn test_write_response_head_only() {
+    let mut builder = http::response::Builder::new();
+    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
+
+    let mut output = vec![];
+    write_response(
+        builder.body("Hello rust".as_bytes()).unwrap(),
+        Connection::KeepAlive,
+        true,
+        &mut output,
+    )
+    .unwrap();
+    let expected = b"HTTP/1.1 200 OK\r\n\
+        connection: keep-alive\r\n\
+        content-length: 10\r\n\
+        date: Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+        \r\n";
+    assert_eq!(&expected[..], &output[..]);
+}
+
+#[test]
+fn test_write_response_latin1_header_value() {
+    let mut builder = http::response::Builder::new();
+    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
+    builder.header(
+        "x-name",
+        http::header::HeaderValue::from_bytes(b"Caf\xe9").unwrap(),
+    );
+
+    let mut output = vec![];
+    write_response(
+        builder.body("".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+    let expected = b"HTTP/1.1 200 OK\r\n\
+        connection: close\r\n\
+        content-length: 0\r\n\
+        date: Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+        x-name: Caf\xe9\r\n\
+        \r\n";
+    assert_eq!(&expected[..], &output[..]);
+}
+
+#[test]
+fn test_write_response_custom_reason() {
+    let mut builder = http::response::Builder::new();
+    builder.status(422);
+    builder.extension(ReasonPhrase::new("Validation Failed").unwrap());
+
+    let mut output = vec![];
+    write_response(
+        builder.body("".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+    assert!(output.starts_with(b"HTTP/1.1 422 Validation Failed\r\n"));
+
+    let mut builder = http::response::Builder::new();
+    builder.status(599);
+    builder.extension(ReasonPhrase::new("Network Connect Timeout").unwrap());
+
+    let mut output = vec![];
+    write_response(
+        builder.body("".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+    assert!(output.starts_with(b"HTTP/1.1 599 Network Connect Timeout\r\n"));
+}
+
+#[test]
+fn test_write_response_status_without_reason() {
+    let mut builder = http::response::Builder::new();
+    builder.status(599);
+
+    let mut output = vec![];
+    write_response(
+        builder.body("".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+    assert!(output.starts_with(b"HTTP/1.1 599 \r\n"));
+}
+
 #[test]
 fn test_write_response_no_headers() {
     let mut builder = http::response::Builder::new();
@@ -484,7 +3938,13 @@ fn test_write_response_no_headers() {
     builder.status(http::StatusCode::OK);
 
     let mut output = vec![];
-    let _ = write_response(builder.body("Hello rust".as_bytes()).unwrap(), &mut output).unwrap();
+    write_response(
+        builder.body("Hello rust".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
     let expected = b"HTTP/1.1 200 OK\r\n\
         connection: close\r\n\
         content-length: 10\r\n\
@@ -493,3 +3953,257 @@ fn test_write_response_no_headers() {
         Hello rust";
     assert_eq!(&expected[..], &output[..]);
 }
+
+#[test]
+fn test_write_response_keep_alive() {
+    let mut builder = http::response::Builder::new();
+    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
+    builder.status(http::StatusCode::OK);
+
+    let mut output = vec![];
+    write_response(
+        builder.body("Hello rust".as_bytes()).unwrap(),
+        Connection::KeepAlive,
+        false,
+        &mut output,
+    )
+    .unwrap();
+    let expected = b"HTTP/1.1 200 OK\r\n\
+        connection: keep-alive\r\n\
+        content-length: 10\r\n\
+        date: Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+        \r\n\
+        Hello rust";
+    assert_eq!(&expected[..], &output[..]);
+}
+
+#[cfg(test)]
+struct TrickleWriter {
+    written: Vec<u8>,
+    writes: usize,
+    max_per_write: usize,
+}
+
+#[cfg(test)]
+impl Write for TrickleWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = std::cmp::min(buf.len(), self.max_per_write);
+        self.written.extend_from_slice(&buf[..n]);
+        self.writes += 1;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_response_handles_short_writes() {
+    let mut builder = http::response::Builder::new();
+    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
+    builder.status(http::StatusCode::OK);
+
+    let body = vec![b'x'; COALESCE_BODY_LIMIT + 1];
+    let mut output = TrickleWriter {
+        written: vec![],
+        writes: 0,
+        max_per_write: 3,
+    };
+    write_response(
+        builder.body(&body[..]).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\n\
+         connection: close\r\n\
+         content-length: {}\r\n\
+         date: Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+         \r\n",
+        body.len()
+    );
+    assert_eq!(head.as_bytes(), &output.written[..head.len()]);
+    assert_eq!(&body[..], &output.written[head.len()..]);
+}
+
+#[test]
+fn test_write_response_coalesces_small_bodies() {
+    let mut builder = http::response::Builder::new();
+    builder.status(http::StatusCode::OK);
+
+    let mut output = TrickleWriter {
+        written: vec![],
+        writes: 0,
+        max_per_write: usize::MAX,
+    };
+    write_response(
+        builder.body("Hello rust".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+
+    assert_eq!(1, output.writes);
+    assert!(output.written.ends_with(b"\r\n\r\nHello rust"));
+}
+
+#[cfg(test)]
+struct VectoredWriter {
+    written: Vec<u8>,
+    chunks: Vec<usize>,
+}
+
+#[cfg(test)]
+impl Write for VectoredWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_vectored(&[std::io::IoSlice::new(buf)])
+    }
+
+    // Accepts the next planned number of bytes, wherever the slice boundaries
+    // fall.
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice]) -> std::io::Result<usize> {
+        let mut budget = if self.chunks.is_empty() {
+            usize::MAX
+        } else {
+            self.chunks.remove(0)
+        };
+        let mut written = 0;
+        for buf in bufs {
+            let n = std::cmp::min(buf.len(), budget);
+            self.written.extend_from_slice(&buf[..n]);
+            written += n;
+            budget -= n;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_all_vectored_resumes_across_the_slice_boundary() {
+    let head = b"0123456789";
+    let body = b"abcdefghij";
+
+    // part of the head, then past the boundary, then the rest of the body
+    let mut output = VectoredWriter {
+        written: vec![],
+        chunks: vec![4, 9, 3],
+    };
+    write_all_vectored(&mut output, head, body).unwrap();
+
+    assert_eq!(&b"0123456789abcdefghij"[..], &output.written[..]);
+    assert!(output.chunks.is_empty());
+}
+
+#[test]
+fn test_write_all_vectored_with_a_whole_head_written() {
+    let mut output = VectoredWriter {
+        written: vec![],
+        chunks: vec![10, 10],
+    };
+    write_all_vectored(&mut output, b"0123456789", b"abcdefghij").unwrap();
+
+    assert_eq!(&b"0123456789abcdefghij"[..], &output.written[..]);
+}
+
+#[test]
+fn test_redirect_location() {
+    let location = |base, uri: &str| redirect_location(base, &uri.parse().unwrap());
+
+    assert_eq!("https://example.com/", location("https://example.com", "/"));
+    assert_eq!(
+        "https://example.com/a/b",
+        location("https://example.com/", "/a/b")
+    );
+    assert_eq!(
+        "https://example.com:8443/search?q=a%20b&x=%2F",
+        location("https://example.com:8443", "/search?q=a%20b&x=%2F")
+    );
+    assert_eq!(
+        "https://example.com//odd/./path?",
+        location("https://example.com", "//odd/./path?")
+    );
+}
+
+#[test]
+fn test_write_response_repeated_headers() {
+    let mut builder = http::response::Builder::new();
+    builder.header(http::header::DATE, "Thu, 01 Jan 1970 00:00:00 GMT");
+    builder.header(http::header::SET_COOKIE, "a=1");
+    builder.header("X-Custom", "first");
+    builder.header(http::header::SET_COOKIE, "b=2");
+    builder.header(http::header::CONTENT_LENGTH, "10");
+
+    let mut output = vec![];
+    write_response(
+        builder.body("Hello rust".as_bytes()).unwrap(),
+        Connection::Close,
+        false,
+        &mut output,
+    )
+    .unwrap();
+    let expected = b"HTTP/1.1 200 OK\r\n\
+        connection: close\r\n\
+        date: Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+        set-cookie: a=1\r\n\
+        set-cookie: b=2\r\n\
+        x-custom: first\r\n\
+        content-length: 10\r\n\
+        \r\n\
+        Hello rust";
+    assert_eq!(&expected[..], &output[..]);
+}
+
+#[test]
+fn test_panic_message() {
+    let message = panic::catch_unwind(|| panic!("static")).unwrap_err();
+    assert_eq!("static", panic_message(&*message));
+
+    let message = panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+    assert_eq!("formatted 1", panic_message(&*message));
+
+    let message = panic::catch_unwind(|| panic::panic_any(1)).unwrap_err();
+    assert_eq!("Box<dyn Any>", panic_message(&*message));
+}
+
+#[test]
+fn test_from_config() {
+    let config = ServerConfig {
+        pool_size: Some(3),
+        request_timeout: Some(Duration::from_secs(5)),
+        static_dir: Some(PathBuf::from("assets")),
+        ..ServerConfig::default()
+    };
+
+    let server = Server::from_config(config, |_, mut response| Ok(response.body(Vec::new())?));
+
+    assert_eq!(
+        Some(ConcurrencyModel::Pool { size: 3 }),
+        server.concurrency_model
+    );
+    assert_eq!(Some(Duration::from_secs(5)), server.timeout);
+    assert_eq!(
+        vec![PathBuf::from("assets")],
+        *server.static_directories.current()
+    );
+    assert_eq!(128, server.queue_depth);
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn test_is_loopback() {
+    for ip in &["127.0.0.1", "127.8.0.1", "::1", "::ffff:127.0.0.1"] {
+        assert!(is_loopback(ip.parse().unwrap()), "{}", ip);
+    }
+    for ip in &["10.0.0.1", "::", "::ffff:10.0.0.1", "fe80::1"] {
+        assert!(!is_loopback(ip.parse().unwrap()), "{}", ip);
+    }
+}
@@ -0,0 +1,283 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use timing::Exchange;
+
+/// Counters describing what a running server has been up to.
+///
+/// They're available from `Server::metrics`. To read them while the server
+/// is listening, share the server between threads with an `Arc`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    reaped_connections: AtomicUsize,
+    rejected_connects: AtomicUsize,
+    exhausted_accepts: AtomicUsize,
+    overloaded_connections: AtomicUsize,
+    busy_workers: AtomicUsize,
+    peak_busy_workers: AtomicUsize,
+    queued_connections: AtomicUsize,
+    peak_queued_connections: AtomicUsize,
+    responses: [AtomicUsize; 5],
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+/// A snapshot of how busy a server's threads are, from `Server::pool_stats`.
+///
+/// Peaks are the highest the numbers have been since the server was made.
+/// Without a pool, with `ConcurrencyModel::ThreadPerConnection`, every
+/// connection being handled counts as a busy worker, and nothing's queued.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The number of threads handling a connection right now.
+    pub busy_workers: usize,
+    /// The most threads that have been handling a connection at once.
+    pub peak_busy_workers: usize,
+    /// The number of accepted connections waiting for a thread.
+    pub queued_connections: usize,
+    /// The most connections that have been waiting for a thread at once.
+    pub peak_queued_connections: usize,
+}
+
+/// Counts a thread as busy for as long as it's kept, panics included.
+pub struct Busy<'a>(&'a Metrics);
+
+impl<'a> Drop for Busy<'a> {
+    fn drop(&mut self) {
+        self.0.busy_workers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Metrics {
+    /// The number of connections closed for sitting idle for longer than the
+    /// idle timeout.
+    pub fn reaped_connections(&self) -> usize {
+        self.reaped_connections.load(Ordering::Relaxed)
+    }
+
+    /// The number of `CONNECT` requests turned away. They're almost always
+    /// someone looking for an open proxy.
+    pub fn rejected_connects(&self) -> usize {
+        self.rejected_connects.load(Ordering::Relaxed)
+    }
+
+    /// The number of times accepting a connection failed because the process
+    /// or the system ran out of file descriptors, each of which was followed
+    /// by a short pause.
+    pub fn exhausted_accepts(&self) -> usize {
+        self.exhausted_accepts.load(Ordering::Relaxed)
+    }
+
+    /// The number of connections answered with a `503 Service Unavailable`
+    /// because every worker thread was busy and the queue of connections
+    /// waiting for one was full.
+    pub fn overloaded_connections(&self) -> usize {
+        self.overloaded_connections.load(Ordering::Relaxed)
+    }
+
+    /// The number of responses sent, by the class of their status: `1xx`
+    /// first, then `2xx`, and so on up to `5xx`.
+    ///
+    /// A connection that's taken over by the handler counts as a `1xx`, for
+    /// its `101 Switching Protocols`. Interim responses sent ahead of a final
+    /// one aren't counted, and neither are connections turned away because
+    /// the server was overloaded; those are counted by
+    /// `overloaded_connections`.
+    pub fn responses_by_class(&self) -> [usize; 5] {
+        let mut responses = [0; 5];
+        for (count, counter) in responses.iter_mut().zip(&self.responses) {
+            *count = counter.load(Ordering::Relaxed);
+        }
+        responses
+    }
+
+    /// The number of bytes read from clients while reading requests.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes of responses written to clients, heads included.
+    /// Interim responses sent by the handler aren't counted.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn responded(&self, exchange: &Exchange) {
+        if let Some(status) = exchange.status {
+            let class = (status.as_u16() / 100) as usize;
+            if let Some(counter) = self.responses.get(class.wrapping_sub(1)) {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.bytes_sent.fetch_add(exchange.sent, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_reaped(&self) {
+        self.reaped_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connect_rejected(&self) {
+        self.rejected_connects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn accept_exhausted(&self) {
+        self.exhausted_accepts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_overloaded(&self) {
+        self.overloaded_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            busy_workers: self.busy_workers.load(Ordering::SeqCst),
+            peak_busy_workers: self.peak_busy_workers.load(Ordering::SeqCst),
+            queued_connections: self.queued_connections.load(Ordering::SeqCst),
+            peak_queued_connections: self.peak_queued_connections.load(Ordering::SeqCst),
+        }
+    }
+
+    pub(crate) fn busy(&self) -> Busy<'_> {
+        let busy = self.busy_workers.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak_busy_workers.fetch_max(busy, Ordering::SeqCst);
+        Busy(self)
+    }
+
+    // Called before a connection is queued, so that the worker that takes it
+    // can't count it off first.
+    pub(crate) fn connection_queued(&self) {
+        let queued = self.queued_connections.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak_queued_connections
+            .fetch_max(queued, Ordering::SeqCst);
+    }
+
+    pub(crate) fn connection_dequeued(&self) {
+        self.queued_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Counts the bytes read through it as received, and those written through it
+/// as sent.
+pub struct Counted<'a, S: 'a> {
+    stream: &'a mut S,
+    metrics: &'a Metrics,
+}
+
+impl<'a, S> Counted<'a, S> {
+    pub fn new(stream: &'a mut S, metrics: &'a Metrics) -> Counted<'a, S> {
+        Counted { stream, metrics }
+    }
+}
+
+impl<'a, S: Read> Read for Counted<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        self.metrics
+            .bytes_received
+            .fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<'a, S: Write> Write for Counted<'a, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.metrics
+            .bytes_sent
+            .fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod metrics_should {
+    use super::*;
+
+    #[test]
+    fn count_busy_workers_until_they_finish() {
+        let metrics = Metrics::default();
+
+        let first = metrics.busy();
+        let second = metrics.busy();
+        assert_eq!(2, metrics.pool_stats().busy_workers);
+
+        drop(first);
+        drop(second);
+        let stats = metrics.pool_stats();
+        assert_eq!(0, stats.busy_workers);
+        assert_eq!(2, stats.peak_busy_workers);
+    }
+
+    #[test]
+    fn stop_counting_a_worker_that_panics() {
+        let metrics = Metrics::default();
+
+        let result = ::std::panic::catch_unwind(|| {
+            let _busy = metrics.busy();
+            panic!("handler panicked");
+        });
+
+        assert!(result.is_err());
+        assert_eq!(0, metrics.pool_stats().busy_workers);
+        assert_eq!(1, metrics.pool_stats().peak_busy_workers);
+    }
+
+    #[test]
+    fn count_responses_by_the_class_of_their_status() {
+        use http::StatusCode;
+
+        let metrics = Metrics::default();
+
+        for &(status, sent) in &[
+            (Some(StatusCode::OK), 100),
+            (Some(StatusCode::NO_CONTENT), 50),
+            (Some(StatusCode::SWITCHING_PROTOCOLS), 20),
+            (Some(StatusCode::NOT_FOUND), 30),
+            (None, 0),
+        ] {
+            metrics.responded(&Exchange {
+                status,
+                sent,
+                ..Exchange::default()
+            });
+        }
+
+        assert_eq!([1, 2, 0, 1, 0], metrics.responses_by_class());
+        assert_eq!(200, metrics.bytes_sent());
+    }
+
+    #[test]
+    fn count_the_bytes_that_go_through_a_stream() {
+        let metrics = Metrics::default();
+        let mut stream = io::Cursor::new(b"GET / HTTP/1.1\r\n\r\n".to_vec());
+
+        let mut request = Vec::new();
+        Counted::new(&mut stream, &metrics)
+            .read_to_end(&mut request)
+            .unwrap();
+        Counted::new(&mut stream, &metrics)
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .unwrap();
+
+        assert_eq!(18, metrics.bytes_received());
+        assert_eq!(25, metrics.bytes_sent());
+    }
+
+    #[test]
+    fn track_the_deepest_queue() {
+        let metrics = Metrics::default();
+
+        metrics.connection_queued();
+        metrics.connection_queued();
+        metrics.connection_dequeued();
+        metrics.connection_queued();
+
+        let stats = metrics.pool_stats();
+        assert_eq!(2, stats.queued_connections);
+        assert_eq!(2, stats.peak_queued_connections);
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Content types for the file extensions commonly found in a static
+// directory. Anything else is sent without a content type.
+const TYPES: &[(&str, &str)] = &[
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("gif", "image/gif"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/x-icon"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "application/javascript"),
+    ("json", "application/json"),
+    ("md", "text/markdown"),
+    ("mjs", "application/javascript"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("ogg", "audio/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("svg", "image/svg+xml"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("wasm", "application/wasm"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xml", "text/xml"),
+    ("zip", "application/zip"),
+];
+
+/// Works out the content type of a file from its extension, preferring any
+/// type set up in `overrides`.
+pub fn guess<'a>(path: &Path, overrides: &'a HashMap<String, String>) -> Option<&'a str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    if let Some(content_type) = overrides.get(&extension) {
+        return Some(content_type);
+    }
+
+    TYPES
+        .iter()
+        .find(|&&(known, _)| known == extension)
+        .map(|&(_, content_type)| content_type)
+}
+
+/// Adds a `charset` parameter to text content types, so that browsers don't
+/// have to guess the encoding.
+///
+/// Binary types, and types that already have a charset, are left alone.
+pub fn with_charset<'a>(content_type: &'a str, charset: &str) -> Cow<'a, str> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    let is_text = essence.starts_with("text/")
+        || essence == "application/javascript"
+        || essence == "application/json";
+    let has_charset = content_type
+        .split(';')
+        .skip(1)
+        .any(|param| param.trim().to_ascii_lowercase().starts_with("charset="));
+
+    if is_text && !has_charset {
+        Cow::Owned(format!("{}; charset={}", content_type, charset))
+    } else {
+        Cow::Borrowed(content_type)
+    }
+}
+
+#[cfg(test)]
+mod mime_should {
+    use super::*;
+
+    #[test]
+    fn guess_from_the_extension() {
+        let overrides = HashMap::new();
+
+        assert_eq!(
+            Some("text/html"),
+            guess(Path::new("a/index.html"), &overrides)
+        );
+        assert_eq!(Some("image/png"), guess(Path::new("logo.PNG"), &overrides));
+        assert_eq!(None, guess(Path::new("README"), &overrides));
+        assert_eq!(None, guess(Path::new("archive.unknown"), &overrides));
+    }
+
+    #[test]
+    fn prefer_overrides() {
+        let mut overrides = HashMap::new();
+        overrides.insert("js".to_string(), "text/javascript".to_string());
+
+        assert_eq!(
+            Some("text/javascript"),
+            guess(Path::new("app.js"), &overrides)
+        );
+    }
+
+    #[test]
+    fn add_a_charset_to_text_types() {
+        assert_eq!(
+            "text/html; charset=utf-8",
+            with_charset("text/html", "utf-8")
+        );
+        assert_eq!(
+            "application/json; charset=utf-8",
+            with_charset("application/json", "utf-8")
+        );
+        assert_eq!(
+            "application/javascript; charset=latin1",
+            with_charset("application/javascript", "latin1")
+        );
+    }
+
+    #[test]
+    fn leave_binary_types_and_existing_charsets_alone() {
+        assert_eq!("image/png", with_charset("image/png", "utf-8"));
+        assert_eq!("application/pdf", with_charset("application/pdf", "utf-8"));
+        assert_eq!(
+            "text/plain; Charset=ISO-8859-1",
+            with_charset("text/plain; Charset=ISO-8859-1", "utf-8")
+        );
+    }
+}
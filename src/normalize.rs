@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+
+/// Turns the path of a request into a path relative to a static directory.
+///
+/// The path is percent-decoded one segment at a time, and resolved purely
+/// lexically: repeated slashes and `.` segments are dropped, and `..` removes
+/// the segment before it. `None` comes back for paths that would climb out of
+/// the directory, or that decode to something that isn't a plain file name,
+/// like a segment with a slash or a NUL in it. On Windows, segments that
+/// Windows wouldn't treat as an ordinary file name are refused too; see
+/// `windows_file_name`.
+///
+/// The root, `/`, comes back as an empty path.
+pub fn static_path(path: &str) -> Option<PathBuf> {
+    let mut segments: Vec<String> = vec![];
+
+    for segment in path.split('/') {
+        let segment = percent_decode(segment)?;
+
+        match &*segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            _ if segment.contains(['/', '\\', '\0']) => return None,
+            _ if cfg!(windows) && !windows_file_name(&segment) => return None,
+            _ => segments.push(segment),
+        }
+    }
+
+    Some(segments.iter().collect())
+}
+
+// The names Windows gives to devices, which open the device rather than a file
+// in any directory, whatever extension follows them.
+const RESERVED_NAMES: &[&str] = &[
+    "CON",
+    "PRN",
+    "AUX",
+    "NUL",
+    "CONIN$",
+    "CONOUT$",
+    "COM0",
+    "COM1",
+    "COM2",
+    "COM3",
+    "COM4",
+    "COM5",
+    "COM6",
+    "COM7",
+    "COM8",
+    "COM9",
+    "COM\u{b9}",
+    "COM\u{b2}",
+    "COM\u{b3}",
+    "LPT0",
+    "LPT1",
+    "LPT2",
+    "LPT3",
+    "LPT4",
+    "LPT5",
+    "LPT6",
+    "LPT7",
+    "LPT8",
+    "LPT9",
+    "LPT\u{b9}",
+    "LPT\u{b2}",
+    "LPT\u{b3}",
+];
+
+/// Whether a decoded path segment is something Windows would open as an
+/// ordinary file of that name.
+///
+/// Windows reads a colon as the end of a drive letter or the start of a
+/// stream name, quietly drops dots and spaces from the end of a name, so that
+/// `secret.txt.` opens `secret.txt`, and opens a device for names like `CON`
+/// or `nul.txt`. Segments like those, or with any other character Windows
+/// doesn't allow in a file name, are refused.
+pub fn windows_file_name(segment: &str) -> bool {
+    if segment.is_empty() || segment.ends_with(['.', ' ']) {
+        return false;
+    }
+
+    if segment
+        .chars()
+        .any(|c| c.is_control() || "\\/:*?\"<>|".contains(c))
+    {
+        return false;
+    }
+
+    let stem = segment.split('.').next().unwrap_or(segment).trim_end();
+    !RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem))
+}
+
+/// Turns a path that came from `static_path` back into an absolute URL path,
+/// percent-encoding each segment.
+///
+/// The result always starts with a single slash, so it can't be mistaken for
+/// a URL with a host of its own, the way `//example.com` would be.
+pub fn url_path(path: &Path) -> String {
+    let mut url = String::new();
+
+    for segment in path.iter() {
+        url.push('/');
+        for &b in segment.to_string_lossy().as_bytes() {
+            if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&b) {
+                url.push(b as char);
+            } else {
+                url.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+
+    if url.is_empty() {
+        url.push('/');
+    }
+    url
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    if !segment.contains('%') {
+        return Some(segment.to_string());
+    }
+
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = ::std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod normalize_should {
+    use super::*;
+
+    #[test]
+    fn resolve_odd_but_legal_paths() {
+        let cases = [
+            ("/", ""),
+            ("//", ""),
+            ("/index.html", "index.html"),
+            ("//index.html", "index.html"),
+            ("/./style.css", "style.css"),
+            ("/foo//bar.png", "foo/bar.png"),
+            ("/foo/./bar.png", "foo/bar.png"),
+            ("/foo/../bar.png", "bar.png"),
+            ("/foo/bar/../../baz", "baz"),
+            ("/foo/", "foo"),
+            ("/foo/.", "foo"),
+            ("/a%20file.txt", "a file.txt"),
+            ("/%C3%A9t%C3%A9.html", "\u{e9}t\u{e9}.html"),
+            ("/%2e/style.css", "style.css"),
+        ];
+
+        for &(path, expected) in &cases {
+            assert_eq!(Some(PathBuf::from(expected)), static_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn encode_paths_back_into_urls() {
+        let cases = [
+            ("", "/"),
+            ("docs", "/docs"),
+            ("pub2", "/pub2"),
+            ("foo/bar.png", "/foo/bar.png"),
+            ("a file.txt", "/a%20file.txt"),
+            ("what?#", "/what%3F%23"),
+            ("\u{e9}t\u{e9}.html", "/%C3%A9t%C3%A9.html"),
+        ];
+
+        for &(path, expected) in &cases {
+            assert_eq!(expected, url_path(Path::new(path)), "{}", path);
+        }
+    }
+
+    #[test]
+    fn refuse_to_climb_out() {
+        for path in &[
+            "/..",
+            "/../secret",
+            "/foo/../../secret",
+            "/%2e%2e/secret",
+            "/foo/%2E%2E/%2e%2e/secret",
+        ] {
+            assert_eq!(None, static_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn refuse_segments_that_are_not_file_names() {
+        for path in &[
+            "/foo%2fbar",
+            "/foo%5Cbar",
+            "/foo\\bar",
+            "/..%5C..%5Cwindows/win.ini",
+            "/%5C%5Cserver%5Cshare/file",
+            "/foo%00",
+            "/%ff",
+            "/%zz",
+            "/%4",
+        ] {
+            assert_eq!(None, static_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn accept_ordinary_windows_file_names() {
+        for segment in &[
+            "index.html",
+            ".well-known",
+            "a file.txt",
+            "CONFIG",
+            "console.log",
+            "nullable.js",
+            "COM10",
+            "LPT",
+            "\u{e9}t\u{e9}.html",
+        ] {
+            assert!(windows_file_name(segment), "{}", segment);
+        }
+    }
+
+    #[test]
+    fn refuse_names_windows_reads_differently() {
+        for segment in &[
+            "C:",
+            "c:secret",
+            "secret.txt::$DATA",
+            "secret.txt.",
+            "secret.txt ",
+            "secret. . ",
+            "back\\slash",
+            "what?",
+            "star*",
+            "tab\t",
+            "CON",
+            "con",
+            "Nul.txt",
+            "aux.tar.gz",
+            "COM1",
+            "lpt9.log",
+            "PRN .txt",
+            "CONOUT$",
+            "COM\u{b9}",
+        ] {
+            assert!(!windows_file_name(segment), "{}", segment);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn refuse_paths_windows_would_misread() {
+        for path in &[
+            "/C:/windows/win.ini",
+            "/C%3A/windows/win.ini",
+            "/secret.txt.",
+            "/secret.txt%20",
+            "/secret.txt::$DATA",
+            "/CON",
+            "/foo/nul.txt",
+            "/com1",
+        ] {
+            assert_eq!(None, static_path(path), "{}", path);
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn keep_names_only_windows_objects_to() {
+        for &(path, expected) in &[
+            ("/CON", "CON"),
+            ("/foo/nul.txt", "foo/nul.txt"),
+            ("/notes.", "notes."),
+            ("/C:", "C:"),
+        ] {
+            assert_eq!(Some(PathBuf::from(expected)), static_path(path), "{}", path);
+        }
+    }
+}
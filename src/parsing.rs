@@ -1,3 +1,4 @@
+use error::Error;
 use httparse;
 
 struct RequestMethodIndices(usize, usize);
@@ -14,6 +15,7 @@ struct HeaderIndices {
 pub struct Request {
     method: RequestMethodIndices,
     proto: RequestProtocolIndices,
+    version: u8,
     headers: Vec<HeaderIndices>,
     body: (usize, usize),
     buffer: Vec<u8>,
@@ -32,12 +34,51 @@ impl Request {
         body
     }
 
+    /// How long the head of the request is, up to the start of the body.
+    pub fn head_len(&self) -> usize {
+        self.body.0
+    }
+
+    /// How many bytes of the body have been read so far.
+    pub fn body_len(&self) -> usize {
+        self.body.1 - self.body.0
+    }
+
+    /// Adds bytes read after the head to the end of the body.
+    pub fn extend_body(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        self.body.1 = self.buffer.len();
+    }
+
+    /// Cuts the body off after `len` bytes, moving anything read past it onto
+    /// the end of `rest`.
+    pub fn truncate_body(&mut self, len: usize, rest: &mut Vec<u8>) {
+        if len < self.body_len() {
+            self.body.1 = self.body.0 + len;
+            rest.extend_from_slice(&self.buffer[self.body.1..]);
+            self.buffer.truncate(self.body.1);
+        }
+    }
+
+    /// Gives up the buffer the request was read into, so that it can be
+    /// reused.
+    pub fn into_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+
     pub fn method(&self) -> &str {
-        ::std::str::from_utf8(&self.buffer[self.method.0..self.method.1]).unwrap()
+        ::std::str::from_utf8(&self.buffer[self.method.0..self.method.1])
+            .expect("the method is checked to be UTF-8 when the request is parsed")
+    }
+
+    /// The minor version of HTTP/1.x that the request was made with.
+    pub fn version(&self) -> u8 {
+        self.version
     }
 
     pub fn path(&self) -> &str {
-        ::std::str::from_utf8(&self.buffer[self.proto.path.0..self.proto.path.1]).unwrap()
+        ::std::str::from_utf8(&self.buffer[self.proto.path.0..self.proto.path.1])
+            .expect("the path is checked to be UTF-8 when the request is parsed")
     }
 
     pub fn headers<'a>(&'a self) -> HeaderIter<'a> {
@@ -52,17 +93,10 @@ impl<'a> Iterator for HeaderIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         use std::str;
-        self.1.next().map(
-            |&HeaderIndices {
-                 ref name,
-                 ref value,
-             }| {
-                Header {
-                    name: str::from_utf8(&self.0[name.0..name.1]).unwrap(),
-                    value: &self.0[value.0..value.1],
-                }
-            },
-        )
+        self.1.next().map(|HeaderIndices { name, value }| Header {
+            name: str::from_utf8(&self.0[name.0..name.1]).unwrap(),
+            value: &self.0[value.0..value.1],
+        })
     }
 }
 
@@ -76,55 +110,107 @@ fn slice_indices(buffer: &[u8], value: &[u8]) -> (usize, usize) {
     (start, start + value.len())
 }
 
-pub fn try_parse_request(buffer: Vec<u8>) -> Result<ParseResult, httparse::Error> {
+// Like `slice_indices`, but for a part of the request line that's handed out
+// as a `str` later, which is refused here if it isn't valid UTF-8.
+fn str_indices(buffer: &[u8], value: &[u8]) -> Result<(usize, usize), Error> {
+    match ::std::str::from_utf8(value) {
+        Ok(_) => Ok(slice_indices(buffer, value)),
+        Err(_) => Err(httparse::Error::Token.into()),
+    }
+}
+
+// Checks for a complete request line made up of only a method and a target,
+// which is what an HTTP/0.9 request looks like.
+fn is_missing_version(buffer: &[u8]) -> bool {
+    match buffer.iter().position(|&b| b == b'\n') {
+        Some(end) => {
+            let line = buffer[..end].strip_suffix(b"\r").unwrap_or(&buffer[..end]);
+            let mut parts = line.split(|&b| b == b' ').filter(|part| !part.is_empty());
+            parts.next().is_some() && parts.next().is_some() && parts.next().is_none()
+        }
+        None => false,
+    }
+}
+
+// Checks for a header line continued onto the next one by starting it with
+// whitespace, which RFC 7230 calls obs-fold. Only the head is looked at.
+fn has_obs_fold(buffer: &[u8]) -> bool {
+    let mut after_header = false;
+
+    for line in buffer
+        .split(|&b| b == b'\n')
+        .skip(1)
+        .take_while(|line| !line.is_empty() && *line != b"\r")
+    {
+        if after_header && (line[0] == b' ' || line[0] == b'\t') {
+            return true;
+        }
+        after_header = line.contains(&b':');
+    }
+    false
+}
+
+// Counts the calls to `try_parse_request` made by the current thread, so
+// that tests can check how often a request is parsed.
+#[cfg(test)]
+thread_local!(pub static PARSE_CALLS: ::std::cell::Cell<usize> = const { ::std::cell::Cell::new(0) });
+
+pub fn try_parse_request(buffer: Vec<u8>) -> Result<ParseResult, Error> {
+    #[cfg(test)]
+    PARSE_CALLS.with(|calls| calls.set(calls.get() + 1));
+
     let result = {
         let mut header_buffer = [httparse::EMPTY_HEADER; 32];
         let mut request = httparse::Request::new(&mut header_buffer);
-        let request = match request.parse(&*buffer)? {
-            httparse::Status::Partial => None,
-            httparse::Status::Complete(n) => Some((request, n)),
+        let request = match request.parse(&buffer) {
+            Ok(httparse::Status::Partial) => None,
+            Ok(httparse::Status::Complete(n)) if has_obs_fold(&buffer[..n]) => {
+                return Err(Error::ObsoleteLineFolding)
+            }
+            Ok(httparse::Status::Complete(n)) => Some((request, n)),
+            Err(_) if is_missing_version(&buffer) => return Err(Error::MissingVersion),
+            Err(_) if has_obs_fold(&buffer) => return Err(Error::ObsoleteLineFolding),
+            Err(e) => return Err(e.into()),
         };
 
         request
-            .map(|(r, n)| {
+            .map(|(r, n)| -> Result<_, Error> {
                 let proto = RequestProtocolIndices {
-                    path: slice_indices(&*buffer, r.path.unwrap().as_bytes()),
+                    path: str_indices(&buffer, r.path.unwrap().as_bytes())?,
                 };
 
-                let method = slice_indices(&*buffer, r.method.unwrap().as_bytes());
+                let method = str_indices(&buffer, r.method.unwrap().as_bytes())?;
                 let method = RequestMethodIndices(method.0, method.1);
 
-                (r, method, proto, n)
-            }).map(|(r, method, proto, n)| {
+                Ok((r, method, proto, n))
+            })
+            .transpose()?
+            .map(|(r, method, proto, n)| {
+                let version = r.version.unwrap();
                 let headers = r
                     .headers
                     .iter()
-                    .map(
-                        |&httparse::Header {
-                             ref name,
-                             ref value,
-                         }| {
-                            HeaderIndices {
-                                name: slice_indices(&*buffer, name.as_bytes()),
-                                value: slice_indices(&*buffer, value),
-                            }
-                        },
-                    ).collect::<Vec<_>>();
-                (method, proto, headers, n)
+                    .map(|httparse::Header { name, value }| HeaderIndices {
+                        name: slice_indices(&buffer, name.as_bytes()),
+                        value: slice_indices(&buffer, value),
+                    })
+                    .collect::<Vec<_>>();
+                (method, proto, version, headers, n)
             })
     };
 
-    if let Some((method, proto, headers, n)) = result {
+    if let Some((method, proto, version, headers, n)) = result {
         return Ok(ParseResult::Complete(Request {
-            method: method,
-            proto: proto,
-            headers: headers,
-            body: slice_indices(&*buffer, &buffer[n..]),
-            buffer: buffer,
+            method,
+            proto,
+            version,
+            headers,
+            body: slice_indices(&buffer, &buffer[n..]),
+            buffer,
         }));
     }
 
-    return Ok(ParseResult::Partial(buffer));
+    Ok(ParseResult::Partial(buffer))
 }
 
 #[cfg(test)]
@@ -145,4 +231,83 @@ mod parsing_should {
             ParseResult::Partial(_) => panic!("Expected Complete. Got Partial!"),
         }
     }
+
+    #[test]
+    fn reject_a_request_line_without_version() {
+        for request in &[&b"GET /\r\n"[..], b"GET /\r\n\r\n", b"GET /index.html\n"] {
+            match try_parse_request(request.to_vec()) {
+                Err(Error::MissingVersion) => {}
+                Err(e) => panic!("Expected MissingVersion. Got {:?}", e),
+                Ok(_) => panic!("Expected MissingVersion. Got Ok(_)"),
+            }
+        }
+    }
+
+    #[test]
+    fn wait_for_the_rest_of_a_request_line() {
+        match try_parse_request(b"GET /".to_vec()) {
+            Ok(ParseResult::Partial(_)) => {}
+            _ => panic!("Expected Partial."),
+        }
+    }
+
+    #[test]
+    fn accept_an_unknown_method_token() {
+        match try_parse_request(b"BREW /pot HTTP/1.1\r\n\r\n".to_vec()) {
+            Ok(ParseResult::Complete(r)) => assert_eq!("BREW", r.method()),
+            _ => panic!("Expected Complete."),
+        }
+    }
+
+    #[test]
+    fn reject_a_missing_crlf_before_headers() {
+        match try_parse_request(b"GET / HTTP/1.1Host: x\r\n\r\n".to_vec()) {
+            Err(Error::HttpParse(httparse::Error::NewLine)) => {}
+            Err(e) => panic!("Expected a parse error. Got {:?}", e),
+            Ok(_) => panic!("Expected a parse error. Got Ok(_)"),
+        }
+    }
+
+    #[test]
+    fn reject_folded_headers() {
+        for request in &[
+            &b"GET / HTTP/1.1\r\nX-Long-Header: first\r\n second\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nX-Long-Header: first\r\n\tsecond\r\nHost: x\r\n\r\n",
+            b"GET / HTTP/1.1\nX-Long-Header: first\n second\n\n",
+        ] {
+            match try_parse_request(request.to_vec()) {
+                Err(Error::ObsoleteLineFolding) => {}
+                Err(e) => panic!("Expected ObsoleteLineFolding. Got {:?}", e),
+                Ok(_) => panic!("Expected ObsoleteLineFolding. Got Ok(_)"),
+            }
+        }
+    }
+
+    #[test]
+    fn reject_a_request_line_that_isnt_utf8() {
+        for request in &[
+            &b"GET /caf\xff HTTP/1.1\r\n\r\n"[..],
+            b"G\xffT / HTTP/1.1\r\n\r\n",
+            b"\xff / HTTP/1.1\r\n\r\n",
+        ] {
+            match try_parse_request(request.to_vec()) {
+                Err(Error::HttpParse(_)) => {}
+                Err(e) => panic!("Expected a parse error. Got {:?}", e),
+                Ok(_) => panic!("Expected a parse error. Got Ok(_)"),
+            }
+        }
+    }
+
+    #[test]
+    fn accept_spaces_inside_header_values() {
+        let request = b"POST / HTTP/1.1\r\nX-Long-Header: first  second\r\n\r\n body".to_vec();
+
+        match try_parse_request(request).unwrap() {
+            ParseResult::Complete(r) => {
+                let header = r.headers().next().unwrap();
+                assert_eq!(&b"first  second"[..], header.value);
+            }
+            ParseResult::Partial(_) => panic!("Expected Complete. Got Partial!"),
+        }
+    }
 }
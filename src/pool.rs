@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+
+// Each worker thread keeps a few spare buffers, which are enough for reading
+// one request and writing the head of its response.
+const MAX_POOLED: usize = 4;
+
+// Buffers that grew bigger than this, say for a large request body, are
+// freed rather than pinning the memory for good.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+// New buffers start at this size, which fits the head of most requests.
+const INITIAL_CAPACITY: usize = 512;
+
+thread_local!(static FREE: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) });
+
+/// Checks an empty buffer out of this thread's pool, allocating one if the
+/// pool is empty.
+pub fn take() -> Vec<u8> {
+    FREE.with(|free| free.borrow_mut().pop())
+        .unwrap_or_else(|| Vec::with_capacity(INITIAL_CAPACITY))
+}
+
+/// Returns a buffer to this thread's pool for reuse, unless it's grown too
+/// big or the pool is already full.
+pub fn give(mut buffer: Vec<u8>) {
+    if buffer.capacity() == 0 || buffer.capacity() > MAX_POOLED_CAPACITY {
+        return;
+    }
+
+    buffer.clear();
+    FREE.with(|free| {
+        let mut free = free.borrow_mut();
+        if free.len() < MAX_POOLED {
+            free.push(buffer);
+        }
+    });
+}
+
+#[cfg(test)]
+mod pool_should {
+    use super::*;
+
+    #[test]
+    fn reuse_buffers() {
+        let buffer = take();
+        let reused = buffer.as_ptr();
+        give(buffer);
+
+        let mut buffer = take();
+        assert_eq!(reused, buffer.as_ptr());
+
+        buffer.extend_from_slice(&[b'x'; INITIAL_CAPACITY]);
+        let reused = buffer.as_ptr();
+        give(buffer);
+
+        let buffer = take();
+        assert_eq!(reused, buffer.as_ptr());
+    }
+
+    #[test]
+    fn hand_out_empty_buffers() {
+        let mut buffer = take();
+        buffer.extend_from_slice(b"left over");
+        give(buffer);
+
+        assert!(take().is_empty());
+    }
+
+    #[test]
+    fn drop_buffers_that_grew_too_big() {
+        let mut buffer = take();
+        buffer.reserve(MAX_POOLED_CAPACITY + 1);
+        give(buffer);
+
+        assert!(take().capacity() <= MAX_POOLED_CAPACITY);
+    }
+}
@@ -0,0 +1,191 @@
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+use trace;
+
+/// What's known about a request whose body is still arriving.
+///
+/// This is what a `Server::on_body_progress` callback is shown, so that it
+/// can tell uploads that are arriving at the same time apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeadInfo {
+    method: String,
+    path: String,
+    peer: Option<SocketAddr>,
+}
+
+impl RequestHeadInfo {
+    /// Returns the request's method, as the client sent it.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the request's target, as the client sent it.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the address of the client, if it's known.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+}
+
+/// A callback that's told how much of a request's body has arrived so far,
+/// and how much there is in all, if that's known.
+pub type BodyProgressHook<'a> = dyn Fn(&RequestHeadInfo, u64, Option<u64>) + Send + Sync + 'a;
+
+/// How much of a body has to arrive, at most, before the progress callback is
+/// called again, unless the server is set up otherwise.
+pub const DEFAULT_PROGRESS_BYTES: u64 = 64 * 1024;
+
+/// How long the progress callback goes without being called, at most, while
+/// a body is arriving, unless the server is set up otherwise.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Someone watching the bodies arriving on a connection, and how often to
+/// tell them about it.
+pub struct Watch<'a> {
+    pub hook: &'a BodyProgressHook<'a>,
+    pub peer: Option<SocketAddr>,
+    pub bytes: u64,
+    pub interval: Duration,
+}
+
+impl<'a> Watch<'a> {
+    /// Starts tracking the body of a request with `method` and `path`, of
+    /// which `received` bytes arrived along with its head.
+    pub fn start(
+        &self,
+        method: &str,
+        path: &str,
+        received: u64,
+        content_length: Option<u64>,
+    ) -> Progress<'_> {
+        Progress {
+            watch: self,
+            info: RequestHeadInfo {
+                method: method.to_string(),
+                path: path.to_string(),
+                peer: self.peer,
+            },
+            content_length,
+            reported: received,
+            reported_at: Instant::now(),
+            panicked: false,
+        }
+    }
+}
+
+/// The progress of one request's body.
+pub struct Progress<'a> {
+    watch: &'a Watch<'a>,
+    info: RequestHeadInfo,
+    content_length: Option<u64>,
+    reported: u64,
+    reported_at: Instant,
+    panicked: bool,
+}
+
+impl<'a> Progress<'a> {
+    /// Notes that `received` bytes of the body have arrived, calling the
+    /// hook if enough has arrived or enough time has passed since it was
+    /// last called, or if that's the whole body.
+    pub fn update(&mut self, received: u64) {
+        let complete = self.content_length == Some(received);
+        let due = received - self.reported >= self.watch.bytes
+            || self.reported_at.elapsed() >= self.watch.interval;
+
+        if self.panicked || received == self.reported || !(complete || due) {
+            return;
+        }
+
+        self.reported = received;
+        self.reported_at = Instant::now();
+
+        // The hook runs in the middle of reading a request, so a panic in it
+        // mustn't be allowed to take the connection down. Once it's panicked,
+        // it isn't called again for the rest of the body.
+        let hook = self.watch.hook;
+        let info = &self.info;
+        let content_length = self.content_length;
+        if panic::catch_unwind(AssertUnwindSafe(|| hook(info, received, content_length))).is_err() {
+            self.panicked = true;
+            trace::progress_hook_panicked(info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod progress_should {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn watch<'a>(hook: &'a BodyProgressHook<'a>, bytes: u64, interval: Duration) -> Watch<'a> {
+        Watch {
+            hook,
+            peer: None,
+            bytes,
+            interval,
+        }
+    }
+
+    #[test]
+    fn report_every_so_many_bytes_and_the_end() {
+        let calls = Mutex::new(Vec::new());
+        let hook = |_: &RequestHeadInfo, received, total| {
+            calls.lock().unwrap().push((received, total));
+        };
+        let watch = watch(&hook, 100, Duration::from_secs(60));
+
+        let mut progress = watch.start("PUT", "/upload", 10, Some(250));
+        for received in &[50, 110, 150, 210, 250] {
+            progress.update(*received);
+        }
+
+        assert_eq!(
+            vec![(110, Some(250)), (210, Some(250)), (250, Some(250))],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn report_once_enough_time_has_passed() {
+        let calls = Mutex::new(Vec::new());
+        let hook = |info: &RequestHeadInfo, received, _| {
+            calls
+                .lock()
+                .unwrap()
+                .push((info.path().to_string(), received));
+        };
+        let watch = watch(&hook, u64::MAX, Duration::from_secs(0));
+
+        let mut progress = watch.start("POST", "/form", 0, Some(100));
+        progress.update(1);
+        progress.update(1);
+        progress.update(2);
+
+        assert_eq!(
+            vec![(String::from("/form"), 1), (String::from("/form"), 2)],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn stop_calling_a_hook_that_panicked() {
+        let calls = Mutex::new(0);
+        let hook = |_: &RequestHeadInfo, _, _| {
+            *calls.lock().unwrap() += 1;
+            panic!("progress hook failed");
+        };
+        let watch = watch(&hook, 1, Duration::from_secs(60));
+
+        let mut progress = watch.start("PUT", "/upload", 0, Some(3));
+        progress.update(1);
+        progress.update(2);
+        progress.update(3);
+
+        assert_eq!(1, *calls.lock().unwrap());
+    }
+}
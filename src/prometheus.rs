@@ -0,0 +1,244 @@
+use metrics::Metrics;
+
+/// The content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Renders a server's metrics in the Prometheus text exposition format.
+pub fn render(metrics: &Metrics) -> String {
+    let mut out = String::new();
+
+    family(
+        &mut out,
+        "simple_server_responses_total",
+        "counter",
+        "Responses sent, by the class of their status.",
+    );
+    for (class, &count) in CLASSES.iter().zip(&metrics.responses_by_class()) {
+        sample(
+            &mut out,
+            "simple_server_responses_total",
+            &[("class", class)],
+            count as u64,
+        );
+    }
+
+    let stats = metrics.pool_stats();
+    let unlabelled: &[(&str, &str, &str, u64)] = &[
+        (
+            "simple_server_received_bytes_total",
+            "counter",
+            "Bytes read from clients while reading requests.",
+            metrics.bytes_received(),
+        ),
+        (
+            "simple_server_sent_bytes_total",
+            "counter",
+            "Bytes of responses written to clients.",
+            metrics.bytes_sent(),
+        ),
+        (
+            "simple_server_active_connections",
+            "gauge",
+            "Connections being handled by a worker thread.",
+            stats.busy_workers as u64,
+        ),
+        (
+            "simple_server_peak_active_connections",
+            "gauge",
+            "The most connections that have been handled at once.",
+            stats.peak_busy_workers as u64,
+        ),
+        (
+            "simple_server_queued_connections",
+            "gauge",
+            "Accepted connections waiting for a worker thread.",
+            stats.queued_connections as u64,
+        ),
+        (
+            "simple_server_peak_queued_connections",
+            "gauge",
+            "The most connections that have waited for a worker thread at once.",
+            stats.peak_queued_connections as u64,
+        ),
+        (
+            "simple_server_overloaded_connections_total",
+            "counter",
+            "Connections turned away because every worker was busy and the queue was full.",
+            metrics.overloaded_connections() as u64,
+        ),
+        (
+            "simple_server_reaped_connections_total",
+            "counter",
+            "Connections closed for sitting idle too long.",
+            metrics.reaped_connections() as u64,
+        ),
+        (
+            "simple_server_rejected_connects_total",
+            "counter",
+            "CONNECT requests turned away.",
+            metrics.rejected_connects() as u64,
+        ),
+        (
+            "simple_server_exhausted_accepts_total",
+            "counter",
+            "Accepts that failed for lack of file descriptors.",
+            metrics.exhausted_accepts() as u64,
+        ),
+    ];
+    for &(name, kind, help, value) in unlabelled {
+        family(&mut out, name, kind, help);
+        sample(&mut out, name, &[], value);
+    }
+
+    out
+}
+
+// Writes the `# HELP` and `# TYPE` lines that come before a metric's samples.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str("# HELP ");
+    out.push_str(name);
+    out.push(' ');
+    escape(help, false, out);
+    out.push_str("\n# TYPE ");
+    out.push_str(name);
+    out.push(' ');
+    out.push_str(kind);
+    out.push('\n');
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: u64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        out.push('{');
+        for (i, &(label, value)) in labels.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(label);
+            out.push_str("=\"");
+            escape(value, true, out);
+            out.push('"');
+        }
+        out.push('}');
+    }
+    out.push(' ');
+    out.push_str(&value.to_string());
+    out.push('\n');
+}
+
+// Escapes backslashes and line feeds, and in label values, double quotes too,
+// as the text format asks.
+fn escape(text: &str, quotes: bool, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '"' if quotes => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod prometheus_should {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Checks that `text` is made up of nothing but well-formed `# HELP`,
+    // `# TYPE` and sample lines, with every sample's metric declared first,
+    // and returns the samples by their name and labels.
+    fn parse(text: &str) -> HashMap<String, u64> {
+        let mut types = HashMap::new();
+        let mut samples = HashMap::new();
+
+        assert!(text.ends_with('\n'));
+        for line in text.lines() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                let (name, _) = help.split_once(' ').expect(line);
+                assert!(!types.contains_key(name), "HELP after TYPE: {}", line);
+            } else if let Some(kind) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = kind.split_once(' ').expect(line);
+                assert!(kind == "counter" || kind == "gauge", "{}", line);
+                assert!(
+                    types.insert(name, kind).is_none(),
+                    "declared twice: {}",
+                    line
+                );
+            } else {
+                let (series, value) = line.rsplit_once(' ').expect(line);
+                let name = series.split('{').next().unwrap();
+                assert!(
+                    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                    "{}",
+                    line
+                );
+                assert!(types.contains_key(name), "undeclared: {}", line);
+                if types[name] == "counter" {
+                    assert!(name.ends_with("_total"), "{}", line);
+                }
+                let value = value.parse().expect(line);
+                assert!(
+                    samples.insert(series.to_string(), value).is_none(),
+                    "{}",
+                    line
+                );
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn render_every_metric_in_the_text_format() {
+        let rendered = render(&Metrics::default());
+        let samples = parse(&rendered);
+
+        assert_eq!(15, samples.len());
+        for class in &CLASSES {
+            let series = format!("simple_server_responses_total{{class=\"{}\"}}", class);
+            assert_eq!(Some(&0), samples.get(&series), "{}", series);
+        }
+        assert!(rendered.contains(
+            "# HELP simple_server_active_connections Connections being handled by a worker thread.\n\
+             # TYPE simple_server_active_connections gauge\n\
+             simple_server_active_connections 0\n"
+        ));
+    }
+
+    #[test]
+    fn render_what_the_metrics_counted() {
+        let metrics = Metrics::default();
+        let _busy = metrics.busy();
+        metrics.connection_reaped();
+
+        let samples = parse(&render(&metrics));
+        assert_eq!(1, samples["simple_server_active_connections"]);
+        assert_eq!(1, samples["simple_server_peak_active_connections"]);
+        assert_eq!(1, samples["simple_server_reaped_connections_total"]);
+    }
+
+    #[test]
+    fn escape_label_values_and_help() {
+        let mut out = String::new();
+        family(
+            &mut out,
+            "a_total",
+            "counter",
+            "Back\\slash \"quoted\"\nnext",
+        );
+        sample(
+            &mut out,
+            "a_total",
+            &[("path", "/a\"b\\c\nd"), ("x", "y")],
+            3,
+        );
+
+        assert_eq!(
+            "# HELP a_total Back\\\\slash \"quoted\"\\nnext\n\
+             # TYPE a_total counter\n\
+             a_total{path=\"/a\\\"b\\\\c\\nd\",x=\"y\"} 3\n",
+            out
+        );
+    }
+}
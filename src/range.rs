@@ -0,0 +1,401 @@
+use http::header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use std::cmp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// More ranges than this in one request are almost certainly someone trying
+// to make us do a lot of work for a small request, so the header is ignored
+// and the whole file is sent instead.
+const MAX_RANGES: usize = 16;
+
+/// An inclusive range of bytes within a body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The value of a `Content-Range` header for this range of a body that's
+    /// `total` bytes long.
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// What a `Range` header asks for, given the length of the body.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ranges {
+    /// The whole body should be sent, either because the header couldn't be
+    /// understood or because honouring it isn't worth it.
+    Full,
+    /// One or more ranges of the body, sorted and with overlaps merged.
+    Satisfiable(Vec<ByteRange>),
+    /// None of the requested ranges overlap the body.
+    Unsatisfiable,
+}
+
+/// Works out which parts of a body `len` bytes long a `Range` header value
+/// asks for.
+pub fn parse(header: &[u8], len: u64) -> Ranges {
+    let header = match ::std::str::from_utf8(header) {
+        Ok(header) => header.trim(),
+        Err(_) => return Ranges::Full,
+    };
+
+    let specs = match header.split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return Ranges::Full,
+    };
+
+    let mut ranges = Vec::new();
+    for (count, spec) in specs.split(',').enumerate() {
+        if count == MAX_RANGES {
+            return Ranges::Full;
+        }
+
+        match parse_spec(spec.trim(), len) {
+            Some(Some(range)) => ranges.push(range),
+            Some(None) => {}
+            None => return Ranges::Full,
+        }
+    }
+
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+
+    Ranges::Satisfiable(coalesce(ranges))
+}
+
+// Returns `None` for a malformed spec, and `Some(None)` for a well-formed one
+// that lies entirely outside the body.
+fn parse_spec(spec: &str, len: u64) -> Option<Option<ByteRange>> {
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // A suffix range: the last `n` bytes.
+        let n = parse_digits(last)?;
+        if n == 0 || len == 0 {
+            return Some(None);
+        }
+        return Some(Some(ByteRange {
+            start: len - cmp::min(n, len),
+            end: len - 1,
+        }));
+    }
+
+    let start = parse_digits(first)?;
+    let end = if last.is_empty() {
+        None
+    } else {
+        Some(parse_digits(last)?)
+    };
+
+    if let Some(end) = end {
+        if end < start {
+            return None;
+        }
+    }
+
+    if start >= len {
+        return Some(None);
+    }
+
+    Some(Some(ByteRange {
+        start,
+        end: cmp::min(end.unwrap_or(len - 1), len - 1),
+    }))
+}
+
+fn parse_digits(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// Sorts the ranges and merges any that overlap or touch, so that nobody can
+// ask for the same bytes over and over again.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = cmp::max(last.end, range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Makes up a boundary for a `multipart/byteranges` body.
+pub fn boundary() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("simple-server-{:08x}{:08x}", nanos, count)
+}
+
+/// The delimiter and headers that come before a range's bytes in a
+/// `multipart/byteranges` body.
+pub fn part_head(boundary: &str, content_type: &[u8], range: &ByteRange, total: u64) -> Vec<u8> {
+    let mut head = format!("--{}\r\ncontent-type: ", boundary).into_bytes();
+    head.extend_from_slice(content_type);
+    head.extend_from_slice(
+        format!("\r\ncontent-range: {}\r\n\r\n", range.content_range(total)).as_bytes(),
+    );
+    head
+}
+
+/// What follows a range's bytes in a `multipart/byteranges` body: a line
+/// break, plus the closing delimiter after the last part.
+pub fn part_tail(boundary: &str, last: bool) -> Vec<u8> {
+    if last {
+        format!("\r\n--{}--\r\n", boundary).into_bytes()
+    } else {
+        b"\r\n".to_vec()
+    }
+}
+
+/// Cuts the body of a `200` response down to the range a `Range` header asks
+/// for, the way static files are, and says that ranges are accepted.
+///
+/// Responses with any other status, or that already have a `Content-Range`,
+/// are left alone. So are requests for several ranges, which get the whole
+/// body.
+pub fn slice<B: Cut>(response: Response<B>, range: Option<&HeaderValue>) -> Response<B> {
+    let (mut parts, body) = response.into_parts();
+    if parts.status != StatusCode::OK || parts.headers.contains_key(CONTENT_RANGE) {
+        return Response::from_parts(parts, body);
+    }
+
+    if !parts.headers.contains_key(ACCEPT_RANGES) {
+        parts
+            .headers
+            .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    }
+
+    let len = body.as_ref().len() as u64;
+    let ranges = match range {
+        Some(range) => parse(range.as_bytes(), len),
+        None => Ranges::Full,
+    };
+
+    match ranges {
+        Ranges::Satisfiable(ref ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts
+                .headers
+                .insert(CONTENT_RANGE, header_value(range.content_range(len)));
+            parts.headers.remove(CONTENT_LENGTH);
+
+            let body = body.cut(range.start as usize, range.end as usize + 1);
+            Response::from_parts(parts, body)
+        }
+        Ranges::Unsatisfiable => {
+            parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+            parts
+                .headers
+                .insert(CONTENT_RANGE, header_value(format!("bytes */{}", len)));
+            parts.headers.remove(CONTENT_LENGTH);
+            // The body is now the error page, not what the handler sent.
+            parts
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+
+            let body = B::from(b"<h1>416</h1><p>Range not satisfiable!<p>");
+            Response::from_parts(parts, body)
+        }
+        Ranges::Full | Ranges::Satisfiable(_) => Response::from_parts(parts, body),
+    }
+}
+
+/// A body that a range can be cut out of.
+pub trait Cut: AsRef<[u8]> + From<&'static [u8]> {
+    /// Keeps only the bytes from `start` up to, but not including, `end`.
+    fn cut(self, start: usize, end: usize) -> Self;
+}
+
+impl Cut for Vec<u8> {
+    fn cut(mut self, start: usize, end: usize) -> Vec<u8> {
+        self.truncate(end);
+        self.drain(..start);
+        self
+    }
+}
+
+impl Cut for &'static [u8] {
+    fn cut(self, start: usize, end: usize) -> &'static [u8] {
+        &self[start..end]
+    }
+}
+
+fn header_value(value: String) -> HeaderValue {
+    HeaderValue::from_str(&value).expect("a Content-Range is always a valid header value")
+}
+
+#[cfg(test)]
+mod range_should {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn parse_a_single_range() {
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(0, 99)]),
+            parse(b"bytes=0-99", 1000)
+        );
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(900, 999)]),
+            parse(b"bytes=900-", 1000)
+        );
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(990, 999)]),
+            parse(b"bytes=-10", 1000)
+        );
+    }
+
+    #[test]
+    fn clamp_ranges_to_the_body() {
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(500, 999)]),
+            parse(b"bytes=500-5000", 1000)
+        );
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(0, 999)]),
+            parse(b"bytes=-5000", 1000)
+        );
+    }
+
+    #[test]
+    fn parse_multiple_ranges() {
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(0, 99), range(200, 299)]),
+            parse(b"bytes=0-99, 200-299", 1000)
+        );
+    }
+
+    #[test]
+    fn merge_overlapping_and_adjacent_ranges() {
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(0, 199)]),
+            parse(b"bytes=100-199,0-99", 1000)
+        );
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(0, 999)]),
+            parse(b"bytes=0-,0-,0-", 1000)
+        );
+    }
+
+    #[test]
+    fn drop_ranges_outside_the_body() {
+        assert_eq!(
+            Ranges::Satisfiable(vec![range(0, 9)]),
+            parse(b"bytes=0-9,5000-6000", 1000)
+        );
+        assert_eq!(Ranges::Unsatisfiable, parse(b"bytes=1000-", 1000));
+        assert_eq!(Ranges::Unsatisfiable, parse(b"bytes=-0", 1000));
+        assert_eq!(Ranges::Unsatisfiable, parse(b"bytes=0-", 0));
+    }
+
+    #[test]
+    fn ignore_malformed_headers() {
+        for header in &[
+            &b"bytes=abc"[..],
+            b"bytes=5-1",
+            b"bytes=",
+            b"bytes=1-2,x",
+            b"items=0-1",
+            b"bytes=+1-2",
+            b"bytes=0-\xff",
+        ] {
+            assert_eq!(Ranges::Full, parse(header, 1000));
+        }
+    }
+
+    #[test]
+    fn ignore_too_many_ranges() {
+        let specs: Vec<String> = (0..MAX_RANGES + 1)
+            .map(|i| format!("{}-{}", i * 10, i * 10))
+            .collect();
+        let header = format!("bytes={}", specs.join(","));
+
+        assert_eq!(Ranges::Full, parse(header.as_bytes(), 1000));
+    }
+
+    fn sliced(range: Option<&str>) -> Response<Vec<u8>> {
+        let response = Response::builder()
+            .header("content-type", "audio/ogg")
+            .body(b"0123456789".to_vec())
+            .unwrap();
+        let range = range.map(|range| HeaderValue::from_str(range).unwrap());
+
+        slice(response, range.as_ref())
+    }
+
+    #[test]
+    fn slice_a_single_range_out_of_a_body() {
+        let response = sliced(Some("bytes=2-5"));
+
+        assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+        assert_eq!("bytes 2-5/10", response.headers()[CONTENT_RANGE]);
+        assert_eq!("bytes", response.headers()[ACCEPT_RANGES]);
+        assert_eq!(&b"2345"[..], &response.body()[..]);
+    }
+
+    #[test]
+    fn send_the_whole_body_without_a_single_range() {
+        for range in &[None, Some("bytes=0-1,5-6"), Some("bytes=abc")] {
+            let response = sliced(*range);
+
+            assert_eq!(StatusCode::OK, response.status());
+            assert_eq!("bytes", response.headers()[ACCEPT_RANGES]);
+            assert_eq!(&b"0123456789"[..], &response.body()[..]);
+        }
+    }
+
+    #[test]
+    fn refuse_ranges_outside_the_body() {
+        let response = sliced(Some("bytes=20-"));
+
+        assert_eq!(StatusCode::RANGE_NOT_SATISFIABLE, response.status());
+        assert_eq!("bytes */10", response.headers()[CONTENT_RANGE]);
+        assert_eq!("text/html", response.headers()[CONTENT_TYPE]);
+    }
+
+    #[test]
+    fn leave_other_responses_alone() {
+        let partial = Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(CONTENT_RANGE, "bytes 0-3/100")
+            .body(b"0123".to_vec())
+            .unwrap();
+        let range = HeaderValue::from_static("bytes=1-2");
+
+        let response = slice(partial, Some(&range));
+
+        assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+        assert_eq!("bytes 0-3/100", response.headers()[CONTENT_RANGE]);
+        assert!(!response.headers().contains_key(ACCEPT_RANGES));
+        assert_eq!(&b"0123"[..], &response.body()[..]);
+    }
+}
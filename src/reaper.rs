@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Keeps track of when each open connection last received anything, so that
+/// the ones left idle for too long can be closed.
+///
+/// Connections are never closed from here, as the worker that owns one may
+/// be in the middle of reading from it. Instead, they're flagged, and the
+/// worker gives up on the connection the next time it goes to read.
+pub struct Registry {
+    epoch: Instant,
+    next_id: AtomicUsize,
+    connections: Mutex<HashMap<usize, Arc<Activity>>>,
+}
+
+struct Activity {
+    last_active_ms: AtomicU64,
+    reap: AtomicBool,
+}
+
+/// A connection's entry in the registry, which is removed when it's dropped.
+pub struct Registration {
+    registry: Arc<Registry>,
+    id: usize,
+    activity: Arc<Activity>,
+}
+
+impl Registry {
+    pub fn new() -> Arc<Registry> {
+        Arc::new(Registry {
+            epoch: Instant::now(),
+            next_id: AtomicUsize::new(0),
+            connections: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn register(registry: &Arc<Registry>) -> Registration {
+        let id = registry.next_id.fetch_add(1, Ordering::Relaxed);
+        let activity = Arc::new(Activity {
+            last_active_ms: AtomicU64::new(registry.now_ms()),
+            reap: AtomicBool::new(false),
+        });
+
+        registry.lock().insert(id, activity.clone());
+
+        Registration {
+            registry: registry.clone(),
+            id,
+            activity,
+        }
+    }
+
+    /// Flags every connection that's been idle for longer than
+    /// `idle_timeout` to be closed, returning how many were newly flagged.
+    pub fn sweep(&self, idle_timeout: Duration) -> usize {
+        let now = self.now_ms();
+        let idle_timeout = idle_timeout.as_millis() as u64;
+
+        self.lock()
+            .values()
+            .filter(|activity| {
+                now.saturating_sub(activity.last_active_ms.load(Ordering::Relaxed)) > idle_timeout
+            })
+            .filter(|activity| !activity.reap.swap(true, Ordering::Relaxed))
+            .count()
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+
+    // Nothing can panic while the lock is held, but a poisoned map is still
+    // perfectly usable.
+    fn lock(&self) -> MutexGuard<'_, HashMap<usize, Arc<Activity>>> {
+        match self.connections.lock() {
+            Ok(connections) => connections,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Registration {
+    /// Whether the reaper has asked for this connection to be closed.
+    pub fn reap_requested(&self) -> bool {
+        self.activity.reap.load(Ordering::Relaxed)
+    }
+
+    fn touch(&self) {
+        self.activity
+            .last_active_ms
+            .store(self.registry.now_ms(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
+}
+
+/// Wraps a connection's stream, recording activity whenever data arrives, and
+/// reporting the end of the stream once the connection has been reaped.
+pub struct Watched<'a, S: 'a> {
+    stream: &'a mut S,
+    registration: &'a Registration,
+}
+
+impl<'a, S: Read> Watched<'a, S> {
+    pub fn new(stream: &'a mut S, registration: &'a Registration) -> Watched<'a, S> {
+        Watched {
+            stream,
+            registration,
+        }
+    }
+}
+
+impl<'a, S: Read> Read for Watched<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.registration.reap_requested() {
+            return Ok(0);
+        }
+
+        let read = self.stream.read(buf)?;
+        if read > 0 {
+            self.registration.touch();
+        }
+        Ok(read)
+    }
+}
+
+// Writes are passed straight through. Only what the client sends counts as
+// activity.
+impl<'a, S: Write> Write for Watched<'a, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod reaper_should {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn flag_only_idle_connections() {
+        let registry = Registry::new();
+        let idle = Registry::register(&registry);
+        thread::sleep(Duration::from_millis(30));
+        let fresh = Registry::register(&registry);
+
+        assert_eq!(1, registry.sweep(Duration::from_millis(20)));
+        assert!(idle.reap_requested());
+        assert!(!fresh.reap_requested());
+
+        // Connections are only counted the first time they're flagged.
+        assert_eq!(0, registry.sweep(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn count_reads_as_activity() {
+        let registry = Registry::new();
+        let registration = Registry::register(&registry);
+        thread::sleep(Duration::from_millis(30));
+
+        let mut stream = &b"GET"[..];
+        let mut buf = [0; 8];
+        assert_eq!(
+            3,
+            Watched::new(&mut stream, &registration)
+                .read(&mut buf)
+                .unwrap()
+        );
+
+        assert_eq!(0, registry.sweep(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn end_the_stream_once_reaped() {
+        let registry = Registry::new();
+        let registration = Registry::register(&registry);
+        thread::sleep(Duration::from_millis(30));
+        registry.sweep(Duration::from_millis(20));
+
+        let mut stream = &b"GET"[..];
+        let mut buf = [0; 8];
+        assert_eq!(
+            0,
+            Watched::new(&mut stream, &registration)
+                .read(&mut buf)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn forget_dropped_connections() {
+        let registry = Registry::new();
+        drop(Registry::register(&registry));
+
+        assert!(registry.lock().is_empty());
+    }
+}
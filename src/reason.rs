@@ -0,0 +1,83 @@
+use error::Error;
+
+/// A reason phrase to send in the status line instead of the standard one.
+///
+/// The `http` crate has no notion of reason phrases, so a response that
+/// wants its own carries one of these in its extensions. Without one, the
+/// status's standard phrase is sent, or an empty one if it has none.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{ReasonPhrase, Server};
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         response.status(422);
+///         response.extension(ReasonPhrase::new("Validation Failed")?);
+///
+///         Ok(response.body("The name is missing.".as_bytes().to_vec())?)
+///     });
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReasonPhrase(String);
+
+impl ReasonPhrase {
+    /// Makes a reason phrase out of `phrase`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidReasonPhrase` if the phrase has a control
+    /// character other than a tab in it, such as a CR or LF, which would
+    /// break the status line.
+    pub fn new<S: Into<String>>(phrase: S) -> Result<ReasonPhrase, Error> {
+        let phrase = phrase.into();
+
+        if phrase
+            .bytes()
+            .any(|b| (b < b' ' && b != b'\t') || b == 0x7f)
+        {
+            return Err(Error::InvalidReasonPhrase);
+        }
+        Ok(ReasonPhrase(phrase))
+    }
+
+    /// The phrase, as it'll be sent.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod reason_should {
+    use super::*;
+
+    #[test]
+    fn accept_printable_phrases() {
+        for phrase in &["Validation Failed", "", "Caf\u{e9}\tOuvert"] {
+            assert_eq!(*phrase, ReasonPhrase::new(*phrase).unwrap().as_str());
+        }
+    }
+
+    #[test]
+    fn reject_control_characters() {
+        for phrase in &[
+            "Bad\r\nSet-Cookie: x=y",
+            "Bad\n",
+            "Bad\r",
+            "Bad\0",
+            "Bad\x7f",
+        ] {
+            match ReasonPhrase::new(*phrase) {
+                Err(Error::InvalidReasonPhrase) => {}
+                other => panic!(
+                    "Expected InvalidReasonPhrase for {:?}. Got {:?}",
+                    phrase, other
+                ),
+            }
+        }
+    }
+}
@@ -1,9 +1,284 @@
 use super::Request;
-use error::Error;
-use std::io::{self, Read};
+use error::{Error, FramingError};
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::uri::{Authority, Scheme};
+use http::{Uri, Version};
+use std::cmp;
+use std::io::{self, Read, Write};
 use std::time::{Duration, Instant};
 
 use parsing;
+use pool;
+use progress::Watch;
+
+/// What happens to the connection once a response has been written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connection {
+    /// The connection stays open for further requests.
+    KeepAlive,
+    /// The connection is closed after the response.
+    Close,
+}
+
+impl Connection {
+    /// The value of the `connection` header announcing this decision.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Connection::KeepAlive => "keep-alive",
+            Connection::Close => "close",
+        }
+    }
+}
+
+/// Works out whether the client wants its connection kept open, based on the
+/// HTTP version and the `Connection` header of its request.
+///
+/// A `close` token always wins. Otherwise HTTP/1.1 connections persist by
+/// default, while HTTP/1.0 ones only do if the client sent `keep-alive`.
+pub fn connection(version: Version, headers: &HeaderMap<HeaderValue>) -> Connection {
+    if has_token(headers, header::CONNECTION, "close") {
+        return Connection::Close;
+    }
+
+    let persistent = if version == Version::HTTP_09 {
+        false
+    } else if version == Version::HTTP_10 {
+        has_token(headers, header::CONNECTION, "keep-alive")
+    } else {
+        true
+    };
+
+    if persistent {
+        Connection::KeepAlive
+    } else {
+        Connection::Close
+    }
+}
+
+/// Checks whether any value of the named header contains `token` in its
+/// comma-separated list, ignoring case.
+pub fn has_token(headers: &HeaderMap<HeaderValue>, name: HeaderName, token: &str) -> bool {
+    headers.get_all(name).iter().any(|value| {
+        value
+            .as_bytes()
+            .split(|&b| b == b',')
+            .any(|t| t.trim_ascii().eq_ignore_ascii_case(token.as_bytes()))
+    })
+}
+
+// Reads at least one byte from the stream, waiting for as long as the
+// timeout allows.
+fn read_some<S: Read>(
+    stream: &mut S,
+    buf: &mut [u8],
+    start_time: &Instant,
+    timeout: Option<Duration>,
+) -> Result<usize, Error> {
+    loop {
+        match stream.read(buf) {
+            Ok(0) => return Err(Error::ConnectionClosed),
+            Ok(n) => return Ok(n),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::WouldBlock && e.kind() != io::ErrorKind::TimedOut {
+                    return Err(e.into());
+                }
+
+                if timeout.is_some()
+                    && elapsed_milliseconds(start_time)
+                        > duration_to_milliseconds(&timeout.unwrap())
+                {
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+    }
+}
+
+// Works out how long the request's body is from its headers, which is the
+// only place that decision is made. Requests whose headers don't agree on
+// where the body ends are refused. A `Transfer-Encoding` has to end in
+// `chunked`, and then the body runs until the last chunk instead.
+//
+// A `Content-Length` has to be nothing but digits, and repeating it is only
+// allowed if every copy says the same.
+fn content_length(request: &parsing::Request) -> Result<Option<u64>, FramingError> {
+    let mut content_length = None;
+    let mut last_coding = None;
+
+    for header in request.headers() {
+        if header.name.eq_ignore_ascii_case("content-length") {
+            for value in header.value.split(|&b| b == b',') {
+                let value = parse_content_length(value.trim_ascii())
+                    .ok_or(FramingError::InvalidContentLength)?;
+                if content_length.is_some_and(|length| length != value) {
+                    return Err(FramingError::ConflictingContentLengths);
+                }
+                content_length = Some(value);
+            }
+        } else if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            last_coding = header
+                .value
+                .split(|&b| b == b',')
+                .map(|coding| coding.trim_ascii())
+                .rfind(|coding| !coding.is_empty())
+                .or(last_coding);
+        }
+    }
+
+    if let Some(coding) = last_coding {
+        if content_length.is_some() {
+            return Err(FramingError::ContentLengthWithTransferEncoding);
+        }
+        if !coding.eq_ignore_ascii_case(b"chunked") {
+            return Err(FramingError::UnsupportedTransferEncoding);
+        }
+    }
+
+    Ok(content_length)
+}
+
+enum Expectation {
+    None,
+    Continue,
+    Unsupported,
+}
+
+// Works out what the request's `Expect` headers ask for. Every expectation,
+// across all of them, has to be `100-continue` for it to be met.
+fn expectation(request: &parsing::Request) -> Expectation {
+    let mut expectation = Expectation::None;
+
+    for header in request.headers() {
+        if !header.name.eq_ignore_ascii_case("expect") {
+            continue;
+        }
+        for value in header.value.split(|&b| b == b',') {
+            match value.trim_ascii() {
+                b"" => {}
+                value if value.eq_ignore_ascii_case(b"100-continue") => {
+                    expectation = Expectation::Continue;
+                }
+                _ => return Expectation::Unsupported,
+            }
+        }
+    }
+
+    expectation
+}
+
+fn has_transfer_encoding(request: &parsing::Request) -> bool {
+    request
+        .headers()
+        .any(|header| header.name.eq_ignore_ascii_case("transfer-encoding"))
+}
+
+// Decodes a chunked body as it arrives, a chunk at a time. Line breaks have
+// to be `\r\n` throughout, as servers and proxies that accept other ones
+// differently can be made to disagree about where the body ends.
+#[derive(Default)]
+struct Chunked {
+    body: Vec<u8>,
+    // Where the next chunk starts in what's been read of the body so far.
+    next: usize,
+}
+
+impl Chunked {
+    // Decodes whatever whole chunks have arrived in `raw`, which is the body
+    // as it was sent. Once the last chunk and the trailers after it are in,
+    // returns how much of `raw` they all took up.
+    fn decode(&mut self, raw: &[u8]) -> Result<Option<usize>, FramingError> {
+        loop {
+            let line_end = match find_crlf(raw, self.next) {
+                Some(line_end) => line_end,
+                None => return Ok(None),
+            };
+            let size =
+                parse_chunk_size(&raw[self.next..line_end]).ok_or(FramingError::InvalidChunk)?;
+            let data = line_end + 2;
+
+            // The trailers are thrown away, so all that matters is where the
+            // empty line after them is.
+            if size == 0 {
+                let mut line = data;
+                loop {
+                    match find_crlf(raw, line) {
+                        Some(end) if end == line => return Ok(Some(end + 2)),
+                        Some(end) => line = end + 2,
+                        None => return Ok(None),
+                    }
+                }
+            }
+
+            // The chunk's data, and the line break after it.
+            if ((raw.len() - data) as u64) < size.saturating_add(2) {
+                return Ok(None);
+            }
+            let data_end = data + size as usize;
+            if &raw[data_end..data_end + 2] != b"\r\n" {
+                return Err(FramingError::InvalidChunk);
+            }
+
+            self.body.extend_from_slice(&raw[data..data_end]);
+            self.next = data_end + 2;
+        }
+    }
+}
+
+fn find_crlf(bytes: &[u8], from: usize) -> Option<usize> {
+    bytes[from..]
+        .windows(2)
+        .position(|pair| pair == b"\r\n")
+        .map(|i| from + i)
+}
+
+// Parses the line that starts a chunk, which is its size in hex, possibly
+// followed by extensions, which are ignored.
+fn parse_chunk_size(line: &[u8]) -> Option<u64> {
+    let size = match line.iter().position(|&b| b == b';') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    if size.is_empty() || size.len() > 16 || !size.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    u64::from_str_radix(::std::str::from_utf8(size).ok()?, 16).ok()
+}
+
+// Parses a `Content-Length` value strictly: no signs, no spaces, and nothing
+// too big for a `u64`.
+fn parse_content_length(value: &[u8]) -> Option<u64> {
+    if value.is_empty() {
+        return None;
+    }
+
+    value.iter().try_fold(0u64, |length, &b| {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        length.checked_mul(10)?.checked_add(u64::from(b - b'0'))
+    })
+}
+
+// Checks for the blank line that ends a request's head, which httparse
+// accepts with or without carriage returns.
+fn ends_head(buffer: &[u8]) -> bool {
+    buffer
+        .windows(2)
+        .enumerate()
+        .any(|(i, pair)| pair == b"\n\n" || (pair == b"\n\r" && buffer.get(i + 2) == Some(&b'\n')))
+}
+
+// How long the request line at the start of `buffer` is, or has got so far,
+// given where its line feed is if it's arrived. The line break itself isn't
+// counted.
+fn request_line_len(buffer: &[u8], line_feed: Option<usize>) -> usize {
+    let line = match line_feed {
+        Some(end) => &buffer[..end],
+        None => buffer,
+    };
+    line.strip_suffix(b"\r").unwrap_or(line).len()
+}
 
 fn elapsed_milliseconds(from: &Instant) -> u64 {
     let elapsed = Instant::now() - *from;
@@ -14,42 +289,213 @@ fn duration_to_milliseconds(from: &Duration) -> u64 {
     (from.as_secs() * 1000) + (from.subsec_nanos() as u64 / 1_000_000)
 }
 
-pub fn read<S: Read>(stream: &mut S, timeout: Option<Duration>) -> Result<Request<Vec<u8>>, Error> {
+/// A callback that's shown what a client sent when its request head couldn't
+/// be read.
+pub type ParseFailureHook<'a> = dyn Fn(&[u8], &Error) + Send + Sync + 'a;
+
+/// The longest request line that's accepted unless the server is set up
+/// otherwise.
+pub const DEFAULT_MAX_REQUEST_LINE: usize = 8 * 1024;
+
+/// The most a request can take up, head and body together, unless the server
+/// is set up otherwise.
+pub const DEFAULT_MAX_REQUEST_SIZE: u64 = 16 * 1024 * 1024;
+
+// At most this much of what a client sent is handed to a `ParseFailureHook`,
+// so that a client sending a huge head can't make the hook expensive too.
+const MAX_OBSERVED_BYTES: usize = 4096;
+
+/// Reads a request from `stream`.
+///
+/// `buffered` holds whatever was read from the stream past the end of the
+/// previous request on the connection, which is where this one starts. Any
+/// bytes read past the end of this request are left in it in turn, so that
+/// pipelined requests aren't lost.
+///
+/// If the client expects a `100 Continue` before sending the body, it's
+/// written to `stream` just before the body is read.
+///
+/// A request line longer than `max_request_line` bytes, not counting the
+/// line break, is refused with `Error::RequestLineTooLong` as soon as that
+/// much of it has arrived.
+///
+/// A request whose head and body together come to more than
+/// `max_request_size` bytes is refused with `Error::RequestTooLarge`, once
+/// that much of its head has arrived, or as soon as its `Content-Length` says
+/// so, before any of the body is read.
+///
+/// If the head can't be parsed, or the client takes too long sending it or
+/// sends too long a request line or head, `on_failure` is called with the
+/// start of what was buffered before the error is returned.
+///
+/// While a body framed by a `Content-Length` arrives, `progress` is kept up
+/// to date with how much of it has, if it's given.
+pub fn read<S: Read + Write>(
+    stream: &mut S,
+    buffered: &mut Vec<u8>,
+    timeout: Option<Duration>,
+    max_request_line: usize,
+    max_request_size: u64,
+    on_failure: Option<&ParseFailureHook<'_>>,
+    progress: Option<&Watch<'_>>,
+) -> Result<Request<Vec<u8>>, Error> {
     use std::mem;
 
+    let observe = |buffer: &[u8], error: &Error| {
+        if let Some(hook) = on_failure {
+            hook(&buffer[..cmp::min(buffer.len(), MAX_OBSERVED_BYTES)], error);
+        }
+    };
+
     let start_time = Instant::now();
-    let mut buffer = Vec::with_capacity(512);
+    let mut buffer = pool::take();
     let mut read_buf = [0_u8; 512];
+    let mut scanned = 0;
+    let mut seen_request_line = false;
 
-    let request = loop {
-        match stream.read(&mut read_buf) {
-            Ok(0) => return Err(Error::ConnectionClosed),
-            Ok(n) => {
-                buffer.extend_from_slice(&read_buf[..n]);
-                match parsing::try_parse_request(mem::replace(&mut buffer, vec![]))? {
-                    parsing::ParseResult::Complete(r) => break r,
-                    parsing::ParseResult::Partial(b) => {
-                        mem::replace(&mut buffer, b);
-                        continue;
+    buffer.append(buffered);
+
+    let mut request = loop {
+        // A pipelined request may already be buffered in full, so the stream
+        // is only read once there's nothing new left to look at.
+        if scanned == buffer.len() {
+            let n = match read_some(stream, &mut read_buf, &start_time, timeout) {
+                Ok(n) => n,
+                Err(e) => {
+                    if let Error::Timeout = e {
+                        observe(&buffer, &e);
                     }
+                    return Err(e);
                 }
-            }
+            };
+            buffer.extend_from_slice(&read_buf[..n]);
+        }
+
+        // Parsing the whole buffer after every read would be quadratic for a
+        // client sending its head a byte at a time. Instead, it's only parsed
+        // once the request line is complete, so that a bad one is rejected
+        // early, and once the head is.
+        let new_line = buffer[scanned..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|i| scanned + i);
+
+        // The request line comes first, so its length can be checked before
+        // much of anything is buffered.
+        if !seen_request_line && request_line_len(&buffer, new_line) > max_request_line {
+            let e = Error::RequestLineTooLong;
+            observe(&buffer, &e);
+            return Err(e);
+        }
+
+        let head_ended = ends_head(&buffer[scanned.saturating_sub(2)..]);
+        scanned = buffer.len();
+
+        // Nor is a head that never ends left to grow without bound.
+        if !head_ended && buffer.len() as u64 > max_request_size {
+            let e = Error::RequestTooLarge;
+            observe(&buffer, &e);
+            return Err(e);
+        }
+
+        if !head_ended && (seen_request_line || new_line.is_none()) {
+            continue;
+        }
+        seen_request_line = true;
+
+        // The parser takes the buffer, so when someone's watching for
+        // failures, what they'd be shown is copied out first.
+        let observed =
+            on_failure.map(|_| buffer[..cmp::min(buffer.len(), MAX_OBSERVED_BYTES)].to_vec());
+
+        match parsing::try_parse_request(mem::take(&mut buffer)) {
+            Ok(parsing::ParseResult::Complete(r)) => break r,
+            Ok(parsing::ParseResult::Partial(b)) => buffer = b,
             Err(e) => {
-                if e.kind() != io::ErrorKind::WouldBlock && e.kind() != io::ErrorKind::TimedOut {
-                    return Err(e.into());
+                if let Some(observed) = observed {
+                    observe(&observed, &e);
                 }
+                return Err(e);
+            }
+        }
+    };
 
-                if timeout.is_some()
-                    && elapsed_milliseconds(&start_time)
-                        > duration_to_milliseconds(&timeout.unwrap())
-                {
-                    return Err(Error::Timeout);
-                }
+    // The stream is only read again if some of the body is still to come;
+    // for a request without one, it isn't touched at all. Anything that
+    // arrived after the body belongs to the next request.
+    let content_length = content_length(&request).map_err(Error::Framing)?;
+
+    // A body that's too big is refused on the client's word, rather than
+    // read, or even asked for. That also bounds how much of one is read
+    // below.
+    if content_length
+        .is_some_and(|length| length > max_request_size.saturating_sub(request.head_len() as u64))
+    {
+        return Err(Error::RequestTooLarge);
+    }
+
+    // The only expectation there is is `100-continue`, and it's met by
+    // asking for the body just before reading it. A client expecting
+    // anything else gets nowhere, and its body is left unread.
+    let expects_continue = match expectation(&request) {
+        Expectation::None => false,
+        Expectation::Continue => request.version() > 0,
+        Expectation::Unsupported => return Err(Error::ExpectationFailed),
+    };
 
-                continue;
+    match content_length {
+        Some(expected) => {
+            if expects_continue && (request.body_len() as u64) < expected {
+                stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+                stream.flush()?;
             }
+            let mut progress = progress
+                .filter(|_| (request.body_len() as u64) < expected)
+                .map(|watch| {
+                    watch.start(
+                        request.method(),
+                        request.path(),
+                        request.body_len() as u64,
+                        Some(expected),
+                    )
+                });
+            while (request.body_len() as u64) < expected {
+                let remaining = expected - request.body_len() as u64;
+                let wanted = cmp::min(read_buf.len() as u64, remaining) as usize;
+                let n = read_some(stream, &mut read_buf[..wanted], &start_time, timeout)?;
+                request.extend_body(&read_buf[..n]);
+                if let Some(progress) = progress.as_mut() {
+                    progress.update(request.body_len() as u64);
+                }
+            }
+            request.truncate_body(expected as usize, buffered);
         }
-    };
+        None if has_transfer_encoding(&request) => {
+            if expects_continue && request.body_len() == 0 {
+                stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+                stream.flush()?;
+            }
+
+            // Without a length to check up front, it's what's been sent,
+            // framing and all, that's held to the limit.
+            let max_len = max_request_size.saturating_sub(request.head_len() as u64);
+            let mut raw = request.split_body();
+            let mut chunked = Chunked::default();
+            let end = loop {
+                if let Some(end) = chunked.decode(&raw).map_err(Error::Framing)? {
+                    break end;
+                }
+                if raw.len() as u64 > max_len {
+                    return Err(Error::RequestTooLarge);
+                }
+                let n = read_some(stream, &mut read_buf, &start_time, timeout)?;
+                raw.extend_from_slice(&read_buf[..n]);
+            };
+            buffered.extend_from_slice(&raw[end..]);
+            request.extend_body(&chunked.body);
+        }
+        None => request.truncate_body(0, buffered),
+    }
 
     build_request(request)
 }
@@ -58,26 +504,138 @@ fn build_request(mut req: parsing::Request) -> Result<Request<Vec<u8>>, Error> {
     let mut http_req = Request::builder();
 
     http_req.method(req.method());
+    http_req.version(match req.version() {
+        0 => Version::HTTP_10,
+        _ => Version::HTTP_11,
+    });
+
+    let mut request = http_req.body(req.split_body())?;
+    *request.uri_mut() = request_target(req.path())?.parse()?;
 
+    // Values are kept as the exact bytes the client sent, which may not be
+    // UTF-8, like a latin-1 `User-Agent`. Anything the parser let through
+    // that `http` won't take means a bad request, rather than a dropped
+    // header.
     for header in req.headers() {
-        http_req.header(header.name, header.value);
+        let name =
+            HeaderName::from_bytes(header.name.as_bytes()).map_err(|_| Error::InvalidHeader)?;
+        let value = HeaderValue::from_bytes(header.value).map_err(|_| Error::InvalidHeader)?;
+        request.headers_mut().append(name, value);
     }
 
-    let mut request = http_req.body(req.split_body())?;
-    let path = req.path();
-    *request.uri_mut() = path.parse()?;
+    if let Some(uri) = with_host(request.uri(), request.headers()) {
+        *request.uri_mut() = uri;
+    }
 
+    pool::give(req.into_buffer());
     Ok(request)
 }
 
+// Makes a path-only target into a full URI, with the scheme and the
+// authority from the `Host` header, so that handlers can build absolute URLs
+// without piecing them together themselves. The path and query are left
+// exactly as they were. A request that's missing its `Host`, has more than
+// one, or has one that isn't a plain host and port, keeps the path-only
+// target it came with.
+fn with_host(uri: &Uri, headers: &HeaderMap) -> Option<Uri> {
+    if uri.authority_part().is_some() || !uri.path().starts_with('/') {
+        return None;
+    }
+
+    let mut hosts = headers.get_all(header::HOST).iter();
+    let host = match (hosts.next(), hosts.next()) {
+        (Some(host), None) => host.to_str().ok()?,
+        _ => return None,
+    };
+    if host.contains('@') {
+        return None;
+    }
+
+    let mut parts = uri.clone().into_parts();
+    parts.scheme = Some(Scheme::HTTP);
+    parts.authority = Some(host.parse::<Authority>().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+// Checks that the request target only uses characters allowed in a URI, and
+// that every `%` starts an escape. Clients aren't supposed to send fragments,
+// but if one does, it's dropped, as it would be for any other URI.
+fn request_target(path: &str) -> Result<&str, Error> {
+    let target = match path.find('#') {
+        Some(fragment) => &path[..fragment],
+        None => path,
+    };
+    let bytes = target.as_bytes();
+
+    if bytes.is_empty() {
+        return Err(Error::InvalidTarget);
+    }
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        let allowed = match byte {
+            b'%' => {
+                bytes.len() > i + 2
+                    && bytes[i + 1].is_ascii_hexdigit()
+                    && bytes[i + 2].is_ascii_hexdigit()
+            }
+            b'-' | b'.' | b'_' | b'~' => true,
+            b':' | b'/' | b'?' | b'[' | b']' | b'@' => true,
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' => true,
+            _ => byte.is_ascii_alphanumeric(),
+        };
+
+        if !allowed {
+            return Err(Error::InvalidTarget);
+        }
+    }
+
+    Ok(target)
+}
+
+// Lets a stream that can only be read from stand in for a connection in
+// tests. Anything written to it, like a `100 Continue`, is thrown away.
+#[cfg(test)]
+pub struct ReadOnly<S>(pub S);
+
+#[cfg(test)]
+impl<S: Read> Read for ReadOnly<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl<S> Write for ReadOnly<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod server_should {
 
     use super::*;
     use http::method::Method;
 
-    static HTTP_REQUEST: &'static [u8] = include_bytes!("../tests/big-http-request.txt");
-    static PUT_REQUEST: &'static [u8] = b"PUT / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n";
+    fn read<S: Read>(stream: &mut S, timeout: Option<Duration>) -> Result<Request<Vec<u8>>, Error> {
+        super::read(
+            &mut ReadOnly(stream),
+            &mut vec![],
+            timeout,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+    }
+
+    static HTTP_REQUEST: &[u8] = include_bytes!("../tests/big-http-request.txt");
+    static PUT_REQUEST: &[u8] = b"PUT / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n";
+    static HTTP10_REQUEST: &[u8] = b"GET / HTTP/1.0\r\n\r\n";
 
     struct ChunkStream<'content> {
         content: &'content [u8],
@@ -89,7 +647,7 @@ mod server_should {
     impl<'content> ChunkStream<'content> {
         fn new(content: &'content [u8]) -> ChunkStream<'content> {
             ChunkStream {
-                content: content,
+                content,
                 bytes_read: 0,
                 read_count: 0,
                 timeout: None,
@@ -98,7 +656,7 @@ mod server_should {
 
         fn with_timeout(content: &'content [u8], timeout: Duration) -> ChunkStream<'content> {
             ChunkStream {
-                content: content,
+                content,
                 bytes_read: 0,
                 read_count: 0,
                 timeout: Some(timeout),
@@ -118,12 +676,12 @@ mod server_should {
                     0 => {
                         let half = self.content.len() / 2;
                         let min = ::std::cmp::min(half, buf.len());
-                        &buf[..min].copy_from_slice(&self.content[..min]);
+                        buf[..min].copy_from_slice(&self.content[..min]);
                         min
                     }
                     _ => {
                         let min = ::std::cmp::min(self.content[self.bytes_read..].len(), buf.len());
-                        &buf[..min]
+                        buf[..min]
                             .copy_from_slice(&self.content[self.bytes_read..self.bytes_read + min]);
                         min
                     }
@@ -170,10 +728,943 @@ mod server_should {
         assert!(r.headers().contains_key("X-ONEMORE-HEADER"));
     }
 
+    fn connection_for(version: Version, value: Option<&str>) -> Connection {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = value {
+            headers.insert(header::CONNECTION, HeaderValue::from_str(value).unwrap());
+        }
+        connection(version, &headers)
+    }
+
+    #[test]
+    fn keep_http11_connections_alive_by_default() {
+        assert_eq!(
+            Connection::KeepAlive,
+            connection_for(Version::HTTP_11, None)
+        );
+        assert_eq!(
+            Connection::KeepAlive,
+            connection_for(Version::HTTP_11, Some("keep-alive"))
+        );
+    }
+
+    #[test]
+    fn close_http10_connections_by_default() {
+        assert_eq!(Connection::Close, connection_for(Version::HTTP_10, None));
+        assert_eq!(
+            Connection::Close,
+            connection_for(Version::HTTP_10, Some("upgrade"))
+        );
+        assert_eq!(
+            Connection::KeepAlive,
+            connection_for(Version::HTTP_10, Some("Keep-Alive"))
+        );
+    }
+
+    #[test]
+    fn honour_connection_close_on_any_version() {
+        for version in &[Version::HTTP_10, Version::HTTP_11] {
+            assert_eq!(Connection::Close, connection_for(*version, Some("close")));
+            assert_eq!(Connection::Close, connection_for(*version, Some("CLOSE")));
+            assert_eq!(
+                Connection::Close,
+                connection_for(*version, Some("keep-alive, Close"))
+            );
+        }
+    }
+
+    #[test]
+    fn not_match_connection_tokens_by_substring() {
+        assert_eq!(
+            Connection::KeepAlive,
+            connection_for(Version::HTTP_11, Some("closed, x-close-later"))
+        );
+    }
+
+    #[test]
+    fn preserve_repeated_headers() {
+        use http::header::*;
+        let mut s = ChunkStream::new(
+            b"GET / HTTP/1.1\r\nAccept: text/html\r\nHost: x\r\naccept: */*\r\n\r\n",
+        );
+        let r = read(&mut s, None).unwrap();
+        let accept: Vec<_> = r.headers().get_all(ACCEPT).iter().collect();
+        assert_eq!(vec!["text/html", "*/*"], accept);
+    }
+
     #[test]
     fn parse_method_correctly() {
         let mut s = ChunkStream::new(PUT_REQUEST);
         let req = read(&mut s, None).expect("Failed to parse PUT request.");
         assert_eq!(Method::PUT, *req.method());
+        assert_eq!(Version::HTTP_11, req.version());
+    }
+
+    #[test]
+    fn parse_http10_version_correctly() {
+        let mut s = ChunkStream::new(HTTP10_REQUEST);
+        let req = read(&mut s, None).expect("Failed to parse HTTP/1.0 request.");
+        assert_eq!(Version::HTTP_10, req.version());
+        assert_eq!(Connection::Close, connection(req.version(), req.headers()));
+    }
+
+    struct ByteAtATime<'content>(&'content [u8]);
+
+    impl<'content> Read for ByteAtATime<'content> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&byte, rest)) if !buf.is_empty() => {
+                    buf[0] = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_a_trickling_request_only_when_needed() {
+        let calls = parsing::PARSE_CALLS.with(|calls| calls.get());
+
+        let req = read(&mut ByteAtATime(HTTP_REQUEST), None).unwrap();
+
+        assert_eq!(4, req.headers().len());
+        // Once for the request line, and once for the whole head.
+        assert_eq!(2, parsing::PARSE_CALLS.with(|c| c.get()) - calls);
+    }
+
+    #[test]
+    fn find_the_end_of_the_head_without_carriage_returns() {
+        let req = read(&mut ByteAtATime(b"GET / HTTP/1.1\nHost: x\n\n"), None).unwrap();
+
+        assert_eq!("x", req.headers()["host"]);
+    }
+
+    #[test]
+    fn reject_a_bad_request_line_before_the_head_ends() {
+        match read(&mut ByteAtATime(b"GET / HTTP/1.1Host: x\r\n"), None) {
+            Err(Error::HttpParse(_)) => {}
+            Err(e) => panic!("Expected HttpParse. Got {:?}", e),
+            Ok(_) => panic!("Expected HttpParse. Got Ok(_)"),
+        }
+    }
+
+    #[test]
+    fn reject_a_request_line_that_isnt_utf8() {
+        for request in &[
+            &b"GET /caf\xff HTTP/1.1\r\nHost: x\r\n\r\n"[..],
+            b"G\xffT / HTTP/1.1\r\n\r\n",
+        ] {
+            match read(&mut ByteAtATime(request), None) {
+                Err(Error::HttpParse(_)) => {}
+                Err(e) => panic!("Expected HttpParse. Got {:?}", e),
+                Ok(_) => panic!("Expected HttpParse. Got Ok(_)"),
+            }
+        }
+    }
+
+    // Hands out its contents in one go, and panics if it's read again.
+    struct StrictStream<'content>(&'content [u8]);
+
+    impl<'content> Read for StrictStream<'content> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                panic!("Read past the end of the request");
+            }
+            let n = ::std::cmp::min(buf.len(), self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn not_read_again_once_the_request_is_complete() {
+        for request in &[
+            &b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello",
+        ] {
+            read(&mut StrictStream(request), None).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_the_rest_of_the_body() {
+        let req = read(
+            &mut ByteAtATime(b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world"),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(&b"hello world"[..], &req.body()[..]);
+    }
+
+    #[test]
+    fn keep_what_follows_a_request_for_the_next_one() {
+        let mut stream = &b"POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+                            GET /b HTTP/1.1\r\n\r\n\
+                            GET /c HTTP/1.1\r\n"[..];
+        let mut buffered = vec![];
+
+        let first = super::read(
+            &mut ReadOnly(&mut stream),
+            &mut buffered,
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!("/a", first.uri().path());
+        assert_eq!(&b"hello"[..], &first.body()[..]);
+
+        let second = super::read(
+            &mut ReadOnly(&mut stream),
+            &mut buffered,
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!("/b", second.uri().path());
+        assert!(second.body().is_empty());
+        assert_eq!(&b"GET /c HTTP/1.1\r\n"[..], &buffered[..]);
+    }
+
+    #[test]
+    fn not_read_again_when_the_next_request_is_buffered() {
+        let mut buffered = b"GET /b HTTP/1.1\r\nHost: x\r\n\r\n".to_vec();
+
+        let req = super::read(
+            &mut ReadOnly(StrictStream(b"")),
+            &mut buffered,
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!("/b", req.uri().path());
+        assert!(buffered.is_empty());
+    }
+
+    #[test]
+    fn reject_a_long_request_line_before_it_ends() {
+        let mut stream = ReadOnly(io::repeat(b'a'));
+
+        match super::read(
+            &mut stream,
+            &mut vec![],
+            None,
+            1024,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        ) {
+            Err(Error::RequestLineTooLong) => {}
+            other => panic!("Expected RequestLineTooLong. Got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_a_long_request_line_spread_over_reads() {
+        let mut request = b"GET /".to_vec();
+        request.extend_from_slice(&[b'a'; 200]);
+        request.extend_from_slice(b" HTTP/1.1\r\nHost: x\r\n\r\n");
+        let mut stream = ReadOnly(ByteAtATime(&request));
+
+        match super::read(
+            &mut stream,
+            &mut vec![],
+            None,
+            100,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        ) {
+            Err(Error::RequestLineTooLong) => {}
+            other => panic!("Expected RequestLineTooLong. Got {:?}", other),
+        }
+        // It gave up as soon as the line got too long.
+        assert_eq!(request.len() - 101, (stream.0).0.len());
+    }
+
+    #[test]
+    fn accept_a_request_line_as_long_as_the_limit() {
+        let request = b"GET /abc HTTP/1.1\r\nHost: x\r\n\r\n";
+
+        let req = super::read(
+            &mut ReadOnly(ByteAtATime(request)),
+            &mut vec![],
+            None,
+            17,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!("/abc", req.uri().path());
+
+        match super::read(
+            &mut ReadOnly(ByteAtATime(request)),
+            &mut vec![],
+            None,
+            16,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        ) {
+            Err(Error::RequestLineTooLong) => {}
+            other => panic!("Expected RequestLineTooLong. Got {:?}", other),
+        }
+    }
+
+    // Hands out one chunk per read, and records whatever's written back.
+    struct Conversation<'content> {
+        reads: Vec<&'content [u8]>,
+        written: Vec<u8>,
+    }
+
+    impl<'content> Read for Conversation<'content> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.reads.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.reads.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl<'content> Write for Conversation<'content> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn converse(reads: Vec<&[u8]>) -> (Result<Request<Vec<u8>>, Error>, Vec<u8>) {
+        let mut stream = Conversation {
+            reads,
+            written: vec![],
+        };
+        let result = super::read(
+            &mut stream,
+            &mut vec![],
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        );
+        (result, stream.written)
+    }
+
+    #[test]
+    fn ask_for_the_body_when_the_client_expects_to_be_asked() {
+        let (result, written) = converse(vec![
+            b"POST / HTTP/1.1\r\nExpect: 100-Continue\r\nContent-Length: 5\r\n\r\n",
+            b"hello",
+        ]);
+
+        assert_eq!(&b"hello"[..], &result.unwrap().body()[..]);
+        assert_eq!(&b"HTTP/1.1 100 Continue\r\n\r\n"[..], &written[..]);
+    }
+
+    #[test]
+    fn not_ask_for_a_body_that_needs_no_asking() {
+        for reads in [
+            // No expectation.
+            vec![
+                &b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n"[..],
+                b"hello",
+            ],
+            // The body's already here.
+            vec![b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\nhello"],
+            // HTTP/1.0 clients can't be sent interim responses.
+            vec![
+                b"POST / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+                b"hello",
+            ],
+        ] {
+            let (result, written) = converse(reads);
+
+            assert_eq!(&b"hello"[..], &result.unwrap().body()[..]);
+            assert!(written.is_empty());
+        }
+    }
+
+    #[test]
+    fn refuse_expectations_that_cant_be_met() {
+        for head in &[
+            &b"POST / HTTP/1.1\r\nExpect: something-else\r\nContent-Length: 5\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nExpect: 100-continue, something-else\r\nContent-Length: 5\r\n\r\n",
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nExpect: 101-continue\r\nContent-Length: 5\r\n\r\n",
+        ] {
+            let (result, written) = converse(vec![head, b"hello"]);
+
+            match result {
+                Err(Error::ExpectationFailed) => {}
+                other => panic!("Expected ExpectationFailed. Got {:?}", other),
+            }
+            assert!(written.is_empty());
+        }
+    }
+
+    #[test]
+    fn refuse_a_huge_body_before_asking_for_it() {
+        let (result, written) = converse(vec![
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 18446744073709551615\r\n\r\n",
+            b"hello",
+        ]);
+
+        match result {
+            Err(Error::RequestTooLarge) => {}
+            other => panic!("Expected RequestTooLarge. Got {:?}", other),
+        }
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn count_the_head_and_body_together_against_the_size_limit() {
+        let request = &b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello"[..];
+        let read_limited = |max_request_size| {
+            super::read(
+                &mut ReadOnly(StrictStream(request)),
+                &mut vec![],
+                None,
+                DEFAULT_MAX_REQUEST_LINE,
+                max_request_size,
+                None,
+                None,
+            )
+        };
+
+        let req = read_limited(request.len() as u64).unwrap();
+        assert_eq!(&b"hello"[..], &req.body()[..]);
+
+        match read_limited(request.len() as u64 - 1) {
+            Err(Error::RequestTooLarge) => {}
+            other => panic!("Expected RequestTooLarge. Got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn refuse_a_head_too_big_to_take_before_it_ends() {
+        let mut request = b"GET / HTTP/1.1\r\n".to_vec();
+        for _ in 0..100 {
+            request.extend_from_slice(b"X-Padding: aaaaaaaaaaaaaaaa\r\n");
+        }
+        request.extend_from_slice(b"\r\n");
+        let mut stream = ReadOnly(ByteAtATime(&request));
+
+        match super::read(
+            &mut stream,
+            &mut vec![],
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            1024,
+            None,
+            None,
+        ) {
+            Err(Error::RequestTooLarge) => {}
+            other => panic!("Expected RequestTooLarge. Got {:?}", other),
+        }
+        // It gave up as soon as the head got too big.
+        assert_eq!(request.len() - 1025, (stream.0).0.len());
+    }
+
+    #[test]
+    fn decode_a_chunked_body_split_across_reads() {
+        let (result, written) = converse(vec![
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+            b"lo\r\n",
+            b"6;name=value\r\n world\r",
+            b"\n0\r\nx-trailer: 1\r",
+            b"\n\r\n",
+        ]);
+
+        assert_eq!(&b"hello world"[..], &result.unwrap().body()[..]);
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn keep_what_follows_a_chunked_body_for_the_next_request() {
+        let mut buffered = vec![];
+
+        let req = super::read(
+            &mut ReadOnly(ByteAtATime(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n\
+                  A\r\n0123456789\r\n0\r\n\r\nGET /b HTTP/1.1\r\n",
+            )),
+            &mut buffered,
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(&b"0123456789"[..], &req.body()[..]);
+        assert!(buffered.is_empty());
+
+        let mut buffered = vec![];
+        super::read(
+            &mut ReadOnly(StrictStream(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /b HTTP/1.1\r\n",
+            )),
+            &mut buffered,
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(&b"GET /b HTTP/1.1\r\n"[..], &buffered[..]);
+    }
+
+    #[test]
+    fn ask_for_a_chunked_body_when_the_client_expects_to_be_asked() {
+        let (result, written) = converse(vec![
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"2\r\nhi\r\n0\r\n\r\n",
+        ]);
+
+        assert_eq!(&b"hi"[..], &result.unwrap().body()[..]);
+        assert_eq!(&b"HTTP/1.1 100 Continue\r\n\r\n"[..], &written[..]);
+    }
+
+    #[test]
+    fn reject_malformed_chunks() {
+        for body in &[
+            &b"zz\r\nhello\r\n0\r\n\r\n"[..],
+            b"\r\nhello\r\n0\r\n\r\n",
+            b" 5\r\nhello\r\n0\r\n\r\n",
+            b"-5\r\nhello\r\n0\r\n\r\n",
+            b"5\nhello\r\n0\r\n\r\n",
+            b"5\r\nhello\n0\r\n\r\n",
+            b"5\r\nhelloXX0\r\n\r\n",
+            b"3\r\nhello\r\n0\r\n\r\n",
+            b"10000000000000000\r\nhello\r\n0\r\n\r\n",
+        ] {
+            let mut request = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+            request.extend_from_slice(body);
+
+            match read(&mut ByteAtATime(&request), None) {
+                Err(Error::Framing(FramingError::InvalidChunk)) => {}
+                other => panic!(
+                    "Expected InvalidChunk for {:?}. Got {:?}",
+                    String::from_utf8_lossy(body),
+                    other
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn refuse_a_chunked_body_too_big_to_take() {
+        let mut request = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        for _ in 0..100 {
+            request.extend_from_slice(b"10\r\n0123456789abcdef\r\n");
+        }
+        request.extend_from_slice(b"0\r\n\r\n");
+        let mut stream = ReadOnly(ByteAtATime(&request));
+
+        match super::read(
+            &mut stream,
+            &mut vec![],
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            1024,
+            None,
+            None,
+        ) {
+            Err(Error::RequestTooLarge) => {}
+            other => panic!("Expected RequestTooLarge. Got {:?}", other),
+        }
+        // It gave up as soon as the body got too big.
+        assert!((stream.0).0.len() > 1000);
+    }
+
+    #[test]
+    fn stop_reading_at_the_end_of_the_body() {
+        let req = read(
+            &mut &b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello world"[..],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(&b"hello"[..], &req.body()[..]);
+    }
+
+    #[test]
+    fn fail_when_the_body_is_cut_short() {
+        match read(
+            &mut &b"POST / HTTP/1.1\r\nContent-Length: 50\r\n\r\nhello"[..],
+            None,
+        ) {
+            Err(Error::ConnectionClosed) => {}
+            Err(e) => panic!("Expected ConnectionClosed. Got {:?}", e),
+            Ok(_) => panic!("Expected ConnectionClosed. Got Ok(_)"),
+        }
+    }
+
+    fn framing_error(request: &[u8]) -> Option<FramingError> {
+        match read(&mut &request[..], None) {
+            Err(Error::Framing(e)) => Some(e),
+            Err(e) => panic!("Expected a framing error. Got {:?}", e),
+            Ok(_) => None,
+        }
+    }
+
+    #[test]
+    fn reject_content_length_with_transfer_encoding() {
+        for request in &[
+            &b"POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\nhello"[..],
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\nhello",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\nContent-Length: 0\r\n\r\n",
+        ] {
+            assert_eq!(
+                Some(FramingError::ContentLengthWithTransferEncoding),
+                framing_error(request)
+            );
+        }
+    }
+
+    #[test]
+    fn reject_transfer_encodings_not_ending_in_chunked() {
+        for request in &[
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\n\r\n",
+        ] {
+            assert_eq!(
+                Some(FramingError::UnsupportedTransferEncoding),
+                framing_error(request)
+            );
+        }
+    }
+
+    #[test]
+    fn accept_chunked_as_the_last_transfer_encoding() {
+        for request in &[
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, CHUNKED\r\n\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        ] {
+            assert_eq!(None, framing_error(request));
+        }
+    }
+
+    #[test]
+    fn reject_conflicting_content_lengths() {
+        for request in &[
+            &b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!"[..],
+            b"POST / HTTP/1.1\r\nContent-Length: 5, 6\r\n\r\nhello!",
+        ] {
+            assert_eq!(
+                Some(FramingError::ConflictingContentLengths),
+                framing_error(request)
+            );
+        }
+    }
+
+    #[test]
+    fn validate_content_lengths_strictly() {
+        let cases: &[(&str, Result<Option<u64>, FramingError>)] = &[
+            ("0", Ok(Some(0))),
+            ("42", Ok(Some(42))),
+            ("007", Ok(Some(7))),
+            (" 42\t", Ok(Some(42))),
+            ("42, 42", Ok(Some(42))),
+            ("1048576", Ok(Some(1048576))),
+            ("", Err(FramingError::InvalidContentLength)),
+            ("abc", Err(FramingError::InvalidContentLength)),
+            ("-1", Err(FramingError::InvalidContentLength)),
+            ("+5", Err(FramingError::InvalidContentLength)),
+            ("4 2", Err(FramingError::InvalidContentLength)),
+            ("0x10", Err(FramingError::InvalidContentLength)),
+            ("1e3", Err(FramingError::InvalidContentLength)),
+            ("42,", Err(FramingError::InvalidContentLength)),
+            (
+                "18446744073709551616",
+                Err(FramingError::InvalidContentLength),
+            ),
+            (
+                "99999999999999999999999999",
+                Err(FramingError::InvalidContentLength),
+            ),
+            ("42, 43", Err(FramingError::ConflictingContentLengths)),
+        ];
+
+        for &(value, ref expected) in cases {
+            let head = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", value);
+            let request = match parsing::try_parse_request(head.into_bytes()).unwrap() {
+                parsing::ParseResult::Complete(request) => request,
+                parsing::ParseResult::Partial(_) => panic!("Partial parse of {:?}", value),
+            };
+
+            assert_eq!(*expected, content_length(&request), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_content_lengths_when_reading() {
+        assert_eq!(
+            Some(FramingError::InvalidContentLength),
+            framing_error(b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n")
+        );
+    }
+
+    #[test]
+    fn accept_repeated_identical_content_lengths() {
+        let req = read(
+            &mut &b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello"[..],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(&b"hello"[..], &req.body()[..]);
+    }
+
+    #[test]
+    fn keep_header_values_that_are_not_utf8() {
+        let req = read(
+            &mut &b"GET / HTTP/1.1\r\nUser-Agent: Caf\xe9 \xff\r\nX-Tab: a\tb\r\n\r\n"[..],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(&b"Caf\xe9 \xff"[..], req.headers()["user-agent"].as_bytes());
+        assert_eq!(&b"a\tb"[..], req.headers()["x-tab"].as_bytes());
+    }
+
+    #[test]
+    fn reject_header_values_with_control_characters() {
+        for request in &[
+            &b"GET / HTTP/1.1\r\nX-Bad: a\x7fb\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nX-Bad: a\x01b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nX-Bad: a\x00b\r\n\r\n",
+        ] {
+            match read(&mut &request[..], None) {
+                Err(Error::InvalidHeader) | Err(Error::HttpParse(_)) => {}
+                Err(e) => panic!("Expected a bad header. Got {:?}", e),
+                Ok(_) => panic!("Expected a bad header. Got Ok(_)"),
+            }
+        }
+    }
+
+    #[test]
+    fn read_authority_form_targets() {
+        for target in &["example.com:443", "127.0.0.1:8080", "[::1]:443"] {
+            let request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target);
+            let req = read(&mut request.as_bytes(), None).unwrap();
+
+            assert_eq!(Method::CONNECT, *req.method());
+            assert_eq!(*target, req.uri().to_string());
+        }
+    }
+
+    fn read_target(target: &[u8]) -> Result<Request<Vec<u8>>, Error> {
+        let mut request = b"GET ".to_vec();
+        request.extend_from_slice(target);
+        request.extend_from_slice(b" HTTP/1.1\r\nHost: x\r\n\r\n");
+        read(&mut &request[..], None)
+    }
+
+    #[test]
+    fn reject_invalid_characters_in_the_target() {
+        for target in &[
+            &b"/a\"b"[..],
+            b"/<script>",
+            b"/a{b}",
+            b"/a|b",
+            b"/a\\b",
+            b"/a^b",
+            b"/a`b",
+            b"/100%",
+            b"/%zz",
+            b"/%4",
+            b"#fragment",
+        ] {
+            match read_target(target) {
+                Err(Error::InvalidTarget) => {}
+                Err(e) => panic!("Expected InvalidTarget for {:?}. Got {:?}", target, e),
+                Ok(_) => panic!("Expected InvalidTarget for {:?}. Got Ok(_)", target),
+            }
+        }
+    }
+
+    #[test]
+    fn drop_fragments_from_the_target() {
+        let request = read_target(b"/docs?page=2#intro").unwrap();
+
+        assert_eq!("http://x/docs?page=2", request.uri().to_string());
+    }
+
+    #[test]
+    fn take_the_authority_from_the_host_header() {
+        let request = b"GET /a/b?c=d HTTP/1.1\r\nHost: example.com:8080\r\n\r\n";
+        let req = read(&mut &request[..], None).unwrap();
+
+        assert_eq!(Some("http"), req.uri().scheme_str());
+        assert_eq!(Some("example.com"), req.uri().host());
+        assert_eq!(Some(8080), req.uri().port_part().map(|port| port.as_u16()));
+        assert_eq!("/a/b", req.uri().path());
+        assert_eq!(Some("c=d"), req.uri().query());
+    }
+
+    #[test]
+    fn keep_a_path_only_target_without_a_usable_host() {
+        for request in &[
+            &b"GET /a?b HTTP/1.0\r\n\r\n"[..],
+            b"GET /a?b HTTP/1.1\r\nHost: bad host\r\n\r\n",
+            b"GET /a?b HTTP/1.1\r\nHost: user@example.com\r\n\r\n",
+            b"GET /a?b HTTP/1.1\r\nHost: one.example\r\nHost: two.example\r\n\r\n",
+        ] {
+            let req = read(&mut &request[..], None).unwrap();
+
+            assert_eq!("/a?b", req.uri().to_string());
+        }
+    }
+
+    #[test]
+    fn leave_targets_that_arent_paths_alone() {
+        for &(target, expected) in &[
+            ("*", "*"),
+            ("http://example.com/a?b", "http://example.com/a?b"),
+        ] {
+            let req = read_target(target.as_bytes()).unwrap();
+
+            assert_eq!(expected, req.uri().to_string());
+        }
+    }
+
+    #[test]
+    fn accept_every_kind_of_valid_target() {
+        for target in &[
+            &b"/"[..],
+            b"/a/b.html?x=1&y=%2F",
+            b"/~user/it's(1)!*+,;=$:@",
+            b"*",
+            b"example.com:443",
+            b"http://example.com/a?b",
+        ] {
+            if let Err(e) = read_target(target) {
+                panic!("Expected {:?} to be accepted. Got {:?}", target, e);
+            }
+        }
+    }
+
+    #[test]
+    fn never_panic_on_malformed_targets() {
+        let mut targets: Vec<Vec<u8>> = vec![];
+        for byte in 0..=255u8 {
+            targets.push(vec![b'/', byte]);
+            targets.push(vec![b'/', b'a', b'?', byte, b'#', byte]);
+            targets.push(vec![b'/', b'%', byte, byte]);
+            targets.push(vec![byte]);
+        }
+        for target in &[
+            "", " ", "/ /", "//", "?", "#", "%", "/%%", "/\u{e9}", "[::1]",
+        ] {
+            targets.push(target.as_bytes().to_vec());
+        }
+
+        for target in &targets {
+            match read_target(target) {
+                Ok(request) => assert!(!request.uri().to_string().contains('#')),
+                Err(Error::InvalidTarget)
+                | Err(Error::InvalidUri(_))
+                | Err(Error::HttpParse(_))
+                | Err(Error::MissingVersion) => {}
+                Err(e) => panic!("Unexpected error for {:?}: {:?}", target, e),
+            }
+        }
+    }
+
+    fn read_watched(request: &[u8]) -> (Option<Error>, Vec<Vec<u8>>) {
+        use std::sync::Mutex;
+
+        let seen = Mutex::new(vec![]);
+        let result = super::read(
+            &mut ReadOnly(ByteAtATime(request)),
+            &mut vec![],
+            None,
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            Some(&|head: &[u8], _: &Error| seen.lock().unwrap().push(head.to_vec())),
+            None,
+        );
+        (result.err(), seen.into_inner().unwrap())
+    }
+
+    #[test]
+    fn show_the_hook_what_failed_to_parse() {
+        let request = b"GET / HTTP/1.1\r\nNot a header\r\n\r\n";
+
+        let (error, seen) = read_watched(request);
+
+        match error {
+            Some(Error::HttpParse(_)) => {}
+            other => panic!("Expected HttpParse. Got {:?}", other),
+        }
+        assert_eq!(vec![request.to_vec()], seen);
+    }
+
+    #[test]
+    fn cap_what_the_hook_is_shown() {
+        let mut request = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.extend_from_slice(&[b'a'; 2 * MAX_OBSERVED_BYTES]);
+        request.extend_from_slice(b"\r\nNot a header\r\n\r\n");
+
+        let (error, seen) = read_watched(&request);
+
+        assert!(error.is_some());
+        assert_eq!(1, seen.len());
+        assert_eq!(&request[..MAX_OBSERVED_BYTES], &seen[0][..]);
+    }
+
+    #[test]
+    fn show_the_hook_requests_that_time_out() {
+        use std::sync::Mutex;
+
+        let timeout = Duration::from_millis(50);
+        let errors = Mutex::new(vec![]);
+
+        let result = super::read(
+            &mut ReadOnly(ChunkStream::with_timeout(HTTP_REQUEST, timeout)),
+            &mut vec![],
+            Some(timeout),
+            DEFAULT_MAX_REQUEST_LINE,
+            DEFAULT_MAX_REQUEST_SIZE,
+            Some(&|_: &[u8], e: &Error| errors.lock().unwrap().push(format!("{:?}", e))),
+            None,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(vec!["Timeout".to_string()], errors.into_inner().unwrap());
+    }
+
+    #[test]
+    fn not_call_the_hook_for_good_requests() {
+        let (error, seen) = read_watched(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+
+        assert!(error.is_none());
+        assert!(seen.is_empty());
     }
 }
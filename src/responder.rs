@@ -0,0 +1,139 @@
+use body::Body;
+use error::Error;
+use http::header::CONTENT_TYPE;
+use http::{Response, StatusCode};
+
+/// Something a handler made with `Server::new_with_responder` can return.
+///
+/// Strings are sent with a `200 OK` as `text/plain`, and bytes with a
+/// `200 OK` and no content type. Pair either with a `StatusCode` to send
+/// another status, or return a `StatusCode` alone for an empty body. For
+/// anything else, build a whole `Response`. A `Result` of any of these can be
+/// returned too, so that `?` works; an `Err` gets a `500`, as it would from
+/// any other handler.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{Server, StatusCode};
+///
+/// fn main() {
+///     let server = Server::new_with_responder(|request| match request.uri().path() {
+///         "/" => (StatusCode::OK, String::from("Hello, world!")),
+///         path => (StatusCode::NOT_FOUND, format!("Nothing at {}", path)),
+///     });
+/// }
+/// ```
+pub trait Responder {
+    /// Turns the value into the response to send.
+    fn into_response(self) -> Result<Response<Body>, Error>;
+}
+
+const TEXT: &str = "text/plain; charset=utf-8";
+
+impl Responder for &'static str {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        (StatusCode::OK, self).into_response()
+    }
+}
+
+impl Responder for String {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        (StatusCode::OK, self).into_response()
+    }
+}
+
+impl Responder for Vec<u8> {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        Ok(Response::new(Body::Bytes(self)))
+    }
+}
+
+impl Responder for StatusCode {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        Ok(Response::builder().status(self).body(Body::Static(b""))?)
+    }
+}
+
+impl Responder for (StatusCode, &'static str) {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        Ok(Response::builder()
+            .status(self.0)
+            .header(CONTENT_TYPE, TEXT)
+            .body(Body::from(self.1))?)
+    }
+}
+
+impl Responder for (StatusCode, String) {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        Ok(Response::builder()
+            .status(self.0)
+            .header(CONTENT_TYPE, TEXT)
+            .body(Body::from(self.1))?)
+    }
+}
+
+impl<B: Into<Body>> Responder for Response<B> {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        Ok(self.map(Into::into))
+    }
+}
+
+impl<R: Responder, E: Into<Error>> Responder for Result<R, E> {
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        self.map_err(Into::into)?.into_response()
+    }
+}
+
+#[cfg(test)]
+mod responder_should {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn send_strings_as_plain_text() {
+        let borrowed = "hello".into_response().unwrap();
+        let owned = String::from("hello").into_response().unwrap();
+
+        for response in &[&borrowed, &owned] {
+            assert_eq!(StatusCode::OK, response.status());
+            assert_eq!(TEXT, response.headers()[CONTENT_TYPE]);
+        }
+        assert_eq!(Body::Static(b"hello"), *borrowed.body());
+        assert_eq!(Body::Bytes(b"hello".to_vec()), *owned.body());
+    }
+
+    #[test]
+    fn send_bytes_without_a_content_type() {
+        let response = vec![1, 2, 3].into_response().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+        assert_eq!(Body::Bytes(vec![1, 2, 3]), *response.body());
+    }
+
+    #[test]
+    fn use_the_status_it_was_given() {
+        let response = (StatusCode::NOT_FOUND, "gone").into_response().unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(Body::Static(b"gone"), *response.body());
+
+        let response = StatusCode::NO_CONTENT.into_response().unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(Body::Static(b""), *response.body());
+    }
+
+    #[test]
+    fn pass_errors_on() {
+        let ok: Result<&'static str, io::Error> = Ok("fine");
+        assert_eq!(StatusCode::OK, ok.into_response().unwrap().status());
+
+        let failed: Result<&'static str, io::Error> = Err(io::Error::other("broken"));
+        match failed.into_response() {
+            Err(Error::Io(_)) => {}
+            other => panic!("Expected an Io error. Got {:?}", other.map(|_| ())),
+        }
+    }
+}
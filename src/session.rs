@@ -0,0 +1,441 @@
+use error::Error;
+use hmac::{Hmac, Mac};
+use http::header::{HeaderValue, COOKIE, SET_COOKIE};
+use http::Request;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ResponseBuilder;
+
+const COOKIE_NAME: &str = "session";
+
+// Browsers only promise to keep cookies up to about this size, name and
+// attributes included.
+const MAX_COOKIE_SIZE: usize = 4096;
+
+pub type HmacSha256 = Hmac<Sha256>;
+
+/// A small set of key/value pairs kept in a signed cookie.
+///
+/// When a session secret is set with `Server::set_session_secret`, every
+/// request passed to the handler carries the session its cookie held, or an
+/// empty one if it had none, or one that was tampered with or expired. Get it
+/// with `request.session()`, from the `SessionExt` trait.
+///
+/// The session is signed, not encrypted: clients can read what's in it, they
+/// just can't change it. Changes are only sent back once `apply_to` is called
+/// on the response.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{Server, SessionExt};
+///
+/// fn main() {
+///     let mut server = Server::new(|request, mut response| {
+///         let mut session = request.session().expect("a session secret is set");
+///
+///         let visits: u32 = session.get("visits").and_then(|v| v.parse().ok()).unwrap_or(0);
+///         session.insert("visits", (visits + 1).to_string());
+///         session.apply_to(&mut response)?;
+///
+///         Ok(response.body(format!("Visit number {}", visits + 1).into_bytes())?)
+///     });
+///
+///     server.set_session_secret(*b"an example secret of 32 bytes!!!");
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Session {
+    values: BTreeMap<String, String>,
+    secret: Arc<[u8; 32]>,
+    max_age: Duration,
+}
+
+/// Gives access to the session of a request.
+pub trait SessionExt {
+    /// The session the request's cookie held, or `None` if the server wasn't
+    /// given a session secret.
+    fn session(&self) -> Option<Session>;
+}
+
+impl<B> SessionExt for Request<B> {
+    fn session(&self) -> Option<Session> {
+        self.extensions().get::<Session>().cloned()
+    }
+}
+
+impl Session {
+    /// Reads the session from the request's cookie, starting afresh if there
+    /// isn't a valid one.
+    pub(crate) fn from_request<B>(
+        request: &Request<B>,
+        secret: Arc<[u8; 32]>,
+        max_age: Duration,
+    ) -> Session {
+        let values = request
+            .headers()
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .filter(|&(name, _)| name == COOKIE_NAME)
+            .find_map(|(_, value)| verify(value, &secret, now()))
+            .unwrap_or_default();
+
+        Session {
+            values,
+            secret,
+            max_age,
+        }
+    }
+
+    /// The value stored under `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Stores `value` under `key`, replacing whatever was there.
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.values.insert(key.into(), value.into());
+    }
+
+    /// Removes the value stored under `key`, returning it.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.values.remove(key)
+    }
+
+    /// Removes everything from the session. Applying an empty session deletes
+    /// the cookie.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Adds a `Set-Cookie` header holding the session to a response.
+    ///
+    /// The cookie is `HttpOnly` and `SameSite=Lax`, and expires after the
+    /// server's session max age, which starts over every time it's applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionTooLarge` if the cookie would be bigger than
+    /// browsers are willing to store.
+    pub fn apply_to(&self, response: &mut ResponseBuilder) -> Result<(), Error> {
+        let cookie = if self.values.is_empty() {
+            format!(
+                "{}=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax",
+                COOKIE_NAME
+            )
+        } else {
+            let expires = now() + self.max_age.as_secs();
+            format!(
+                "{}={}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax",
+                COOKIE_NAME,
+                sign(&self.values, expires, &self.secret),
+                self.max_age.as_secs()
+            )
+        };
+
+        if cookie.len() > MAX_COOKIE_SIZE {
+            return Err(Error::SessionTooLarge(cookie.len()));
+        }
+
+        response.header(SET_COOKIE, HeaderValue::from_str(&cookie)?);
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn mac(secret: &[u8; 32]) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret).expect("HMAC takes keys of any size")
+}
+
+// The cookie's value is the payload and its signature, both base64 encoded and
+// separated by a dot. The payload is the expiry time followed by the escaped
+// keys and values, like a query string: `1700000000&user=42&theme=dark`.
+fn sign(values: &BTreeMap<String, String>, expires: u64, secret: &[u8; 32]) -> String {
+    let mut payload = expires.to_string();
+    for (key, value) in values {
+        payload.push('&');
+        escape(key, &mut payload);
+        payload.push('=');
+        escape(value, &mut payload);
+    }
+
+    let mut mac = mac(secret);
+    mac.update(payload.as_bytes());
+    let signature = mac.finalize().into_bytes();
+
+    format!(
+        "{}.{}",
+        base64_encode(payload.as_bytes()),
+        base64_encode(&signature)
+    )
+}
+
+// Returns the values in a cookie, as long as its signature is right and it
+// hasn't expired by `now`.
+fn verify(cookie: &str, secret: &[u8; 32], now: u64) -> Option<BTreeMap<String, String>> {
+    let (payload, signature) = cookie.split_once('.')?;
+    let payload = base64_decode(payload)?;
+
+    let mut mac = mac(secret);
+    mac.update(&payload);
+    if mac.verify_slice(&base64_decode(signature)?).is_err() {
+        debug!("Ignoring a session cookie with a bad signature");
+        return None;
+    }
+
+    let payload = String::from_utf8(payload).ok()?;
+    let mut fields = payload.split('&');
+    let expires: u64 = fields.next()?.parse().ok()?;
+    if expires <= now {
+        debug!("Ignoring an expired session cookie");
+        return None;
+    }
+
+    fields
+        .map(|field| {
+            let (key, value) = field.split_once('=')?;
+            Some((unescape(key)?, unescape(value)?))
+        })
+        .collect()
+}
+
+fn escape(text: &str, out: &mut String) {
+    for &byte in text.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+pub fn unescape(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = ::std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(out).ok()
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// URL-safe base64 without padding, which needs no quoting in a cookie.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+
+    out
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let digit = BASE64.iter().position(|&b| b == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod session_should {
+    use super::*;
+
+    const SECRET: &[u8; 32] = b"0123456789abcdef0123456789abcdef";
+
+    fn values() -> BTreeMap<String, String> {
+        let mut values = BTreeMap::new();
+        values.insert("user".to_string(), "42".to_string());
+        values.insert("note".to_string(), "a=b&c; d%e".to_string());
+        values
+    }
+
+    fn request_with_cookie(cookie: &str) -> Request<()> {
+        Request::get("/")
+            .header(COOKIE, format!("theme=dark; {}={}", COOKIE_NAME, cookie))
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trip_base64() {
+        for len in 0..10 {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37) as u8).collect();
+            assert_eq!(Some(bytes.clone()), base64_decode(&base64_encode(&bytes)));
+        }
+        assert_eq!("TWFu", base64_encode(b"Man"));
+        assert_eq!("TWE", base64_encode(b"Ma"));
+        assert_eq!(None, base64_decode("TWFuT"));
+    }
+
+    #[test]
+    fn verify_signed_values() {
+        let cookie = sign(&values(), 2000, SECRET);
+
+        assert_eq!(Some(values()), verify(&cookie, SECRET, 1000));
+    }
+
+    #[test]
+    fn reject_tampered_cookies() {
+        let cookie = sign(&values(), 2000, SECRET);
+        let (payload, signature) = cookie.split_once('.').unwrap();
+
+        let mut forged = values();
+        forged.insert("user".to_string(), "1".to_string());
+        let forged_payload = sign(&forged, 2000, SECRET);
+        let forged_payload = forged_payload.split_once('.').unwrap().0;
+
+        assert_eq!(
+            None,
+            verify(&format!("{}.{}", forged_payload, signature), SECRET, 1000)
+        );
+        assert_eq!(
+            None,
+            verify(&format!("{}.{}x", payload, signature), SECRET, 1000)
+        );
+        assert_eq!(None, verify(payload, SECRET, 1000));
+        assert_eq!(
+            None,
+            verify(&cookie, b"another secret of thirty-two b!!", 1000)
+        );
+    }
+
+    #[test]
+    fn reject_expired_cookies() {
+        let cookie = sign(&values(), 2000, SECRET);
+
+        assert_eq!(None, verify(&cookie, SECRET, 2000));
+        assert_eq!(None, verify(&cookie, SECRET, 3000));
+    }
+
+    #[test]
+    fn read_the_session_from_the_request() {
+        let cookie = sign(&values(), now() + 60, SECRET);
+        let session = Session::from_request(
+            &request_with_cookie(&cookie),
+            Arc::new(*SECRET),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(Some("42"), session.get("user"));
+        assert_eq!(Some("a=b&c; d%e"), session.get("note"));
+    }
+
+    #[test]
+    fn start_afresh_without_a_valid_cookie() {
+        let session = Session::from_request(
+            &request_with_cookie("garbage"),
+            Arc::new(*SECRET),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(None, session.get("user"));
+    }
+
+    #[test]
+    fn set_the_cookie_on_the_response() {
+        let mut session = Session::from_request(
+            &Request::get("/").body(()).unwrap(),
+            Arc::new(*SECRET),
+            Duration::from_secs(60),
+        );
+        session.insert("user", "42");
+
+        let mut response = ResponseBuilder::new();
+        session.apply_to(&mut response).unwrap();
+        let response = response.body(()).unwrap();
+        let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+
+        assert!(set_cookie.ends_with("; Max-Age=60; Path=/; HttpOnly; SameSite=Lax"));
+        let returned = request_with_cookie(
+            set_cookie
+                .split(';')
+                .next()
+                .unwrap()
+                .split_once('=')
+                .unwrap()
+                .1,
+        );
+        let session = Session::from_request(&returned, Arc::new(*SECRET), Duration::from_secs(60));
+        assert_eq!(Some("42"), session.get("user"));
+    }
+
+    #[test]
+    fn delete_the_cookie_once_cleared() {
+        let cookie = sign(&values(), now() + 60, SECRET);
+        let mut session = Session::from_request(
+            &request_with_cookie(&cookie),
+            Arc::new(*SECRET),
+            Duration::from_secs(60),
+        );
+        session.clear();
+
+        let mut response = ResponseBuilder::new();
+        session.apply_to(&mut response).unwrap();
+        let response = response.body(()).unwrap();
+
+        assert!(response.headers()[SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .starts_with("session=; Max-Age=0;"));
+    }
+
+    #[test]
+    fn refuse_to_send_oversized_cookies() {
+        let mut session = Session::from_request(
+            &Request::get("/").body(()).unwrap(),
+            Arc::new(*SECRET),
+            Duration::from_secs(60),
+        );
+        session.insert("blob", "x".repeat(4000));
+
+        match session.apply_to(&mut ResponseBuilder::new()) {
+            Err(Error::SessionTooLarge(size)) => assert!(size > MAX_COOKIE_SIZE),
+            other => panic!("Expected SessionTooLarge. Got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Changes where a server serves static files from while it's running.
+///
+/// `listen` borrows the server for as long as it runs, so its setters can't
+/// be called any more once it's started. A handle, taken from
+/// `Server::static_config_handle` beforehand, can be kept and used from
+/// another thread instead. Handles can be cloned, and every clone changes the
+/// same server.
+///
+/// A change applies to requests that start after it. Requests that are
+/// already being answered carry on with the directories they started with.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate simple_server;
+///
+/// use simple_server::Server;
+/// use std::thread;
+///
+/// fn main() {
+///     let mut server = Server::new(|request, mut response| {
+///         Ok(response.body("Hello, world!".as_bytes().to_vec())?)
+///     });
+///     server.set_static_directory("content/v1");
+///
+///     let static_config = server.static_config_handle();
+///     thread::spawn(move || {
+///         // ... download the next version of the content ...
+///
+///         static_config.set_directory("content/v2");
+///     });
+///
+///     server.listen("127.0.0.1", "7979");
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticConfigHandle {
+    directories: Arc<RwLock<Arc<Vec<PathBuf>>>>,
+}
+
+impl StaticConfigHandle {
+    pub(crate) fn new(directories: Vec<PathBuf>) -> StaticConfigHandle {
+        StaticConfigHandle {
+            directories: Arc::new(RwLock::new(Arc::new(directories))),
+        }
+    }
+
+    /// Serves static files from `path` alone, from the next request on.
+    pub fn set_directory<P: Into<PathBuf>>(&self, path: P) {
+        self.replace(vec![path.into()]);
+    }
+
+    /// Stops serving static files, from the next request on.
+    pub fn disable(&self) {
+        self.replace(Vec::new());
+    }
+
+    /// The directories to serve static files from, in the order they're
+    /// searched in. Static files are disabled when there are none.
+    pub(crate) fn current(&self) -> Arc<Vec<PathBuf>> {
+        // Nothing can panic while the lock is held, so a poisoned one still
+        // holds a whole list.
+        let directories = self
+            .directories
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&directories)
+    }
+
+    pub(crate) fn replace(&self, directories: Vec<PathBuf>) {
+        let mut current = self
+            .directories
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *current = Arc::new(directories);
+    }
+
+    pub(crate) fn add(&self, path: PathBuf) {
+        let mut directories = self.current().to_vec();
+        directories.push(path);
+        self.replace(directories);
+    }
+}
+
+#[cfg(test)]
+mod static_config_should {
+    use super::*;
+
+    #[test]
+    fn leave_earlier_snapshots_alone() {
+        let handle = StaticConfigHandle::new(vec![PathBuf::from("v1")]);
+        let before = handle.current();
+
+        handle.clone().set_directory("v2");
+
+        assert_eq!(vec![PathBuf::from("v1")], *before);
+        assert_eq!(vec![PathBuf::from("v2")], *handle.current());
+    }
+
+    #[test]
+    fn disable_static_files() {
+        let handle = StaticConfigHandle::new(vec![PathBuf::from("public")]);
+
+        handle.disable();
+
+        assert!(handle.current().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use std::any::Any;
+use std::cell::RefCell;
+
+// The state set up with `Server::set_thread_state` for the connection
+// handling thread this is, if any.
+thread_local!(static STATE: RefCell<Option<Box<dyn Any>>> = RefCell::new(None));
+
+/// Makes a thread's state, type and all, for `Server::set_thread_state`.
+pub type Init = dyn Fn() -> Box<dyn Any> + Send + Sync;
+
+/// Keeps a thread's state in place until it's dropped, when the state is too.
+pub struct Installed(());
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        // The state is dropped after the borrow ends, in case dropping it
+        // looks at the state of its own thread.
+        let state = STATE.with(|state| state.borrow_mut().take());
+        drop(state);
+    }
+}
+
+/// Sets up the current thread's state with `init`.
+pub fn install(init: &Init) -> Installed {
+    let state = init();
+    STATE.with(|current| *current.borrow_mut() = Some(state));
+    Installed(())
+}
+
+/// Calls `f` with the state that `Server::set_thread_state` set up for the
+/// current thread, returning what it returns.
+///
+/// Each thread that handles connections gets its own state, so a handler can
+/// use it for things that can't be shared between threads, like a database
+/// connection, without locking. This returns `None` on a thread without any
+/// state, when the state isn't a `T`, or when it's called from inside `f`,
+/// as the state is already borrowed then.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{with_thread_state, Server};
+///
+/// fn main() {
+///     let mut server = Server::new(|request, mut response| {
+///         let served = with_thread_state(|served: &mut u64| {
+///             *served += 1;
+///             *served
+///         });
+///
+///         let body = format!("This thread has answered {} requests", served.unwrap_or(0));
+///         Ok(response.body(body.into_bytes())?)
+///     });
+///
+///     server.set_thread_state(|| 0_u64);
+/// }
+/// ```
+pub fn with_thread_state<T: 'static, R, F: FnOnce(&mut T) -> R>(f: F) -> Option<R> {
+    STATE.with(|state| {
+        let mut state = state.try_borrow_mut().ok()?;
+        let state = state.as_mut()?.downcast_mut::<T>()?;
+        Some(f(state))
+    })
+}
+
+#[cfg(test)]
+mod thread_state_should {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    fn counter() -> Box<Init> {
+        Box::new(|| Box::new(0_u32))
+    }
+
+    #[test]
+    fn keep_state_between_calls_on_a_thread() {
+        thread::spawn(|| {
+            let _installed = install(&*counter());
+
+            for expected in 1..4 {
+                let count = with_thread_state(|count: &mut u32| {
+                    *count += 1;
+                    *count
+                });
+                assert_eq!(Some(expected), count);
+            }
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn give_nothing_without_state_of_the_right_type() {
+        thread::spawn(|| {
+            assert_eq!(None, with_thread_state(|_: &mut u32| ()));
+
+            let _installed = install(&*counter());
+            assert_eq!(None, with_thread_state(|_: &mut String| ()));
+            assert_eq!(
+                Some(None),
+                with_thread_state(|_: &mut u32| with_thread_state(|_: &mut u32| ()))
+            );
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn drop_the_state_with_the_guard() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Connection;
+
+        impl Drop for Connection {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        thread::spawn(|| {
+            let init: Box<Init> = Box::new(|| Box::new(Connection));
+
+            let installed = install(&*init);
+            assert_eq!(0, DROPPED.load(Ordering::SeqCst));
+
+            drop(installed);
+            assert_eq!(1, DROPPED.load(Ordering::SeqCst));
+            assert_eq!(None, with_thread_state(|_: &mut Connection| ()));
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use http::StatusCode;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+// A connection is handled start to finish on one worker thread, so what's
+// learned about the request being answered is kept per thread, rather than
+// passed through every way a response can be written.
+thread_local!(static CURRENT: Cell<Exchange> = Cell::new(Exchange::default()));
+
+/// What was learned while answering a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Exchange {
+    /// How long the handler ran for, if it was called.
+    pub handler: Duration,
+    /// The status of the response, once its head has been written.
+    pub status: Option<StatusCode>,
+    /// How many bytes of responses were written.
+    pub sent: u64,
+}
+
+/// Forgets about the last request answered on this thread.
+pub fn start() {
+    CURRENT.with(|current| current.set(Exchange::default()));
+}
+
+/// Runs the handler, `f`, keeping track of how long it took.
+pub fn handler<F: FnOnce() -> R, R>(f: F) -> R {
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed();
+
+    CURRENT.with(|current| {
+        let mut exchange = current.get();
+        exchange.handler += elapsed;
+        current.set(exchange);
+    });
+    result
+}
+
+/// Notes the status of the response being sent.
+pub fn status(status: StatusCode) {
+    CURRENT.with(|current| {
+        let mut exchange = current.get();
+        exchange.status = Some(status);
+        current.set(exchange);
+    });
+}
+
+/// Notes that `bytes` more bytes of the response were written.
+pub fn sent(bytes: u64) {
+    CURRENT.with(|current| {
+        let mut exchange = current.get();
+        exchange.sent += bytes;
+        current.set(exchange);
+    });
+}
+
+/// What was learned since `start`.
+pub fn finish() -> Exchange {
+    CURRENT.with(|current| current.get())
+}
+
+#[cfg(test)]
+mod timing_should {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn keep_track_of_the_handler_and_status() {
+        start();
+        handler(|| thread::sleep(Duration::from_millis(5)));
+        status(StatusCode::CREATED);
+        sent(100);
+        sent(20);
+
+        let exchange = finish();
+        assert!(exchange.handler >= Duration::from_millis(5));
+        assert_eq!(Some(StatusCode::CREATED), exchange.status);
+        assert_eq!(120, exchange.sent);
+
+        start();
+        assert_eq!(Exchange::default(), finish());
+    }
+}
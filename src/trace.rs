@@ -0,0 +1,340 @@
+//! Spans and structured events for the `tracing` feature.
+//!
+//! Without the feature, these do nothing, and the server logs through the
+//! `log` crate as it always has.
+
+use error::Error;
+use http::{Method, Request, StatusCode};
+use progress::RequestHeadInfo;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::field;
+
+/// A span covering all the work done for one connection or one request.
+pub struct Scope {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    started: Instant,
+}
+
+impl Scope {
+    /// Runs `f` inside the span, recording how long it took.
+    #[cfg(feature = "tracing")]
+    pub fn in_scope<F: FnOnce() -> R, R>(self, f: F) -> R {
+        let result = self.span.in_scope(f);
+        self.span
+            .record("duration_ms", self.started.elapsed().as_millis() as u64);
+        self.span.in_scope(|| debug!("finished"));
+        result
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn in_scope<F: FnOnce() -> R, R>(self, f: F) -> R {
+        f()
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub fn connection(peer: Option<SocketAddr>) -> Scope {
+    Scope {
+        span: info_span!("connection", peer = ?peer, duration_ms = field::Empty),
+        started: Instant::now(),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn connection(_peer: Option<SocketAddr>) -> Scope {
+    Scope {}
+}
+
+/// A span for the `number`th request on a connection, counting from 1.
+#[cfg(feature = "tracing")]
+pub fn request<B>(request: &Request<B>, number: usize) -> Scope {
+    Scope {
+        span: info_span!(
+            "request",
+            method = %request.method(),
+            path = %request.uri().path(),
+            number = number,
+            status = field::Empty,
+            duration_ms = field::Empty,
+        ),
+        started: Instant::now(),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn request<B>(_request: &Request<B>, _number: usize) -> Scope {
+    Scope {}
+}
+
+/// Notes the status of the response being sent on the current request span,
+/// if there is one.
+#[cfg(feature = "tracing")]
+pub fn status(status: StatusCode) {
+    tracing::Span::current().record("status", status.as_u16());
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn status(_status: StatusCode) {}
+
+#[cfg(feature = "tracing")]
+pub fn malformed_request(error: &Error) {
+    info!(error = ?error, "rejecting malformed request");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn malformed_request(error: &Error) {
+    info!("Rejecting malformed request: {:?}", error);
+}
+
+#[cfg(feature = "tracing")]
+pub fn handler_failed(error: &Error) {
+    error!(error = ?error, "handler returned an error");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn handler_failed(error: &Error) {
+    error!("Handler returned an error: {:?}", error);
+}
+
+#[cfg(feature = "tracing")]
+pub fn connection_failed(error: &Error) {
+    warn!(error = ?error, "error handling connection");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn connection_failed(error: &Error) {
+    warn!("Error handling connection: {:?}", error);
+}
+
+/// A panic while handling a connection, and what the thread was doing.
+pub struct Panic<'a> {
+    pub thread: &'a str,
+    pub message: &'a str,
+    pub peer: Option<SocketAddr>,
+    pub request: Option<&'a str>,
+}
+
+#[cfg(feature = "tracing")]
+pub fn panicked(panic: &Panic) {
+    error!(
+        thread = %panic.thread,
+        message = %panic.message,
+        peer = ?panic.peer,
+        request = panic.request,
+        "panicked handling connection"
+    );
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn panicked(panic: &Panic) {
+    let peer = panic
+        .peer
+        .map(|peer| peer.to_string())
+        .unwrap_or_else(|| String::from("unknown peer"));
+
+    error!(
+        "Thread {} panicked handling {} from {}: {}",
+        panic.thread,
+        panic.request.unwrap_or("a connection"),
+        peer,
+        panic.message
+    );
+}
+
+/// A request that took longer than it should have, and where the time went.
+pub struct SlowRequest<'a> {
+    pub method: &'a Method,
+    pub path: &'a str,
+    pub status: Option<StatusCode>,
+    pub peer: Option<SocketAddr>,
+    pub read: Duration,
+    pub handler: Duration,
+    pub write: Duration,
+}
+
+#[cfg(feature = "tracing")]
+pub fn slow_request(slow: &SlowRequest) {
+    warn!(
+        method = %slow.method,
+        path = %slow.path,
+        status = slow.status.map(|status| status.as_u16()),
+        peer = ?slow.peer,
+        read_ms = slow.read.as_millis() as u64,
+        handler_ms = slow.handler.as_millis() as u64,
+        write_ms = slow.write.as_millis() as u64,
+        "slow request"
+    );
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn slow_request(slow: &SlowRequest) {
+    let status = slow
+        .status
+        .map(|status| status.as_str().to_string())
+        .unwrap_or_else(|| String::from("-"));
+    let peer = slow
+        .peer
+        .map(|peer| peer.to_string())
+        .unwrap_or_else(|| String::from("unknown peer"));
+
+    warn!(
+        "Slow request: {} {} {} from {} took {}ms (read {}ms, handler {}ms, write {}ms)",
+        slow.method,
+        slow.path,
+        status,
+        peer,
+        (slow.read + slow.handler + slow.write).as_millis(),
+        slow.read.as_millis(),
+        slow.handler.as_millis(),
+        slow.write.as_millis(),
+    );
+}
+
+#[cfg(feature = "tracing")]
+pub fn progress_hook_panicked(info: &RequestHeadInfo) {
+    error!(
+        method = %info.method(),
+        path = %info.path(),
+        peer = ?info.peer(),
+        "body progress hook panicked"
+    );
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn progress_hook_panicked(info: &RequestHeadInfo) {
+    let peer = info
+        .peer()
+        .map(|peer| peer.to_string())
+        .unwrap_or_else(|| String::from("unknown peer"));
+
+    error!(
+        "Body progress hook panicked for {} {} from {}",
+        info.method(),
+        info.path(),
+        peer
+    );
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod trace_should {
+    use super::*;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::subscriber::{self, Subscriber};
+    use tracing::{Event, Metadata};
+    use tracing_core::span::Current;
+
+    // Remembers the fields recorded on every span, as `span.field=value`.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<&'static Metadata<'static>>>>,
+        entered: Arc<Mutex<Vec<Id>>>,
+        fields: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Fields<'a>(&'a Recorder, &'static str);
+
+    impl<'a> Visit for Fields<'a> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .fields
+                .lock()
+                .unwrap()
+                .push(format!("{}.{}={:?}", self.1, field.name(), value));
+        }
+    }
+
+    impl Recorder {
+        fn metadata(&self, span: &Id) -> &'static Metadata<'static> {
+            self.spans.lock().unwrap()[span.into_u64() as usize - 1]
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let id = {
+                let mut spans = self.spans.lock().unwrap();
+                spans.push(span.metadata());
+                Id::from_u64(spans.len() as u64)
+            };
+            span.record(&mut Fields(self, span.metadata().name()));
+            id
+        }
+
+        fn record(&self, span: &Id, values: &Record) {
+            values.record(&mut Fields(self, self.metadata(span).name()));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event) {}
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.clone());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            match self.entered.lock().unwrap().last() {
+                Some(span) => Current::new(span.clone(), self.metadata(span)),
+                None => Current::none(),
+            }
+        }
+    }
+
+    impl Recorder {
+        fn field(&self, span: &str, name: &str) -> Option<String> {
+            let prefix = format!("{}.{}=", span, name);
+            self.fields
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|field| field.starts_with(&prefix))
+                .map(|field| field[prefix.len()..].to_string())
+        }
+    }
+
+    #[test]
+    fn record_request_fields() {
+        let recorder = Recorder::default();
+        let upload = Request::post("/upload?x=1").body(()).unwrap();
+
+        subscriber::with_default(recorder.clone(), || {
+            connection("127.0.0.1:7979".parse().ok()).in_scope(|| {
+                request(&upload, 3).in_scope(|| status(StatusCode::CREATED));
+            });
+        });
+
+        assert_eq!(
+            Some("Some(127.0.0.1:7979)".to_string()),
+            recorder.field("connection", "peer")
+        );
+        assert_eq!(
+            Some("POST".to_string()),
+            recorder.field("request", "method")
+        );
+        assert_eq!(
+            Some("/upload".to_string()),
+            recorder.field("request", "path")
+        );
+        assert_eq!(Some("3".to_string()), recorder.field("request", "number"));
+        assert_eq!(Some("201".to_string()), recorder.field("request", "status"));
+        assert!(recorder.field("request", "duration_ms").is_some());
+    }
+}
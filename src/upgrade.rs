@@ -0,0 +1,77 @@
+use std::fmt;
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+type TakeOver = Box<dyn FnOnce(TcpStream, Vec<u8>) + Send>;
+
+/// Hands a connection over to the handler once the response to its request
+/// has been sent, for protocols that start out with an HTTP handshake.
+///
+/// A response carrying one of these in its extensions has its status line
+/// and headers written as they are: no `Content-Length` or `Connection` is
+/// added, and the body is never sent. The callback is then called with the
+/// connection's stream and any bytes the client sent past the end of the
+/// request, which belong to the new protocol.
+///
+/// From then on, the connection is the callback's. The server forgets about
+/// it, so no timeouts, idle reaping or keep-alive apply, and the stream is
+/// back to blocking reads without a timeout. It's closed once the callback
+/// drops it, whether that's on return or later on, from some other thread.
+///
+/// The callback runs on the worker that answered the request, so one that
+/// keeps the connection going for long should move the stream to a thread
+/// of its own.
+///
+/// # Examples
+///
+/// ```
+/// extern crate simple_server;
+///
+/// use simple_server::{Server, Upgrade};
+/// use std::io::Write;
+/// use std::thread;
+///
+/// fn main() {
+///     let server = Server::new(|request, mut response| {
+///         response.status(101);
+///         response.header("connection", "upgrade");
+///         response.header("upgrade", "echo");
+///         response.extension(Upgrade::new(|mut stream, buffered| {
+///             thread::spawn(move || {
+///                 let _ = stream.write_all(&buffered);
+///                 // ... the rest of the protocol ...
+///             });
+///         }));
+///
+///         Ok(response.body(Vec::new())?)
+///     });
+/// }
+/// ```
+// The mutex is only there so that the upgrade is `Sync`, as extensions have
+// to be; the callback is only ever taken out of it once.
+pub struct Upgrade(Mutex<TakeOver>);
+
+impl Upgrade {
+    /// Makes an upgrade that calls `take_over` with the connection.
+    pub fn new<F>(take_over: F) -> Upgrade
+    where
+        F: FnOnce(TcpStream, Vec<u8>) + Send + 'static,
+    {
+        Upgrade(Mutex::new(Box::new(take_over)))
+    }
+
+    /// Gives the connection to the callback.
+    pub(crate) fn take_over(self, stream: TcpStream, buffered: Vec<u8>) {
+        let take_over = match self.0.into_inner() {
+            Ok(take_over) => take_over,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        take_over(stream, buffered);
+    }
+}
+
+impl fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Upgrade").finish()
+    }
+}
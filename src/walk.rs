@@ -0,0 +1,63 @@
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+
+/// Calls `visit` with the path and metadata of every file under `dir`, however
+/// deeply nested. Symlinks are followed, like serving a file would, and
+/// anything that can't be read is skipped.
+pub fn files<F: FnMut(PathBuf, &Metadata)>(dir: &Path, visit: &mut F) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            trace!("Skipping {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                trace!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        if metadata.is_dir() {
+            files(&path, visit);
+        } else if metadata.is_file() {
+            visit(path, &metadata);
+        }
+    }
+}
+
+#[cfg(test)]
+mod walk_should {
+    use super::*;
+
+    #[test]
+    fn visit_files_in_nested_directories() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("simple-server-{}-walk-files", ::std::process::id()));
+        fs::create_dir_all(dir.join("css/print")).unwrap();
+        fs::write(dir.join("index.html"), "<p>hi</p>").unwrap();
+        fs::write(dir.join("css/print/site.css"), "p {}").unwrap();
+
+        let mut visited = Vec::new();
+        files(&dir, &mut |path, metadata| {
+            visited.push((path, metadata.len()))
+        });
+        files(Path::new("/no/such/directory"), &mut |path, _| {
+            panic!("Visited {}", path.display())
+        });
+
+        visited.sort();
+        assert_eq!(
+            vec![
+                (dir.join("css/print/site.css"), 4),
+                (dir.join("index.html"), 9),
+            ],
+            visited
+        );
+    }
+}
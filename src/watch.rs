@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use walk;
+
+/// The modification times of every file under some directories, at one
+/// point in time.
+pub type Snapshot = HashMap<PathBuf, SystemTime>;
+
+/// How a file differs between two snapshots.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Removed,
+}
+
+/// Records the modification time of every file under `roots`. Anything that
+/// can't be read is left out.
+pub fn scan(roots: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for root in roots {
+        walk::files(root, &mut |path, metadata| {
+            if let Ok(modified) = metadata.modified() {
+                snapshot.insert(path, modified);
+            }
+        });
+    }
+    snapshot
+}
+
+/// Lists the files that were added, modified or removed between `before`
+/// and `after`, sorted by path.
+pub fn changes(before: &Snapshot, after: &Snapshot) -> Vec<(Change, PathBuf)> {
+    let mut changes: Vec<(Change, PathBuf)> = after
+        .iter()
+        .filter_map(|(path, modified)| match before.get(path) {
+            None => Some((Change::Added, path.clone())),
+            Some(previous) if previous != modified => Some((Change::Modified, path.clone())),
+            Some(_) => None,
+        })
+        .chain(
+            before
+                .keys()
+                .filter(|path| !after.contains_key(*path))
+                .map(|path| (Change::Removed, path.clone())),
+        )
+        .collect();
+
+    changes.sort_by(|a, b| a.1.cmp(&b.1));
+    changes
+}
+
+#[cfg(test)]
+mod watch_should {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn snapshot(files: &[(&str, u64)]) -> Snapshot {
+        files
+            .iter()
+            .map(|&(path, secs)| (PathBuf::from(path), UNIX_EPOCH + Duration::from_secs(secs)))
+            .collect()
+    }
+
+    #[test]
+    fn list_changed_files() {
+        let before = snapshot(&[("a.css", 1), ("b.js", 1), ("c.html", 1)]);
+        let after = snapshot(&[("a.css", 1), ("b.js", 2), ("d.png", 2)]);
+
+        assert_eq!(
+            vec![
+                (Change::Modified, PathBuf::from("b.js")),
+                (Change::Removed, PathBuf::from("c.html")),
+                (Change::Added, PathBuf::from("d.png")),
+            ],
+            changes(&before, &after)
+        );
+        assert!(changes(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn scan_nested_directories() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("simple-server-{}-watch-scan", ::std::process::id()));
+        fs::create_dir_all(dir.join("css")).unwrap();
+        fs::write(dir.join("index.html"), "<p>hi</p>").unwrap();
+        fs::write(dir.join("css/site.css"), "p {}").unwrap();
+
+        let snapshot = scan(&[dir.clone(), PathBuf::from("/no/such/directory")]);
+
+        assert_eq!(2, snapshot.len());
+        assert!(snapshot.contains_key(&dir.join("css/site.css")));
+    }
+}
@@ -0,0 +1,80 @@
+// Counting allocations means replacing the global allocator, which applies to
+// the whole test binary, so these tests get a binary of their own.
+
+extern crate simple_server;
+
+use simple_server::Server;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+// Counts allocations made by the current thread, so that the client's
+// allocations don't throw the server's numbers off.
+struct CountingAllocator;
+
+thread_local!(static ALLOCATIONS: Cell<usize> = const { Cell::new(0) });
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Counts the allocations the server makes on this thread while it answers
+// `requests` small requests sent down one connection.
+fn allocations(server: &Server, listener: &TcpListener, requests: usize) -> usize {
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut raw = Vec::new();
+        for _ in 1..requests {
+            raw.extend_from_slice(b"GET /index.html HTTP/1.1\r\nHost: x\r\n\r\n");
+        }
+        raw.extend_from_slice(b"GET /index.html HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+        stream.write_all(&raw).unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+    });
+
+    let before = ALLOCATIONS.with(Cell::get);
+    server.accept_one(listener).unwrap();
+    let count = ALLOCATIONS.with(Cell::get) - before;
+
+    client.join().unwrap();
+    count
+}
+
+#[test]
+fn test_requests_reuse_their_buffers() {
+    let mut server = Server::new(|_request, mut response| Ok(response.body(b"Hello!".to_vec())?));
+    server.dont_serve_static_files();
+    server.set_max_requests_per_connection(usize::MAX);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    allocations(&server, &listener, 1);
+    let few = allocations(&server, &listener, 100);
+    let many = allocations(&server, &listener, 1100);
+
+    // Answering a request takes ten allocations, mostly in building the
+    // `http::Request` and `http::Response`. Without the buffers being reused,
+    // every request also needs one to be read into and one for the head of
+    // its response.
+    let per_request = (many - few) as f64 / 1000.0;
+    assert!(
+        per_request < 11.0,
+        "{} allocations per request",
+        per_request
+    );
+}
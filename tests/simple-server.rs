@@ -1,6 +1,58 @@
+extern crate http;
 extern crate simple_server;
 
-use simple_server::Server;
+use simple_server::{
+    with_thread_state, Body, ConcurrencyModel, Deadline, Interim, Method, Request, Response,
+    ResponseBuilder, ResponseResult, Server, StatusCode,
+};
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn spawn_server(server: Server) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || server.listen_on_socket(listener));
+    addr
+}
+
+fn send(addr: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+fn static_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("simple-server-{}-{}", process::id(), name));
+    for &(path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn static_server(dir: PathBuf) -> Server {
+    let mut server = Server::new(|_request, mut response| {
+        response.status(404);
+        Ok(response.body("handler".as_bytes().to_vec())?)
+    });
+    server.set_static_directory(dir);
+    server
+}
 
 #[test]
 fn test_server_new() {
@@ -17,3 +69,1586 @@ fn test_error_fallback() {
         Ok(response.body("".as_bytes().to_vec())?)
     });
 }
+
+#[test]
+fn test_http09_request_is_rejected() {
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        Ok(response.body("Hello Rust!".as_bytes().to_vec())?)
+    }));
+
+    let response = send(addr, b"GET /\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("HTTP/0.9"));
+}
+
+#[test]
+fn test_malformed_request_is_rejected() {
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        Ok(response.body("Hello Rust!".as_bytes().to_vec())?)
+    }));
+
+    let response = send(addr, b"GET / HTTP/1.1Host: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
+
+#[test]
+fn test_request_line_that_isnt_utf8_is_rejected() {
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        Ok(response.body("Hello Rust!".as_bytes().to_vec())?)
+    }));
+
+    let response = send(addr, b"GET /caf\xff HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_invalid_request_target_is_rejected() {
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        Ok(response.body("Hello Rust!".as_bytes().to_vec())?)
+    }));
+
+    let response = send(addr, b"GET /<script> HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("invalid request target"));
+}
+
+#[test]
+fn test_ambiguous_framing_is_rejected() {
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        Ok(response.body("Hello Rust!".as_bytes().to_vec())?)
+    }));
+
+    let response = send(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("\r\nconnection: close\r\n"));
+    assert!(!response.contains("Hello Rust!"));
+}
+
+#[test]
+fn test_chunked_bodies_are_decoded() {
+    let addr = spawn_server(Server::new(|request, mut response| {
+        Ok(response.body(request.body().clone())?)
+    }));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    for part in &[
+        &b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n"[..],
+        b"5\r\nhel",
+        b"lo\r\n1;ext\r\n \r\n",
+        b"5\r\nworld\r\n0\r\n\r\n",
+        b"GET /next HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    ] {
+        stream.write_all(part).unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let responses: Vec<&str> = response.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+    assert_eq!(2, responses.len());
+    assert!(responses[0].contains("connection: keep-alive\r\n"));
+    assert!(responses[0].ends_with("\r\n\r\nhello world"));
+    assert!(responses[1].ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn test_folded_headers_are_rejected() {
+    let addr = spawn_server(Server::new(|request, mut response| {
+        let value = request.headers()["x-long-header"].as_bytes().to_vec();
+        Ok(response.body(value)?)
+    }));
+
+    let response = send(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\nX-Long-Header: first\r\n second\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("obs-fold"));
+
+    let response = send(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\nX-Long-Header: first second\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nfirst second"));
+}
+
+#[test]
+fn test_parse_failures_are_shown_to_the_hook() {
+    use std::sync::Mutex;
+
+    let seen = Arc::new(Mutex::new(vec![]));
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("Hello Rust!".as_bytes().to_vec())?));
+    let hook_seen = seen.clone();
+    server.on_parse_failure(move |head, _error| hook_seen.lock().unwrap().push(head.to_vec()));
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nNot a header\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    assert_eq!(
+        vec![b"GET / HTTP/1.1\r\nNot a header\r\n\r\n".to_vec()],
+        *seen.lock().unwrap()
+    );
+}
+
+#[test]
+fn test_keep_alive() {
+    let addr = spawn_server(Server::new(|request, mut response| {
+        Ok(response.body(request.uri().path().as_bytes().to_vec())?)
+    }));
+
+    let response = send(
+        addr,
+        b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n",
+    );
+    assert_eq!(2, response.matches("HTTP/1.1 200 OK\r\n").count());
+    assert_eq!(2, response.matches("connection: keep-alive\r\n").count());
+    assert!(response.ends_with("\r\n\r\n/b"));
+
+    let response = send(
+        addr,
+        b"GET /a HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+    );
+    assert_eq!(1, response.matches("HTTP/1.1 200 OK\r\n").count());
+    assert!(response.contains("connection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\n/a"));
+}
+
+#[test]
+fn test_head_responses_have_no_body() {
+    let dir = static_dir("head", &[("a.txt", "hello world")]);
+    let addr = spawn_server(static_server(dir));
+
+    let response = send(
+        addr,
+        b"HEAD /a.txt HTTP/1.1\r\nHost: x\r\n\r\n\
+          GET /a.txt HTTP/1.1\r\nHost: x\r\n\r\n\
+          HEAD /missing HTTP/1.1\r\nHost: x\r\n\r\n\
+          GET /missing HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    let responses: Vec<&str> = response.split("HTTP/1.1 ").skip(1).collect();
+    assert_eq!(4, responses.len());
+
+    assert!(responses[0].starts_with("200 OK\r\n"));
+    assert!(responses[0].contains("content-length: 11\r\n"));
+    assert!(responses[0].ends_with("\r\n\r\n"));
+    assert!(responses[1].ends_with("\r\n\r\nhello world"));
+
+    assert!(responses[2].starts_with("404 Not Found\r\n"));
+    assert!(responses[2].contains("content-length: 7\r\n"));
+    assert!(responses[2].ends_with("\r\n\r\n"));
+    assert!(responses[3].ends_with("\r\n\r\nhandler"));
+}
+
+#[test]
+fn test_max_requests_per_connection() {
+    let mut server = Server::new(|request, mut response| {
+        Ok(response.body(request.uri().path().as_bytes().to_vec())?)
+    });
+    server.set_max_requests_per_connection(2);
+    let addr = spawn_server(server);
+
+    let response = send(
+        addr,
+        b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n\
+          GET /b HTTP/1.1\r\nHost: x\r\n\r\n\
+          GET /c HTTP/1.1\r\nHost: x\r\n\r\n",
+    );
+    let responses: Vec<&str> = response.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+    assert_eq!(2, responses.len());
+    assert!(responses[0].contains("connection: keep-alive\r\n"));
+    assert!(responses[1].contains("connection: close\r\n"));
+    assert!(responses[1].ends_with("\r\n\r\n/b"));
+}
+
+#[test]
+fn test_long_request_lines_are_rejected() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("Hello Rust!".as_bytes().to_vec())?));
+    server.set_max_request_line_length(64);
+    let addr = spawn_server(server);
+
+    let request = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(100));
+    let response = send(addr, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+
+    let request = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(40));
+    let response = send(addr, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_large_request_bodies_are_rejected() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("Hello Rust!".as_bytes().to_vec())?));
+    server.set_max_request_size(1024);
+    let addr = spawn_server(server);
+
+    let response = send(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 18446744073709551615\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 512\r\n\r\n{}",
+        "a".repeat(512)
+    );
+    let response = send(addr, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_large_request_heads_are_rejected() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("Hello Rust!".as_bytes().to_vec())?));
+    server.set_max_request_size(1024);
+    let addr = spawn_server(server);
+
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: x\r\nX-Padding: {}\r\n\r\n",
+        "a".repeat(2048)
+    );
+    let response = send(addr, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+}
+
+#[test]
+fn test_upgrade() {
+    use simple_server::Upgrade;
+
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        response.status(101);
+        response.header("connection", "upgrade");
+        response.header("upgrade", "echo");
+        response.extension(Upgrade::new(|mut stream, buffered| {
+            stream.write_all(&buffered).unwrap();
+            io::copy(&mut stream.try_clone().unwrap(), &mut stream).unwrap();
+        }));
+        Ok(response.body(b"never sent".to_vec())?)
+    }));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nUpgrade: echo\r\n\r\nhello ")
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"world").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, rest) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(head.contains("upgrade: echo"));
+    assert!(!head.contains("content-length"));
+    assert_eq!("hello world", rest);
+}
+
+#[test]
+fn test_expectations() {
+    let addr = spawn_server(Server::new(|request, mut response| {
+        Ok(response.body(request.body().clone())?)
+    }));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+        )
+        .unwrap();
+    let mut interim = [0; 25];
+    stream.read_exact(&mut interim).unwrap();
+    assert_eq!(&b"HTTP/1.1 100 Continue\r\n\r\n"[..], &interim[..]);
+    stream.write_all(b"hello").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    let response = send(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nExpect: something-else\r\nContent-Length: 5\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    assert!(response.contains("Expectation failed!"));
+
+    let response = send(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!response.contains("100 Continue"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+}
+
+#[test]
+fn test_handler_ranges() {
+    let mut server = Server::new(|request, mut response| match request.uri().path() {
+        "/partial" => Ok(response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header("Content-Range", "bytes 0-3/100")
+            .body(b"0123".to_vec())?),
+        _ => Ok(response.body(b"0123456789".to_vec())?),
+    });
+    server.set_handler_ranges(true);
+    let addr = spawn_server(server);
+
+    let response = send(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\nRange: bytes=2-5\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.contains("content-range: bytes 2-5/10\r\n"));
+    assert!(response.contains("content-length: 4\r\n"));
+    assert!(response.ends_with("\r\n\r\n2345"));
+
+    let response = send(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\nRange: bytes=10-\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert!(response.contains("content-range: bytes */10\r\n"));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("accept-ranges: bytes\r\n"));
+    assert!(response.ends_with("\r\n\r\n0123456789"));
+
+    let response = send(
+        addr,
+        b"GET /partial HTTP/1.1\r\nHost: x\r\nRange: bytes=1-2\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.contains("content-range: bytes 0-3/100\r\n"));
+    assert!(response.ends_with("\r\n\r\n0123"));
+}
+
+#[test]
+fn test_deadline() {
+    let handler = |request: Request<Vec<u8>>, mut response: ResponseBuilder| {
+        let remaining = match request.extensions().get::<Deadline>() {
+            Some(deadline) => format!("{}", deadline.remaining().as_secs()),
+            None => String::from("none"),
+        };
+        Ok(response.body(remaining.into_bytes())?)
+    };
+
+    let addr = spawn_server(Server::with_timeout(Duration::from_secs(60), handler));
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\n59"));
+
+    let addr = spawn_server(Server::new(handler));
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nnone"));
+}
+
+#[test]
+fn test_overload_is_refused_with_503() {
+    let mut server = Server::new(|request, mut response| {
+        thread::sleep(Duration::from_millis(200));
+        Ok(response.body(request.uri().path().as_bytes().to_vec())?)
+    });
+    server.set_queue_depth(1);
+    let server = Arc::new(server);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listening = server.clone();
+    thread::spawn(move || listening.listen_on_socket(listener));
+
+    let clients = thread::available_parallelism().map_or(1, |n| n.get()) * 4 + 8;
+    let responses: Vec<_> = (0..clients)
+        .map(|i| {
+            thread::spawn(move || {
+                let path = format!("/{}", i);
+                let request = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+                (path, send(addr, request.as_bytes()))
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|client| client.join().unwrap())
+        .collect();
+
+    let mut refused = 0;
+    for (path, response) in &responses {
+        if response.starts_with("HTTP/1.1 503 Service Unavailable\r\n") {
+            assert!(response.contains("retry-after: 1\r\n"));
+            refused += 1;
+        } else {
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+            assert!(response.ends_with(&format!("\r\n\r\n{}", path)));
+        }
+    }
+    assert!(refused > 0);
+    assert!(refused < clients);
+    assert_eq!(refused, server.metrics().overloaded_connections());
+}
+
+#[test]
+fn test_pool_stats() {
+    let mut server = Server::new(|request, mut response| {
+        match request.uri().path() {
+            "/panic" => panic!("handler panicked on purpose"),
+            _ => thread::sleep(Duration::from_millis(300)),
+        }
+        Ok(response.body(Vec::new())?)
+    });
+    server.set_concurrency_model(ConcurrencyModel::Pool { size: 2 });
+    let server = Arc::new(server);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listening = server.clone();
+    thread::spawn(move || listening.listen_on_socket(listener));
+
+    let wait_for_busy = |busy| {
+        let started = Instant::now();
+        while server.pool_stats().busy_workers != busy {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "{:?}",
+                server.pool_stats()
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    };
+
+    let clients: Vec<_> = (0..2)
+        .map(|_| thread::spawn(move || send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")))
+        .collect();
+    wait_for_busy(2);
+    for client in clients {
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+    wait_for_busy(0);
+
+    send(addr, b"GET /panic HTTP/1.1\r\nHost: x\r\n\r\n");
+    wait_for_busy(0);
+
+    let stats = server.pool_stats();
+    assert_eq!(2, stats.peak_busy_workers);
+    assert_eq!(0, stats.queued_connections);
+}
+
+#[test]
+fn test_worker_threads_are_named() {
+    let addr = spawn_server(Server::new(|_request, mut response| {
+        let name = thread::current().name().unwrap_or("").to_string();
+        Ok(response.body(name.into_bytes())?)
+    }));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\n\r\nsimple-server-worker-"));
+}
+
+#[test]
+fn test_thread_per_connection() {
+    let handler = |request: Request<Vec<u8>>, mut response: ResponseBuilder| {
+        match request.uri().path() {
+            "/panic" => panic!("handler panicked on purpose"),
+            "/slow" => thread::sleep(Duration::from_millis(300)),
+            _ => {}
+        }
+        let name = thread::current().name().unwrap_or("").to_string();
+        Ok(response.body(name.into_bytes())?)
+    };
+
+    let mut server = Server::new(handler);
+    server.set_concurrency_model(ConcurrencyModel::ThreadPerConnection { max_threads: None });
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /panic HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.is_empty());
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\n\r\nsimple-server-connection-"));
+
+    // Slow requests don't wait for each other.
+    let started = Instant::now();
+    let clients: Vec<_> = (0..4)
+        .map(|_| thread::spawn(move || send(addr, b"GET /slow HTTP/1.1\r\nHost: x\r\n\r\n")))
+        .collect();
+    for client in clients {
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+    assert!(started.elapsed() < Duration::from_millis(1000));
+
+    let mut server = Server::new(handler);
+    server.set_concurrency_model(ConcurrencyModel::ThreadPerConnection {
+        max_threads: Some(1),
+    });
+    let addr = spawn_server(server);
+
+    let slow = thread::spawn(move || send(addr, b"GET /slow HTTP/1.1\r\nHost: x\r\n\r\n"));
+    thread::sleep(Duration::from_millis(100));
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(slow.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_handler_bodies() {
+    let dir = static_dir("handler-bodies", &[("exports/report.csv", "a,b\n1,2\n")]);
+    let mut server = Server::new(move |request, mut response| {
+        let body = match request.uri().path() {
+            "/static" => Body::Static(b"static bytes"),
+            "/report" => Body::File(dir.join("exports/report.csv")),
+            "/missing" => Body::File(dir.join("exports/missing.csv")),
+            "/directory" => Body::File(dir.join("exports")),
+            _ => Body::from(String::from("owned bytes")),
+        };
+        Ok(response.header("Content-Type", "text/csv").body(body)?)
+    });
+    server.dont_serve_static_files();
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /static HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nstatic bytes"));
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nowned bytes"));
+
+    let response = send(addr, b"GET /report HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("content-type: text/csv\r\n"));
+    assert!(response.contains("content-length: 8\r\n"));
+    assert!(response.ends_with("\r\n\r\na,b\n1,2\n"));
+
+    let response = send(
+        addr,
+        b"GET /report HTTP/1.1\r\nHost: x\r\nRange: bytes=4-6\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.ends_with("\r\n\r\n1,2"));
+
+    for path in &["/missing", "/directory"] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+        let response = send(addr, request.as_bytes());
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn test_static_directory_can_be_swapped_while_listening() {
+    let v1 = static_dir("swap-v1", &[("index.html", "<p>v1</p>")]);
+    let v2 = static_dir("swap-v2", &[("index.html", "<p>v2</p>")]);
+    let server = static_server(v1);
+    let static_config = server.static_config_handle();
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("<p>v1</p>"));
+
+    static_config.clone().set_directory(v2);
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("<p>v2</p>"));
+
+    static_config.disable();
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nhandler"));
+}
+
+#[test]
+fn test_responders() {
+    let mut server = Server::new_with_responder(|request| -> ResponseResult {
+        match request.uri().path() {
+            "/text" => Ok(Response::new(b"bytes".to_vec())),
+            "/fail" => Err(io::Error::other("failed").into()),
+            _ => Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new())?),
+        }
+    });
+    server.dont_serve_static_files();
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /text HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nbytes"));
+    let response = send(addr, b"GET /fail HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    let response = send(addr, b"GET /other HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let addr = spawn_server(Server::new_with_responder(|request| {
+        if request.uri().path() == "/" {
+            (StatusCode::OK, String::from("hello"))
+        } else {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no {}", request.uri().path()),
+            )
+        }
+    }));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("content-type: text/plain; charset=utf-8\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+    let response = send(addr, b"GET /x HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.ends_with("\r\n\r\nno /x"));
+}
+
+#[test]
+fn test_thread_state() {
+    let inits = Arc::new(AtomicUsize::new(0));
+    let counted = inits.clone();
+
+    let mut server = Server::new_with_responder(|_request| {
+        let served = with_thread_state(|served: &mut usize| {
+            *served += 1;
+            *served
+        });
+        format!("{:?}", served)
+    });
+    server.set_concurrency_model(ConcurrencyModel::Pool { size: 1 });
+    server.set_thread_state(move || {
+        counted.fetch_add(1, Ordering::SeqCst);
+        0_usize
+    });
+    let addr = spawn_server(server);
+
+    for expected in 1..4 {
+        let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(response.ends_with(&format!("\r\n\r\nSome({})", expected)));
+    }
+    assert_eq!(1, inits.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_body_progress() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let seen = calls.clone();
+
+    let mut server = Server::new_with_responder(|request| format!("{}", request.body().len()));
+    server.on_body_progress(move |head, received, total| {
+        let call = (
+            head.method().to_string(),
+            head.path().to_string(),
+            head.peer(),
+            received,
+            total,
+        );
+        seen.lock().unwrap().push(call);
+    });
+    server.set_body_progress_granularity(1000, Duration::from_secs(60));
+    let addr = spawn_server(server);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"PUT /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 3000\r\n\r\n")
+        .unwrap();
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(&[b'x'; 1000]).unwrap();
+    }
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("\r\n\r\n3000"));
+
+    let calls = calls.lock().unwrap();
+    assert!(calls.len() >= 3, "{:?}", calls);
+    assert!(calls.windows(2).all(|pair| pair[0].3 < pair[1].3));
+    assert_eq!(3000, calls.last().unwrap().3);
+    for (method, path, peer, _, total) in calls.iter() {
+        assert_eq!("PUT", method);
+        assert_eq!("/upload", path);
+        assert_eq!(Some(stream.local_addr().unwrap()), *peer);
+        assert_eq!(Some(3000), *total);
+    }
+}
+
+#[test]
+fn test_serve_n_returns_after_the_last_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let server = Server::new_with_responder(|request| request.uri().path().to_string());
+        server.serve_n(&listener, 2).unwrap();
+        server.accept_one(&listener).unwrap();
+    });
+
+    for path in &["/one", "/two", "/three"] {
+        let response = send(
+            addr,
+            format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path).as_bytes(),
+        );
+        assert!(response.ends_with(&format!("\r\n\r\n{}", path)));
+    }
+
+    server.join().unwrap();
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn test_accept_one_gives_up_on_an_idle_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let server = Server::with_timeout(Duration::from_millis(100), |_request, mut response| {
+            Ok(response.body(Vec::new())?)
+        });
+        server.accept_one(&listener).unwrap();
+    });
+
+    let _idle = TcpStream::connect(addr).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_embedded_assets() {
+    let dir = static_dir(
+        "embedded-assets",
+        &[("app.js", "from disk"), ("style.css", "body {}")],
+    );
+    let mut server = static_server(dir);
+    server.set_content_etags(true);
+    server.set_embedded_assets([
+        (String::from("/"), (&b"<h1>embedded</h1>"[..], "text/html")),
+        (
+            String::from("/app.js"),
+            (&b"from memory"[..], "text/javascript"),
+        ),
+    ]);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\ncontent-type: text/html\r\n"));
+    assert!(response.ends_with("\r\n\r\n<h1>embedded</h1>"));
+
+    // Embedded assets come before the static directory.
+    let response = send(addr, b"GET /app.js HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nfrom memory"));
+    let etag = response
+        .lines()
+        .find(|line| line.starts_with("etag: "))
+        .map(|line| line["etag: ".len()..].to_string())
+        .unwrap();
+
+    let request = format!(
+        "GET /app.js HTTP/1.1\r\nHost: x\r\nIf-None-Match: {}\r\n\r\n",
+        etag
+    );
+    let response = send(addr, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+
+    let response = send(
+        addr,
+        b"GET /app.js HTTP/1.1\r\nHost: x\r\nRange: bytes=5-\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.contains("\r\ncontent-range: bytes 5-10/11\r\n"));
+    assert!(response.ends_with("\r\n\r\nmemory"));
+
+    let response = send(addr, b"GET /style.css HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nbody {}"));
+}
+
+#[test]
+fn test_default_content_type() {
+    let dir = static_dir("default-content-type", &[("notes.txt", "notes")]);
+    let mut server = Server::new(|request, mut response| match request.uri().path() {
+        "/json" => Ok(response
+            .header("content-type", "application/json")
+            .body(b"{}".to_vec())?),
+        "/empty" => Ok(response.status(StatusCode::NO_CONTENT).body(Vec::new())?),
+        _ => Ok(response.body(b"<p>hi</p>".to_vec())?),
+    });
+    server.set_static_directory(dir);
+    server.set_default_content_type("text/html; charset=utf-8");
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\ncontent-type: text/html; charset=utf-8\r\n"));
+
+    let response = send(addr, b"GET /json HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\ncontent-type: application/json\r\n"));
+    assert!(!response.contains("text/html"));
+
+    let response = send(addr, b"GET /empty HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+    assert!(!response.contains("content-type"));
+
+    let response = send(addr, b"GET /notes.txt HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\ncontent-type: text/plain; charset=utf-8\r\n"));
+}
+
+#[test]
+fn test_early_errors_wait_for_the_body() {
+    let mut server = Server::new(|_request, mut response| Ok(response.body(Vec::new())?));
+    server.set_early_response_drain(16 * 1024 * 1024, Duration::from_secs(5));
+    let addr = spawn_server(server);
+
+    // The body is more than the socket buffers hold, so without draining, the
+    // unread part would reset the connection before the client got to read
+    // the response.
+    let body = vec![b'x'; 8 * 1024 * 1024];
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let head = format!(
+        "PUT /upload HTTP/1.1\r\nHost: x\r\nExpect: 200-ok\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(&body).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+
+    // A client that doesn't finish isn't waited for past the limit.
+    let mut server = Server::new(|_request, mut response| Ok(response.body(Vec::new())?));
+    server.set_early_response_drain(1024 * 1024, Duration::from_millis(100));
+    let addr = spawn_server(server);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(head.as_bytes()).unwrap();
+    let started = Instant::now();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_static_files_only() {
+    let dir = static_dir(
+        "static-only",
+        &[
+            ("docs/index.html", "<p>docs</p>"),
+            ("404.html", "<p>lost</p>"),
+        ],
+    );
+    let server = Server::static_files(dir);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /docs/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\ncontent-type: text/html; charset=utf-8\r\n"));
+    assert!(response.ends_with("\r\n\r\n<p>docs</p>"));
+
+    let response = send(addr, b"GET /missing HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.ends_with("\r\n\r\n<p>lost</p>"));
+}
+
+#[test]
+fn test_clean_urls() {
+    let dir = static_dir(
+        "clean-urls",
+        &[
+            ("about.html", "<p>about page</p>"),
+            ("about/index.html", "<p>about directory</p>"),
+            ("docs/index.html", "<p>docs</p>"),
+            ("notes", "plain notes"),
+            ("notes.html", "<p>notes</p>"),
+            ("app.html", "<p>app</p>"),
+        ],
+    );
+    let mut server = static_server(dir);
+    server.set_clean_urls(true);
+    server.set_fallback_file("app.html");
+    let addr = spawn_server(server);
+
+    let get = |path: &str| {
+        send(
+            addr,
+            format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path).as_bytes(),
+        )
+    };
+
+    // An exact match comes first, then the page with `.html` added, then a
+    // directory's index, and only then the fallback.
+    assert!(get("/notes").ends_with("\r\n\r\nplain notes"));
+    assert!(get("/about").ends_with("\r\n\r\n<p>about page</p>"));
+    assert!(get("/about/").ends_with("\r\n\r\n<p>about directory</p>"));
+    let response = get("/docs");
+    assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert!(response.contains("\r\nlocation: /docs/\r\n"));
+    assert!(get("/missing").ends_with("\r\n\r\n<p>app</p>"));
+
+    // Paths with an extension are only ever matched exactly.
+    assert!(get("/about.txt").starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn test_metrics_endpoint() {
+    let mut server = Server::new(|request, mut response| {
+        Ok(response.body(format!("handler saw {}", request.uri().path()).into_bytes())?)
+    });
+    server.enable_metrics_endpoint("/metrics");
+    server.set_metrics_loopback_only(true);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let response = send(addr, b"GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("content-type: text/plain; version=0.0.4; charset=utf-8\r\n"));
+    assert!(response.contains("# TYPE simple_server_responses_total counter\n"));
+    assert!(response.contains("\nsimple_server_responses_total{class=\"2xx\"} 1\n"));
+    assert!(response.contains("\nsimple_server_active_connections 1\n"));
+    assert!(!response.contains("handler saw"));
+
+    let response = send(addr, b"POST /metrics HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler saw /metrics"));
+}
+
+#[test]
+fn test_options_asterisk() {
+    let dir = static_dir("options-asterisk", &[("index.html", "<p>index</p>")]);
+    let mut server = static_server(dir.clone());
+    server.set_allowed_methods(&[Method::GET, Method::OPTIONS]);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"OPTIONS * HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nallow: GET, OPTIONS\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    let response = send(addr, b"GET * HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    let mut server = Server::new(|request, mut response| {
+        Ok(response.body(request.uri().to_string().into_bytes())?)
+    });
+    server.set_static_directory(dir);
+    server.set_answer_options_asterisk(false);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"OPTIONS * HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\n*"));
+}
+
+#[test]
+fn test_connect_is_rejected() {
+    let dir = static_dir("connect", &[("index.html", "<p>index</p>")]);
+    let server = Arc::new(static_server(dir));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listening = server.clone();
+    thread::spawn(move || listening.listen_on_socket(listener));
+
+    for target in &["example.com:443", "[::1]:443", "x:1"] {
+        let request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target);
+        let response = send(addr, request.as_bytes());
+        assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+    }
+    assert_eq!(3, server.metrics().rejected_connects());
+}
+
+#[test]
+fn test_static_paths_are_normalized() {
+    let dir = static_dir(
+        "normalize",
+        &[
+            ("index.html", "root index"),
+            ("style.css", "style"),
+            ("foo/bar.png", "bar"),
+            ("a file.txt", "spaced"),
+        ],
+    );
+    let addr = spawn_server(static_server(dir));
+
+    for &(path, body) in &[
+        ("/", "root index"),
+        ("//", "root index"),
+        ("//index.html", "root index"),
+        ("/./style.css", "style"),
+        ("/style.css?v=2", "style"),
+        ("/foo//bar.png", "bar"),
+        ("/foo/./bar.png", "bar"),
+        ("/foo/../style.css", "style"),
+        ("/foo/bar/../../index.html", "root index"),
+        ("/a%20file.txt", "spaced"),
+    ] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+        let response = send(addr, request.as_bytes());
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", path);
+        assert!(response.ends_with(body), "{}", path);
+    }
+
+    for path in &[
+        "/../index.html",
+        "/foo/../../index.html",
+        "/%2e%2e/index.html",
+    ] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+        let response = send(addr, request.as_bytes());
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            path
+        );
+        assert!(!response.contains("root index"), "{}", path);
+    }
+}
+
+#[test]
+fn test_directories_are_redirected_to_a_trailing_slash() {
+    let dir = static_dir(
+        "trailing-slash",
+        &[
+            ("docs/index.html", "docs index"),
+            ("docs/page.html", "page"),
+        ],
+    );
+    let addr = spawn_server(static_server(dir.clone()));
+
+    let response = send(addr, b"GET /docs HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert!(response.contains("\r\nlocation: /docs/\r\n"));
+
+    let response = send(addr, b"GET /docs?lang=en HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\nlocation: /docs/?lang=en\r\n"));
+
+    // The redirect stays on this server, however the path was written.
+    let response = send(addr, b"GET //docs HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert!(response.contains("\r\nlocation: /docs/\r\n"));
+
+    let response = send(addr, b"GET /./docs/../docs?x HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\nlocation: /docs/?x\r\n"));
+
+    let response = send(addr, b"GET /docs/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("docs index"));
+
+    let response = send(addr, b"GET /docs/page.html HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("page"));
+
+    let mut server = static_server(dir);
+    server.dont_redirect_directories();
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /docs HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("docs index"));
+}
+
+#[test]
+fn test_header_values_that_are_not_utf8() {
+    let addr = spawn_server(Server::new(|request, mut response| {
+        let user_agent = request.headers()["user-agent"].as_bytes().to_vec();
+        Ok(response.body(user_agent)?)
+    }));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nUser-Agent: Caf\xe9/\xff\r\n\r\n")
+        .unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with(b"\r\n\r\nCaf\xe9/\xff"));
+}
+
+#[test]
+fn test_static_file_single_range() {
+    let dir = static_dir("single-range", &[("digits.txt", "0123456789")]);
+    let addr = spawn_server(static_server(dir));
+
+    let response = send(
+        addr,
+        b"GET /digits.txt HTTP/1.1\r\nHost: x\r\nRange: bytes=2-5\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.contains("content-range: bytes 2-5/10\r\n"));
+    assert!(response.ends_with("\r\n\r\n2345"));
+}
+
+#[test]
+fn test_static_file_multiple_ranges() {
+    let dir = static_dir("multi-range", &[("digits.txt", "0123456789")]);
+    let addr = spawn_server(static_server(dir));
+
+    let response = send(
+        addr,
+        b"GET /digits.txt HTTP/1.1\r\nHost: x\r\nRange: bytes=0-1,7-\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+
+    let boundary = response
+        .split("content-type: multipart/byteranges; boundary=")
+        .nth(1)
+        .unwrap()
+        .split("\r\n")
+        .next()
+        .unwrap();
+    let body = format!(
+        "--{b}\r\n\
+         content-type: text/plain; charset=utf-8\r\n\
+         content-range: bytes 0-1/10\r\n\r\n\
+         01\r\n\
+         --{b}\r\n\
+         content-type: text/plain; charset=utf-8\r\n\
+         content-range: bytes 7-9/10\r\n\r\n\
+         789\r\n\
+         --{b}--\r\n",
+        b = boundary
+    );
+    assert!(response.contains(&format!("content-length: {}\r\n", body.len())));
+    assert!(response.ends_with(&format!("\r\n\r\n{}", body)));
+}
+
+#[test]
+fn test_static_file_unsatisfiable_range() {
+    let dir = static_dir("bad-range", &[("digits.txt", "0123456789")]);
+    let addr = spawn_server(static_server(dir));
+
+    let response = send(
+        addr,
+        b"GET /digits.txt HTTP/1.1\r\nHost: x\r\nRange: bytes=20-\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert!(response.contains("content-range: bytes */10\r\n"));
+    assert!(response.contains("content-type: text/html\r\n"));
+    assert!(!response.contains("text/plain"));
+}
+
+#[test]
+fn test_spa_fallback() {
+    let dir = static_dir(
+        "spa",
+        &[("index.html", "<h1>app</h1>"), ("app.js", "run()")],
+    );
+    let mut server = static_server(dir);
+    server.set_fallback_file("index.html");
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /settings/profile HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("<h1>app</h1>"));
+
+    let response = send(addr, b"GET /app.js HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("run()"));
+
+    // missing assets still go on to the handler
+    let response = send(addr, b"GET /missing.css HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.ends_with("handler"));
+
+    let response = send(addr, b"POST /settings HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler"));
+}
+
+#[test]
+fn test_custom_error_pages() {
+    let dir = static_dir("error-pages", &[("500.html", "<p>custom oops</p>")]);
+    let mut server = Server::new(|_request, mut response| {
+        response.header("Foo", "Bar\r\n");
+        Ok(response.body("".as_bytes().to_vec())?)
+    });
+    server.set_static_directory(dir);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(response.contains("content-type: text/html\r\n"));
+    assert!(response.ends_with("<p>custom oops</p>"));
+
+    // there's no 404.html, so the built-in page is used
+    let response = send(addr, b"GET /../secret HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.ends_with("<h1>404</h1><p>Not found!<p>"));
+}
+
+#[test]
+fn test_index_files() {
+    let dir = static_dir(
+        "index-files",
+        &[
+            ("docs/index.html", "index.html"),
+            ("docs/index.htm", "index.htm"),
+            ("other/README.html", "readme"),
+        ],
+    );
+
+    let addr = spawn_server(static_server(dir.clone()));
+    let response = send(addr, b"GET /docs/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nindex.html"));
+    let response = send(addr, b"GET /other/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler"));
+
+    let mut server = static_server(dir.clone());
+    server.set_index_files(vec!["README.html", "index.htm", "index.html"]);
+    let addr = spawn_server(server);
+    let response = send(addr, b"GET /docs/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nindex.htm"));
+    let response = send(addr, b"GET /other/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nreadme"));
+
+    let mut server = static_server(dir);
+    server.set_index_files(Vec::<String>::new());
+    let addr = spawn_server(server);
+    let response = send(addr, b"GET /docs/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler"));
+}
+
+#[test]
+fn test_static_content_types() {
+    let dir = static_dir(
+        "content-types",
+        &[
+            ("page.html", "<p>hi</p>"),
+            ("image.png", "not really"),
+            ("notes.txt", "caf\u{e9}"),
+        ],
+    );
+    let mut server = static_server(dir);
+    server.set_mime_type("txt", "text/plain; charset=iso-8859-1");
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /page.html HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("content-type: text/html; charset=utf-8\r\n"));
+    let response = send(addr, b"GET /image.png HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("content-type: image/png\r\n"));
+    let response = send(addr, b"GET /notes.txt HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("content-type: text/plain; charset=iso-8859-1\r\n"));
+}
+
+#[test]
+fn test_static_charset_can_be_disabled() {
+    let dir = static_dir("no-charset", &[("page.html", "<p>hi</p>")]);
+    let mut server = static_server(dir);
+    server.dont_add_static_charset();
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /page.html HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("content-type: text/html\r\n"));
+}
+
+#[test]
+fn test_redirect_server() {
+    let addr = spawn_server(Server::redirect("https://example.com"));
+
+    let response = send(
+        addr,
+        b"GET /docs/a%20b?x=1&y=%2F HTTP/1.1\r\nHost: x\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert!(response.contains("location: https://example.com/docs/a%20b?x=1&y=%2F\r\n"));
+}
+
+#[test]
+fn test_disallowed_methods() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("handler".as_bytes().to_vec())?));
+    server.set_allowed_methods(&[Method::GET, Method::HEAD]);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"BREW /pot HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+    assert!(response.contains("allow: GET, HEAD\r\n"));
+    assert!(!response.ends_with("handler"));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler"));
+}
+
+#[test]
+fn test_disallowed_methods_with_405() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("handler".as_bytes().to_vec())?));
+    server.set_allowed_methods(&[Method::GET]);
+    server.set_disallowed_method_status(StatusCode::METHOD_NOT_ALLOWED);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"OPTIONS / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert!(response.contains("allow: GET\r\n"));
+}
+
+#[test]
+fn test_all_methods_are_allowed_by_default() {
+    let addr = spawn_server(Server::new(|request, mut response| {
+        Ok(response.body(request.method().as_str().as_bytes().to_vec())?)
+    }));
+
+    let response = send(addr, b"BREW /pot HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nBREW"));
+}
+
+fn early_hints_server() -> Server {
+    Server::new(|request, mut response| {
+        let mut hints = http::HeaderMap::new();
+        hints.insert(
+            http::header::LINK,
+            "</style.css>; rel=preload".parse().unwrap(),
+        );
+        request
+            .extensions()
+            .get::<Interim>()
+            .unwrap()
+            .send_interim(StatusCode::from_u16(103).unwrap(), hints)?;
+
+        Ok(response.body("final".as_bytes().to_vec())?)
+    })
+}
+
+#[test]
+fn test_early_hints() {
+    let addr = spawn_server(early_hints_server());
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with(
+        "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\nHTTP/1.1 200 OK\r\n"
+    ));
+    assert!(response.ends_with("final"));
+}
+
+#[test]
+fn test_early_hints_are_skipped_for_http10() {
+    let addr = spawn_server(early_hints_server());
+
+    let response = send(addr, b"GET / HTTP/1.0\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_multiple_listeners() {
+    let first = TcpListener::bind("127.0.0.1:0").unwrap();
+    let second = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addrs = [first.local_addr().unwrap(), second.local_addr().unwrap()];
+
+    let server = Server::new(|request, mut response| {
+        Ok(response.body(request.uri().path().as_bytes().to_vec())?)
+    });
+    thread::spawn(move || server.listen_on_sockets(vec![first, second]));
+
+    for &addr in &addrs {
+        let response = send(addr, b"GET /both HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\n/both"));
+    }
+}
+
+#[test]
+fn test_nonblocking_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server =
+        Server::new(|_request, mut response| Ok(response.body("polled".as_bytes().to_vec())?));
+    thread::spawn(move || server.listen_on_socket(listener));
+
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\npolled"));
+}
+
+#[test]
+fn test_layered_static_directories() {
+    let overrides = static_dir(
+        "layered-overrides",
+        &[
+            ("robots.txt", "override"),
+            ("docs/index.html", "docs override"),
+        ],
+    );
+    let main = static_dir(
+        "layered-main",
+        &[
+            ("robots.txt", "main"),
+            ("logo.svg", "<svg/>"),
+            ("docs/index.html", "docs main"),
+        ],
+    );
+    let mut server = static_server(overrides);
+    server.add_static_directory(main.clone());
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /robots.txt HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\noverride"));
+    let response = send(addr, b"GET /logo.svg HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\n<svg/>"));
+    let response = send(addr, b"GET /docs/ HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\ndocs override"));
+
+    // setting the static directory replaces the whole list
+    let mut server = static_server(main);
+    server.add_static_directory(static_dir("layered-extra", &[("extra.txt", "extra")]));
+    server.set_static_directory(static_dir("layered-only", &[]));
+    let addr = spawn_server(server);
+    let response = send(addr, b"GET /robots.txt HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler"));
+    let response = send(addr, b"GET /extra.txt HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("handler"));
+}
+
+#[test]
+fn test_download_extensions() {
+    let dir = static_dir(
+        "downloads",
+        &[("monthly-report.CSV", "a,b\n"), ("page.html", "<p>hi</p>")],
+    );
+    let mut server = static_server(dir);
+    server.set_download_extensions(vec!["csv"]);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /monthly-report.CSV HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response
+        .contains("\r\ncontent-disposition: attachment; filename=\"monthly-report.CSV\"\r\n"));
+
+    let response = send(addr, b"GET /page.html HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!response.contains("content-disposition"));
+}
+
+#[test]
+fn test_content_etags() {
+    let dir = static_dir("etags", &[("app.js", "console.log(1)")]);
+    let mut server = static_server(dir);
+    server.set_content_etags(true);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /app.js HTTP/1.1\r\nHost: x\r\n\r\n");
+    let etag = response
+        .lines()
+        .find_map(|line| line.strip_prefix("etag: "))
+        .expect("no etag")
+        .to_string();
+    assert!(etag.starts_with('"'));
+
+    let request = format!(
+        "GET /app.js HTTP/1.1\r\nHost: x\r\nIf-None-Match: {}\r\n\r\n",
+        etag
+    );
+    let response = send(addr, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert!(response.contains(&format!("\r\netag: {}\r\n", etag)));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    let response = send(
+        addr,
+        b"GET /app.js HTTP/1.1\r\nHost: x\r\nIf-None-Match: \"stale\"\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("console.log(1)"));
+}
+
+#[test]
+fn test_dev_mode() {
+    let dir = static_dir("dev-mode", &[("app.js", "console.log(1)")]);
+    let mut server = static_server(dir.clone());
+    server.set_content_etags(true);
+    server.set_dev_mode(true);
+    let addr = spawn_server(server);
+
+    let response = send(addr, b"GET /app.js HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("\r\ncache-control: no-store\r\n"));
+    assert!(!response.contains("etag"));
+
+    fs::write(dir.join("app.js"), "console.log(2)").unwrap();
+    let response = send(addr, b"GET /app.js HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("console.log(2)"));
+}
+
+#[test]
+fn test_idle_connections_are_reaped() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("awake".as_bytes().to_vec())?));
+    server.set_idle_timeout(Duration::from_millis(100));
+    let server = Arc::new(server);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listening = server.clone();
+    thread::spawn(move || listening.listen_on_socket(listener));
+
+    // A client that never sends anything is disconnected...
+    let mut idle = TcpStream::connect(addr).unwrap();
+    idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = Vec::new();
+    idle.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+    assert_eq!(1, server.metrics().reaped_connections());
+
+    // ...leaving the server free for everyone else.
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nawake"));
+}
+
+#[test]
+fn test_quiet_kept_alive_connections_are_closed() {
+    let mut server =
+        Server::new(|_request, mut response| Ok(response.body("awake".as_bytes().to_vec())?));
+    server.set_concurrency_model(ConcurrencyModel::Pool { size: 1 });
+    let addr = spawn_server(server);
+
+    // A client that keeps its connection open after its first request...
+    let mut kept = TcpStream::connect(addr).unwrap();
+    kept.set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    kept.write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"awake") {
+        let mut buf = [0; 1024];
+        let n = kept.read(&mut buf).unwrap();
+        assert!(n > 0);
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    // ...doesn't keep the only worker from everyone else for long.
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nawake"));
+
+    let mut rest = Vec::new();
+    kept.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_debug_errors() {
+    let failing = |_request, _response| -> ResponseResult {
+        Err(io::Error::other("<db> unavailable").into())
+    };
+
+    let addr = spawn_server(Server::new(failing));
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(!response.contains("unavailable"));
+
+    let mut server = Server::new(failing);
+    server.set_debug_errors(true);
+    let addr = spawn_server(server);
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(response.contains("&lt;db&gt; unavailable"));
+
+    // Custom error pages still win.
+    let dir = static_dir("debug-errors", &[("500.html", "<p>Sorry!</p>")]);
+    let mut server = Server::new(failing);
+    server.set_static_directory(dir);
+    server.set_debug_errors(true);
+    let addr = spawn_server(server);
+    let response = send(addr, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\n<p>Sorry!</p>"));
+}